@@ -0,0 +1,353 @@
+//! The `accounts` module owns account storage for `TransactionProcessor`:
+//! the live `Pubkey -> Account` map, the checkpoint/rollback delta stack
+//! layered over it, the per-account lock set `process_transactions` uses
+//! to let disjoint transactions execute concurrently, and account-change
+//! subscriptions. Pulling this out of `TransactionProcessor` keeps the
+//! account layer independently testable and reusable by the checkpoint
+//! and parallel-execution paths without dragging in signature/last-id
+//! bookkeeping, which stays behind in `TransactionProcessor`.
+
+use bincode::serialize;
+use hash::{hash, Hash};
+use transaction::Transaction;
+use transaction_processor::{Result, Sink, TransactionProcessorError};
+use vote_program::VoteState;
+use xpz_program_interface::account::Account;
+use xpz_program_interface::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::RwLock;
+
+#[derive(Default)]
+pub struct ErrorCounters {
+    pub account_not_found_validator: usize,
+    pub account_not_found_leader: usize,
+    pub account_not_found_vote: usize,
+    pub insufficient_funds: usize,
+    pub duplicate_signature: usize,
+    pub last_id_not_found: usize,
+    pub reserve_signature: usize,
+}
+
+/// A narrowing condition for `Accounts::accounts_matching`: an account must
+/// satisfy every filter in the list to be included in the result.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ProgramAccountsFilter {
+    /// Keep only accounts whose userdata is exactly `size` bytes long.
+    DataSize(usize),
+    /// Keep only accounts whose userdata, starting at `offset`, matches
+    /// `bytes` byte-for-byte.
+    Memcmp { offset: usize, bytes: Vec<u8> },
+}
+
+impl ProgramAccountsFilter {
+    fn matches(&self, account: &Account) -> bool {
+        match *self {
+            ProgramAccountsFilter::DataSize(size) => account.userdata.len() == size,
+            ProgramAccountsFilter::Memcmp { offset, ref bytes } => match offset.checked_add(bytes.len()) {
+                Some(end) if end <= account.userdata.len() => account.userdata[offset..end] == bytes[..],
+                _ => false,
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Accounts {
+    accounts: RwLock<HashMap<Pubkey, Account>>,
+
+    /// Copy-on-write layers stacked on top of `accounts`, one per open
+    /// `checkpoint()`. A write lands in the top delta (`None` meaning the
+    /// account was deleted in that layer); a read walks the stack
+    /// top-down and falls through to `accounts` once it's empty. `squash`
+    /// merges the top delta into the next one down (or into `accounts`
+    /// once it's the last layer); `rollback` just drops it.
+    account_deltas: RwLock<Vec<HashMap<Pubkey, Option<Account>>>>,
+
+    /// Accounts currently claimed by an in-flight `process_transactions`
+    /// call. `lock_accounts`/`unlock_accounts` are the only things that
+    /// touch this, bracketing the load/execute/store of whichever
+    /// transactions they let through.
+    locked_accounts: RwLock<HashSet<Pubkey>>,
+
+    /// Subscribers watching an account, notified from `store_accounts`
+    /// every time that account is written. Unlike signature subscriptions
+    /// these aren't one-shot; a caller removes them explicitly.
+    subscriptions: RwLock<HashMap<Pubkey, Vec<Box<Sink<Account>>>>>,
+}
+
+impl Accounts {
+    /// Flatten `account_deltas` over `accounts` into a single map, as of
+    /// the current top of the checkpoint stack.
+    fn snapshot(&self) -> HashMap<Pubkey, Account> {
+        let mut snapshot = self.accounts.read().unwrap().clone();
+        for delta in self.account_deltas.read().unwrap().iter() {
+            for (pubkey, account) in delta {
+                match account {
+                    Some(account) => {
+                        snapshot.insert(*pubkey, account.clone());
+                    }
+                    None => {
+                        snapshot.remove(pubkey);
+                    }
+                }
+            }
+        }
+        snapshot
+    }
+
+    pub fn get_account(&self, pubkey: &Pubkey) -> Option<Account> {
+        for delta in self.account_deltas.read().unwrap().iter().rev() {
+            if let Some(account) = delta.get(pubkey) {
+                return account.clone();
+            }
+        }
+        self.accounts.read().unwrap().get(pubkey).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.read().unwrap().len()
+    }
+
+    /// Write `account` (or, if `None`, a deletion) into the top checkpoint
+    /// layer, or straight into `accounts` if there's no open checkpoint.
+    pub fn set_account(&self, pubkey: Pubkey, account: Option<Account>) {
+        let mut deltas = self.account_deltas.write().unwrap();
+        if let Some(top) = deltas.last_mut() {
+            top.insert(pubkey, account);
+        } else {
+            drop(deltas);
+            let mut accounts = self.accounts.write().unwrap();
+            match account {
+                Some(account) => {
+                    accounts.insert(pubkey, account);
+                }
+                None => {
+                    accounts.remove(&pubkey);
+                }
+            }
+        }
+    }
+
+    /// Push a new copy-on-write layer on top of `accounts`.
+    pub fn checkpoint(&self) {
+        self.account_deltas.write().unwrap().push(HashMap::new());
+    }
+
+    /// Discard every account change made since the matching `checkpoint`.
+    /// Panics if there's no open checkpoint.
+    pub fn rollback(&self) {
+        self.account_deltas
+            .write()
+            .unwrap()
+            .pop()
+            .expect("rollback() with no open checkpoint");
+    }
+
+    /// Merge the top checkpoint layer down into the one below it, or into
+    /// `accounts` once it's the last layer, keeping the changes instead of
+    /// discarding them. Panics if there's no open checkpoint.
+    pub fn squash(&self) {
+        let top = self
+            .account_deltas
+            .write()
+            .unwrap()
+            .pop()
+            .expect("squash() with no open checkpoint");
+
+        let mut deltas = self.account_deltas.write().unwrap();
+        if let Some(parent) = deltas.last_mut() {
+            parent.extend(top);
+        } else {
+            drop(deltas);
+            let mut accounts = self.accounts.write().unwrap();
+            for (pubkey, account) in top {
+                match account {
+                    Some(account) => {
+                        accounts.insert(pubkey, account);
+                    }
+                    None => {
+                        accounts.remove(&pubkey);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fold checkpoint layers older than the most recent `depth` into
+    /// `accounts`, so a validator doesn't have to keep every speculative
+    /// layer back to the root once a fork that deep is confirmed final.
+    pub fn purge(&self, depth: usize) {
+        loop {
+            let oldest = {
+                let mut deltas = self.account_deltas.write().unwrap();
+                if deltas.len() <= depth {
+                    break;
+                }
+                deltas.remove(0)
+            };
+            let mut accounts = self.accounts.write().unwrap();
+            for (pubkey, account) in oldest {
+                match account {
+                    Some(account) => {
+                        accounts.insert(pubkey, account);
+                    }
+                    None => {
+                        accounts.remove(&pubkey);
+                    }
+                }
+            }
+        }
+    }
+
+    fn load_account(
+        &self,
+        tx: &Transaction,
+        accounts: &HashMap<Pubkey, Account>,
+        is_leader: bool,
+        error_counters: &mut ErrorCounters,
+    ) -> Result<Vec<Account>> {
+        if accounts.get(&tx.keys[0]).is_none() {
+            if !is_leader {
+                error_counters.account_not_found_validator += 1;
+            } else {
+                error_counters.account_not_found_leader += 1;
+            }
+            if tx.program_ids.iter().any(VoteState::check_id) {
+                error_counters.account_not_found_vote += 1;
+            }
+            Err(TransactionProcessorError::AccountNotFound)
+        } else if accounts.get(&tx.keys[0]).unwrap().tokens < tx.fee {
+            error_counters.insufficient_funds += 1;
+            Err(TransactionProcessorError::InsufficientFundsForFee)
+        } else {
+            let mut called_accounts: Vec<Account> = tx
+                .keys
+                .iter()
+                .map(|key| accounts.get(key).cloned().unwrap_or_default())
+                .collect();
+            called_accounts[0].tokens -= tx.fee;
+            Ok(called_accounts)
+        }
+    }
+
+    pub fn load_accounts(
+        &self,
+        txs: &[Transaction],
+        is_leader: bool,
+        error_counters: &mut ErrorCounters,
+    ) -> Vec<Result<Vec<Account>>> {
+        let accounts = self.snapshot();
+        txs.iter()
+            .map(|tx| self.load_account(tx, &accounts, is_leader, error_counters))
+            .collect()
+    }
+
+    pub fn store_accounts(&self, txs: &[Transaction], res: &[Result<()>], loaded: &[Result<Vec<Account>>]) {
+        for (i, racc) in loaded.iter().enumerate() {
+            if res[i].is_err() || racc.is_err() {
+                continue;
+            }
+
+            let tx = &txs[i];
+            let acc = racc.as_ref().unwrap();
+            for (key, account) in tx.keys.iter().zip(acc.iter()) {
+                if account.tokens == 0 {
+                    self.set_account(*key, None);
+                } else {
+                    self.set_account(*key, Some(account.clone()));
+                }
+                self.notify_subscribers(key, account);
+            }
+        }
+    }
+
+    /// Claim every account each `tx` touches in one pass over the shared
+    /// locked-keys set. A tx that collides with a key already claimed —
+    /// by an earlier tx in this same call, or one still outstanding from a
+    /// call running concurrently on another thread — is rejected outright
+    /// as `AccountInUse` rather than retried; its keys are left untouched.
+    pub fn lock_accounts(&self, txs: &[Transaction]) -> Vec<Result<()>> {
+        let mut locked_accounts = self.locked_accounts.write().unwrap();
+        txs.iter()
+            .map(|tx| {
+                if tx.keys.iter().any(|key| locked_accounts.contains(key)) {
+                    Err(TransactionProcessorError::AccountInUse)
+                } else {
+                    locked_accounts.extend(tx.keys.iter().cloned());
+                    Ok(())
+                }
+            }).collect()
+    }
+
+    /// Release the keys of every tx that `lock_accounts` let through,
+    /// regardless of whether it went on to execute successfully — once
+    /// locked, a tx's keys must always come back out of the set.
+    pub fn unlock_accounts(&self, txs: &[Transaction], lock_results: &[Result<()>]) {
+        let mut locked_accounts = self.locked_accounts.write().unwrap();
+        for (tx, lock_result) in txs.iter().zip(lock_results.iter()) {
+            if lock_result.is_ok() {
+                for key in &tx.keys {
+                    locked_accounts.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Register `sink` to be notified with `pubkey`'s latest `Account`
+    /// every time `store_accounts` writes to it, until explicitly removed.
+    pub fn add_subscription(&self, pubkey: Pubkey, sink: Box<Sink<Account>>) {
+        self.subscriptions
+            .write()
+            .unwrap()
+            .entry(pubkey)
+            .or_insert_with(Vec::new)
+            .push(sink);
+    }
+
+    pub fn remove_subscription(&self, pubkey: &Pubkey) {
+        self.subscriptions.write().unwrap().remove(pubkey);
+    }
+
+    fn notify_subscribers(&self, pubkey: &Pubkey, account: &Account) {
+        if let Some(sinks) = self.subscriptions.read().unwrap().get(pubkey) {
+            for sink in sinks {
+                sink.notify(account.clone());
+            }
+        }
+    }
+
+    /// Every account currently owned by `program_id`, as of the merged view
+    /// of the checkpoint stack.
+    pub fn accounts_owned_by(&self, program_id: &Pubkey) -> Vec<Account> {
+        self.snapshot()
+            .into_iter()
+            .filter(|(_, account)| account.program_id == *program_id)
+            .map(|(_, account)| account)
+            .collect()
+    }
+
+    /// Every `(Pubkey, Account)` owned by `program_id`, as of the merged
+    /// view of the checkpoint stack, narrowed to accounts matching every
+    /// filter in `filters`. Backs `getProgramAccounts`, so a thin client can
+    /// enumerate a program's state in one round trip instead of guessing
+    /// keys.
+    pub fn accounts_matching(
+        &self,
+        program_id: &Pubkey,
+        filters: &[ProgramAccountsFilter],
+    ) -> Vec<(Pubkey, Account)> {
+        self.snapshot()
+            .into_iter()
+            .filter(|&(_, ref account)| account.program_id == *program_id)
+            .filter(|&(_, ref account)| filters.iter().all(|filter| filter.matches(account)))
+            .collect()
+    }
+
+    pub fn hash_internal_state(&self) -> Hash {
+        let mut ordered_accounts: BTreeMap<Pubkey, Account> = BTreeMap::new();
+        for (pubkey, account) in self.snapshot() {
+            ordered_accounts.insert(pubkey, account);
+        }
+        hash(&serialize(&ordered_accounts).unwrap())
+    }
+}
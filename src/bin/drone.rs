@@ -2,28 +2,29 @@ extern crate bincode;
 extern crate bytes;
 #[macro_use]
 extern crate clap;
+extern crate futures;
 extern crate log;
 extern crate serde_json;
 extern crate hypercube;
 extern crate tokio;
-extern crate tokio_codec;
+extern crate tokio_util;
 
 use bincode::{deserialize, serialize};
 use bytes::Bytes;
 use clap::{App, Arg};
+use futures::{SinkExt, StreamExt};
 use hypercube::faucet::{Drone, DroneRequest, DRONE_PORT};
 use hypercube::logger;
 use hypercube::metrics::set_panic_hook;
 use hypercube::signature::read_keypair;
 use std::error;
-use std::io;
 use std::net::{Ipv4Addr, SocketAddr};
 use std::process::exit;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use tokio::net::TcpListener;
-use tokio::prelude::*;
-use tokio_codec::{BytesCodec, Decoder};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tokio::time;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
 macro_rules! socketaddr {
     ($ip:expr, $port:expr) => {
@@ -35,7 +36,84 @@ macro_rules! socketaddr {
     }};
 }
 
-fn main() -> Result<(), Box<error::Error>> {
+async fn handle_connection(socket: TcpStream, faucet: Arc<Mutex<Drone>>) {
+    let client_ip = socket.peer_addr().ok().map(|addr| addr.ip());
+    let framed = Framed::new(socket, LengthDelimitedCodec::new());
+    let (mut sink, mut stream) = framed.split();
+    let (response_tx, mut response_rx) = mpsc::channel::<Bytes>(16);
+
+    let processor = tokio::spawn(async move {
+        while let Some(frame) = stream.next().await {
+            let bytes = match frame {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    println!("faucet read error; error = {:?}", e);
+                    break;
+                }
+            };
+
+            let req: DroneRequest = match deserialize(&bytes) {
+                Ok(req) => req,
+                Err(err) => {
+                    println!("deserialize packet in faucet: {:?}", err);
+                    continue;
+                }
+            };
+
+            if let Some(client_ip) = client_ip {
+                let allowed = {
+                    let mut faucet = faucet.lock().await;
+                    faucet.check_rate_limit(client_ip)
+                };
+                if !allowed {
+                    println!("Per-IP limit reached for {}", client_ip);
+                    continue;
+                }
+            }
+
+            println!("Airdrop requested...");
+            let result = {
+                let mut faucet = faucet.lock().await;
+                faucet.send_airdrop(req)
+            };
+
+            let signature = match result {
+                Ok(signature) => {
+                    println!("Airdrop sent!");
+                    println!("Airdrop tx signature: {:?}", signature);
+                    signature
+                }
+                Err(_) => {
+                    println!("Request limit reached for this time slice");
+                    continue;
+                }
+            };
+
+            match serialize(&signature) {
+                Ok(response_vec) => {
+                    if response_tx.send(Bytes::from(response_vec)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(err) => println!("serialize signature in faucet: {:?}", err),
+            }
+        }
+    });
+
+    let writer = tokio::spawn(async move {
+        while let Some(response) = response_rx.recv().await {
+            if let Err(e) = sink.send(response).await {
+                println!("faucet write error; error = {:?}", e);
+                break;
+            }
+        }
+    });
+
+    let _ = tokio::join!(processor, writer);
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn error::Error>> {
     logger::setup();
     set_panic_hook("faucet");
     let matches = App::new("faucet")
@@ -82,18 +160,12 @@ fn main() -> Result<(), Box<error::Error>> {
     let mint_keypair =
         read_keypair(matches.value_of("keypair").unwrap()).expect("failed to read client keypair");
 
-    let time_slice: Option<u64>;
-    if let Some(secs) = matches.value_of("slice") {
-        time_slice = Some(secs.to_string().parse().expect("failed to parse slice"));
-    } else {
-        time_slice = None;
-    }
-    let request_cap: Option<u64>;
-    if let Some(c) = matches.value_of("cap") {
-        request_cap = Some(c.to_string().parse().expect("failed to parse cap"));
-    } else {
-        request_cap = None;
-    }
+    let time_slice: Option<u64> = matches
+        .value_of("slice")
+        .map(|secs| secs.to_string().parse().expect("failed to parse slice"));
+    let request_cap: Option<u64> = matches
+        .value_of("cap")
+        .map(|c| c.to_string().parse().expect("failed to parse cap"));
 
     let faucet_addr = socketaddr!(0, DRONE_PORT);
 
@@ -105,59 +177,42 @@ fn main() -> Result<(), Box<error::Error>> {
         request_cap,
     )));
 
-    let faucet1 = faucet.clone();
-    thread::spawn(move || loop {
-        let time = faucet1.lock().unwrap().time_slice;
-        thread::sleep(time);
-        faucet1.lock().unwrap().clear_request_count();
+    let clear_time_slice = faucet.lock().await.time_slice;
+    let clear_faucet = faucet.clone();
+    let clear_task = tokio::spawn(async move {
+        let mut interval = time::interval(clear_time_slice);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    clear_faucet.lock().await.clear_request_count();
+                }
+                _ = tokio::signal::ctrl_c() => {
+                    break;
+                }
+            }
+        }
     });
 
-    let socket = TcpListener::bind(&faucet_addr).unwrap();
+    let listener = TcpListener::bind(&faucet_addr).await?;
     println!("Drone started. Listening on: {}", faucet_addr);
-    let done = socket
-        .incoming()
-        .map_err(|e| println!("failed to accept socket; error = {:?}", e))
-        .for_each(move |socket| {
-            let faucet2 = faucet.clone();
-            // let client_ip = socket.peer_addr().expect("faucet peer_addr").ip();
-            let framed = BytesCodec::new().framed(socket);
-            let (writer, reader) = framed.split();
-
-            let processor = reader.and_then(move |bytes| {
-                let req: DroneRequest = deserialize(&bytes).or_else(|err| {
-                    Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        format!("deserialize packet in faucet: {:?}", err),
-                    ))
-                })?;
-
-                println!("Airdrop requested...");
-                // let res = faucet2.lock().unwrap().check_rate_limit(client_ip);
-                let res1 = faucet2.lock().unwrap().send_airdrop(req);
-                match res1 {
-                    Ok(_) => println!("Airdrop sent!"),
-                    Err(_) => println!("Request limit reached for this time slice"),
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                match accepted {
+                    Ok((socket, _)) => {
+                        tokio::spawn(handle_connection(socket, faucet.clone()));
+                    }
+                    Err(e) => println!("failed to accept socket; error = {:?}", e),
                 }
-                let response = res1?;
-                println!("Airdrop tx signature: {:?}", response);
-                let response_vec = serialize(&response).or_else(|err| {
-                    Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        format!("serialize signature in faucet: {:?}", err),
-                    ))
-                })?;
-                let response_bytes = Bytes::from(response_vec.clone());
-                Ok(response_bytes)
-            });
-            let server = writer
-                .send_all(processor.or_else(|err| {
-                    Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        format!("Drone response: {:?}", err),
-                    ))
-                })).then(|_| Ok(()));
-            tokio::spawn(server)
-        });
-    tokio::run(done);
+            }
+            _ = tokio::signal::ctrl_c() => {
+                println!("faucet shutting down");
+                break;
+            }
+        }
+    }
+
+    let _ = clear_task.await;
     Ok(())
 }
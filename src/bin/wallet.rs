@@ -5,12 +5,12 @@ extern crate dirs;
 extern crate hypercube;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
-use hypercube::faucet::DRONE_PORT;
 use hypercube::logger;
-use hypercube::rpc::RPC_PORT;
 use hypercube::signature::{read_keypair, KeypairUtil};
 use hypercube::thin_client::poll_gossip_for_leader;
-use hypercube::qtc::{gen_keypair_file, parse_command, process_command, QtcConfig, QtcError};
+use hypercube::qtc::{
+    gen_keypair_file, normalize_rpc_addr, parse_command, process_command, QtcConfig, QtcError,
+};
 use std::error;
 use std::net::SocketAddr;
 
@@ -50,32 +50,28 @@ pub fn parse_args(matches: &ArgMatches) -> Result<QtcConfig, Box<error::Error>>
     })?;
 
     let leader = poll_gossip_for_leader(network, timeout)?;
+    let command = parse_command(id.pubkey(), &matches)?;
 
-    let mut faucet_addr = leader.contact_info.tx_creator;
-    faucet_addr.set_port(DRONE_PORT);
+    let mut config = QtcConfig {
+        id,
+        command,
+        ..QtcConfig::default()
+    };
 
-    let rpc_addr = if let Some(proxy) = matches.value_of("proxy") {
-        proxy.to_string()
+    let tls = matches.is_present("tls");
+    if let Some(proxy) = matches.value_of("proxy") {
+        config.update_leader_addrs(leader, None, tls);
+        config.rpc_addr = normalize_rpc_addr(proxy);
     } else {
         let rpc_port = if let Some(port) = matches.value_of("rpc-port") {
-            port.to_string().parse().expect("integer")
+            Some(port.to_string().parse().expect("integer"))
         } else {
-            RPC_PORT
+            None
         };
-        let mut rpc_addr = leader.contact_info.tx_creator;
-        rpc_addr.set_port(rpc_port);
-        format!("http://{}", rpc_addr.to_string())
-    };
-
-    let command = parse_command(id.pubkey(), &matches)?;
+        config.update_leader_addrs(leader, rpc_port, tls);
+    }
 
-    Ok(QtcConfig {
-        leader,
-        id,
-        faucet_addr, // TODO: Add an option for this.
-        rpc_addr,
-        command,
-    })
+    Ok(config)
 }
 
 fn main() -> Result<(), Box<error::Error>> {
@@ -115,8 +111,35 @@ fn main() -> Result<(), Box<error::Error>> {
                 .value_name("URL")
                 .help("Address of TLS proxy")
                 .conflicts_with("rpc-port")
+        ).arg(
+            Arg::with_name("tls")
+                .long("tls")
+                .takes_value(false)
+                .help("Connect to the RPC node over https instead of http")
         ).subcommand(SubCommand::with_name("address").about("Get your public key"))
         .subcommand(
+            SubCommand::with_name("advance-nonce-account")
+                .about("Advance a durable nonce account to a fresh blockhash")
+                .arg(
+                    Arg::with_name("nonce-account")
+                        .index(1)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The pubkey of the nonce account to advance"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("authorize-voter")
+                .about("Authorize a new voter for a vote account")
+                .arg(
+                    Arg::with_name("vote-account")
+                        .index(1)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The pubkey of the vote account"),
+                ),
+        ).subcommand(
             SubCommand::with_name("airdrop")
                 .about("Request a batch of tokens")
                 .arg(
@@ -127,8 +150,17 @@ fn main() -> Result<(), Box<error::Error>> {
                         .required(true)
                         .help("The number of tokens to request"),
                 ),
-        ).subcommand(SubCommand::with_name("balance").about("Get your balance"))
-        .subcommand(
+        ).subcommand(
+            SubCommand::with_name("balance")
+                .about("Get your balance, or an arbitrary account's")
+                .arg(
+                    Arg::with_name("pubkey")
+                        .index(1)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .help("The pubkey of the account to query; defaults to your own"),
+                ),
+        ).subcommand(
             SubCommand::with_name("cancel")
                 .about("Cancel a transfer")
                 .arg(
@@ -150,6 +182,97 @@ fn main() -> Result<(), Box<error::Error>> {
                         .required(true)
                         .help("The transaction signature to confirm"),
                 ),
+        ).subcommand(
+            SubCommand::with_name("create-nonce-account")
+                .about("Create and initialize a durable nonce account")
+                .arg(
+                    Arg::with_name("nonce-account")
+                        .index(1)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The pubkey of the nonce account to create"),
+                ).arg(
+                    Arg::with_name("tokens")
+                        .index(2)
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The number of tokens to fund the nonce account with"),
+                ).arg(
+                    Arg::with_name("authority")
+                        .long("authority")
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .help("The pubkey authorized to advance this nonce account; defaults to your own"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("create-vote-account")
+                .about("Create a vote account for a validator")
+                .arg(
+                    Arg::with_name("vote-account")
+                        .index(1)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The pubkey of the vote account to create"),
+                ).arg(
+                    Arg::with_name("node-pubkey")
+                        .index(2)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The pubkey of the validator node to delegate to"),
+                ).arg(
+                    Arg::with_name("commission")
+                        .index(3)
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The commission the vote account charges"),
+                ).arg(
+                    Arg::with_name("tokens")
+                        .index(4)
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The number of tokens to fund the vote account with"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("deploy")
+                .about("Deploy a program")
+                .arg(
+                    Arg::with_name("program-location")
+                        .index(1)
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .required(true)
+                        .help("/path/to/program.o"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("estimate-fee")
+                .about("Estimate a fee from recent network demand")
+                .arg(
+                    Arg::with_name("percentile")
+                        .long("percentile")
+                        .value_name("PERCENTILE")
+                        .takes_value(true)
+                        .help("Percentile of recently paid fees to target, 0-100 (default: 50)"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("get-transaction-count")
+                .about("Get current transaction count"),
+        ).subcommand(
+            SubCommand::with_name("show-vote-account")
+                .about("Show the state of a vote account")
+                .arg(
+                    Arg::with_name("vote-account")
+                        .index(1)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The pubkey of the vote account"),
+                ),
         ).subcommand(
             SubCommand::with_name("pay")
                 .about("Send a payment")
@@ -192,6 +315,45 @@ fn main() -> Result<(), Box<error::Error>> {
                     Arg::with_name("cancelable")
                         .long("cancelable")
                         .takes_value(false),
+                ).arg(
+                    Arg::with_name("blockhash")
+                        .long("blockhash")
+                        .value_name("BLOCKHASH")
+                        .takes_value(true)
+                        .conflicts_with("nonce-account")
+                        .help("Use the supplied blockhash instead of fetching a recent one"),
+                ).arg(
+                    Arg::with_name("nonce-account")
+                        .long("nonce-account")
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .help("Use the stored blockhash from this durable nonce account instead of fetching a recent one"),
+                ).arg(
+                    Arg::with_name("sign-only")
+                        .long("sign-only")
+                        .takes_value(false)
+                        .help("Sign the transaction offline and print the signature without sending it"),
+                ).arg(
+                    Arg::with_name("signer")
+                        .long("signer")
+                        .value_name("PUBKEY=SIGNATURE")
+                        .takes_value(true)
+                        .conflicts_with("reply")
+                        .help("Provide a signature obtained from an offline signer"),
+                ).arg(
+                    Arg::with_name("reply")
+                        .long("reply")
+                        .value_name("JSON")
+                        .takes_value(true)
+                        .conflicts_with("blockhash")
+                        .help("Finalize and broadcast the JSON reply printed by a --sign-only payment"),
+                ).arg(
+                    Arg::with_name("threshold")
+                        .long("threshold")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .requires("witness")
+                        .help("Number of required signatures that must be collected before the payment clears (defaults to all of them)"),
                 ),
         ).subcommand(
             SubCommand::with_name("send-signature")
@@ -235,6 +397,9 @@ fn main() -> Result<(), Box<error::Error>> {
                         .takes_value(true)
                         .help("Optional arbitrary timestamp to apply")
                 )
+        ).subcommand(
+            SubCommand::with_name("vote")
+                .about("Submit a vote from this identity"),
         ).get_matches();
 
     let config = parse_args(&matches)?;
@@ -0,0 +1,185 @@
+//! The `broadcast_stage` turns the entries a leader's pipeline produces into
+//! blobs stamped with this node's id and the cluster's `shred_version`,
+//! records them in the shared window so repair requests can serve them
+//! later, and fans them out to every peer in the current `BlockThread` view.
+
+use bincode::serialize;
+use blockthread::BlockThread;
+use entry::Entry;
+use hash::Hash;
+use packet::SharedBlob;
+use result::{Error, Result};
+use service::Service;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, Builder, JoinHandle};
+use std::time::Duration;
+use window::SharedWindow;
+
+/// Which path `BroadcastStage` takes from entries to blobs on the wire.
+/// `Standard` is the production path; `FailEntryVerification` corrupts
+/// every entry's id before broadcasting, so tests can exercise how
+/// validators react to a leader that hands out bad data.
+pub enum BroadcastStageType {
+    Standard,
+    FailEntryVerification,
+}
+
+impl BroadcastStageType {
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
+    pub fn new_broadcast_stage(
+        &self,
+        sockets: Vec<Arc<UdpSocket>>,
+        blockthread: Arc<RwLock<BlockThread>>,
+        window: SharedWindow,
+        entry_height: u64,
+        shred_version: u16,
+        receiver: Receiver<Vec<Entry>>,
+        exit_sender: Arc<AtomicBool>,
+    ) -> BroadcastStage {
+        let corrupt_entries = match *self {
+            BroadcastStageType::Standard => false,
+            BroadcastStageType::FailEntryVerification => true,
+        };
+        BroadcastStage::new(
+            sockets,
+            blockthread,
+            window,
+            entry_height,
+            shred_version,
+            receiver,
+            exit_sender,
+            corrupt_entries,
+        )
+    }
+}
+
+pub struct BroadcastStage {
+    thread_hdl: JoinHandle<()>,
+}
+
+impl BroadcastStage {
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
+    fn new(
+        sockets: Vec<Arc<UdpSocket>>,
+        blockthread: Arc<RwLock<BlockThread>>,
+        window: SharedWindow,
+        entry_height: u64,
+        shred_version: u16,
+        receiver: Receiver<Vec<Entry>>,
+        exit_sender: Arc<AtomicBool>,
+        corrupt_entries: bool,
+    ) -> Self {
+        let thread_hdl = Builder::new()
+            .name("hypercube-broadcast-stage".to_string())
+            .spawn(move || {
+                let mut entry_height = entry_height;
+                let socket_index = AtomicUsize::new(0);
+                loop {
+                    match Self::broadcast(
+                        &sockets,
+                        &socket_index,
+                        &blockthread,
+                        &window,
+                        &mut entry_height,
+                        shred_version,
+                        &receiver,
+                        corrupt_entries,
+                    ) {
+                        Err(Error::RecvTimeoutError(RecvTimeoutError::Disconnected)) => break,
+                        Err(Error::RecvTimeoutError(RecvTimeoutError::Timeout)) => (),
+                        Err(e) => error!("hypercube-broadcast-stage unexpected error {:?}", e),
+                        Ok(()) => (),
+                    }
+                }
+                exit_sender.store(true, Ordering::Relaxed);
+            }).unwrap();
+
+        BroadcastStage { thread_hdl }
+    }
+
+    /// Package one batch of entries into blobs, file them into the shared
+    /// window at their `entry_height` slot so repair can serve them later,
+    /// and send them to every peer `BlockThread` currently knows about,
+    /// round-robining across `sockets` to spread the outbound load.
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
+    fn broadcast(
+        sockets: &[Arc<UdpSocket>],
+        socket_index: &AtomicUsize,
+        blockthread: &Arc<RwLock<BlockThread>>,
+        window: &SharedWindow,
+        entry_height: &mut u64,
+        shred_version: u16,
+        receiver: &Receiver<Vec<Entry>>,
+        corrupt_entries: bool,
+    ) -> Result<()> {
+        let mut entries = receiver.recv_timeout(Duration::from_millis(100))?;
+        while let Ok(mut more) = receiver.try_recv() {
+            entries.append(&mut more);
+        }
+
+        let my_id = blockthread.read().unwrap().id;
+        let blobs = Self::entries_to_blobs(&entries, *entry_height, my_id, shred_version, corrupt_entries);
+        *entry_height += entries.len() as u64;
+
+        {
+            let mut window = window.write().unwrap();
+            for blob in &blobs {
+                window.add_blob(blob.clone());
+            }
+        }
+
+        let broadcast_table = blockthread.read().unwrap().compute_broadcast_table();
+        for blob in &blobs {
+            let data = blob.read().unwrap();
+            let index = socket_index.fetch_add(1, Ordering::Relaxed) % sockets.len();
+            for peer in &broadcast_table {
+                sockets[index]
+                    .send_to(&data.data[..data.meta.size], &peer.contact_info.tx_signer)
+                    .expect("broadcast send_to");
+            }
+        }
+
+        inc_new_counter_info!("broadcast-entries", entries.len());
+        Ok(())
+    }
+
+    fn entries_to_blobs(
+        entries: &[Entry],
+        entry_height: u64,
+        id: ::xpz_program_interface::pubkey::Pubkey,
+        shred_version: u16,
+        corrupt_entries: bool,
+    ) -> Vec<SharedBlob> {
+        entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let mut entry = entry.clone();
+                if corrupt_entries {
+                    entry.id = Hash::default();
+                }
+                let blob = SharedBlob::default();
+                {
+                    let mut w = blob.write().unwrap();
+                    w.set_index(entry_height + i as u64).unwrap();
+                    w.set_id(id).unwrap();
+                    w.set_shred_version(shred_version);
+                    let serialized = serialize(&entry).expect("serialize entry in broadcast_stage");
+                    w.data_mut()[..serialized.len()].copy_from_slice(&serialized);
+                    w.set_size(serialized.len());
+                }
+                blob
+            }).collect()
+    }
+}
+
+impl Service for BroadcastStage {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        self.thread_hdl.join()
+    }
+}
@@ -4,12 +4,35 @@ use rand::thread_rng;
 use result::Result;
 use xpz_program_interface::pubkey::Pubkey;
 use std;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub const DEFAULT_WEIGHT: u32 = 1;
 
 pub trait ChooseGossipPeerStrategy {
-    fn choose_peer<'a>(&self, options: Vec<&'a NodeInfo>) -> Result<&'a NodeInfo>;
+    /// `exclude` holds peers to skip (e.g. contacted in the last N rounds,
+    /// or currently marked unresponsive); it's only honored if doing so
+    /// still leaves at least one candidate, so a caller can never exclude
+    /// its way down to `BlockThreadError::NoPeers` when peers do exist.
+    fn choose_peer<'a>(&self, options: Vec<&'a NodeInfo>, exclude: &HashSet<Pubkey>) -> Result<&'a NodeInfo>;
+}
+
+/// Drop any peer in `exclude` from `options`, unless doing so would leave
+/// nothing to choose from, in which case the exclusion is ignored for this
+/// round rather than returning `NoPeers`.
+fn apply_exclusions<'a>(options: Vec<&'a NodeInfo>, exclude: &HashSet<Pubkey>) -> Vec<&'a NodeInfo> {
+    if exclude.is_empty() {
+        return options;
+    }
+    let filtered: Vec<&'a NodeInfo> = options
+        .iter()
+        .cloned()
+        .filter(|peer| !exclude.contains(&peer.id))
+        .collect();
+    if filtered.is_empty() {
+        options
+    } else {
+        filtered
+    }
 }
 
 pub struct ChooseRandomPeerStrategy<'a> {
@@ -27,7 +50,8 @@ impl<'a, 'b> ChooseRandomPeerStrategy<'a> {
 }
 
 impl<'a> ChooseGossipPeerStrategy for ChooseRandomPeerStrategy<'a> {
-    fn choose_peer<'b>(&self, options: Vec<&'b NodeInfo>) -> Result<&'b NodeInfo> {
+    fn choose_peer<'b>(&self, options: Vec<&'b NodeInfo>, exclude: &HashSet<Pubkey>) -> Result<&'b NodeInfo> {
+        let options = apply_exclusions(options, exclude);
         if options.is_empty() {
             Err(BlockThreadError::NoPeers)?;
         }
@@ -127,7 +151,8 @@ impl<'a> ChooseWeightedPeerStrategy<'a> {
 }
 
 impl<'a> ChooseGossipPeerStrategy for ChooseWeightedPeerStrategy<'a> {
-    fn choose_peer<'b>(&self, options: Vec<&'b NodeInfo>) -> Result<&'b NodeInfo> {
+    fn choose_peer<'b>(&self, options: Vec<&'b NodeInfo>, exclude: &HashSet<Pubkey>) -> Result<&'b NodeInfo> {
+        let options = apply_exclusions(options, exclude);
         if options.is_empty() {
             Err(BlockThreadError::NoPeers)?;
         }
@@ -143,14 +168,72 @@ impl<'a> ChooseGossipPeerStrategy for ChooseWeightedPeerStrategy<'a> {
     }
 }
 
+/// Blends an inner `ChooseWeightedPeerStrategy`'s liveness scoring with a
+/// flat uniform weight over the same candidates, so a handful of
+/// high-liveness peers can never fully starve exploration of the rest of
+/// the cluster. `weighted_mix` is the share (0.0-1.0) of each peer's final
+/// weight that comes from its liveness score; the remainder is split
+/// evenly across all candidates.
+pub struct ChooseCompositePeerStrategy<'a> {
+    weighted: &'a ChooseWeightedPeerStrategy<'a>,
+    weighted_mix: f64,
+}
+
+impl<'a> ChooseCompositePeerStrategy<'a> {
+    pub fn new(weighted: &'a ChooseWeightedPeerStrategy<'a>, weighted_mix: f64) -> Self {
+        ChooseCompositePeerStrategy {
+            weighted,
+            weighted_mix: weighted_mix.max(0.0).min(1.0),
+        }
+    }
+}
+
+impl<'a> ChooseGossipPeerStrategy for ChooseCompositePeerStrategy<'a> {
+    fn choose_peer<'b>(&self, options: Vec<&'b NodeInfo>, exclude: &HashSet<Pubkey>) -> Result<&'b NodeInfo> {
+        let options = apply_exclusions(options, exclude);
+        if options.is_empty() {
+            Err(BlockThreadError::NoPeers)?;
+        }
+
+        let liveness: Vec<f64> = options
+            .iter()
+            .map(|peer| f64::from(self.weighted.calculate_weighted_remote_index(peer.id)))
+            .collect();
+        let total_liveness: f64 = liveness.iter().sum();
+        let uniform_share = 1.0 / options.len() as f64;
+
+        // Build one combined `WeightedChoice` distribution rather than
+        // sampling the two strategies separately, so a single draw reflects
+        // both the liveness ranking and the exploration floor at once.
+        let mut weighted_peers = vec![];
+        for (peer, &peer_liveness) in options.into_iter().zip(liveness.iter()) {
+            let liveness_share = if total_liveness > 0.0 {
+                peer_liveness / total_liveness
+            } else {
+                uniform_share
+            };
+            let combined = self.weighted_mix * liveness_share + (1.0 - self.weighted_mix) * uniform_share;
+            let weight = ((combined * f64::from(std::u32::MAX)) as u32).max(DEFAULT_WEIGHT);
+            weighted_peers.push(Weighted { weight, item: peer });
+        }
+
+        let mut rng = thread_rng();
+        Ok(WeightedChoice::new(&mut weighted_peers).sample(&mut rng))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use choose_gossip_peer_strategy::{ChooseWeightedPeerStrategy, DEFAULT_WEIGHT};
+    use blockthread::NodeInfo;
+    use choose_gossip_peer_strategy::{
+        ChooseCompositePeerStrategy, ChooseGossipPeerStrategy, ChooseRandomPeerStrategy,
+        ChooseWeightedPeerStrategy, DEFAULT_WEIGHT,
+    };
     use logger;
     use signature::{Keypair, KeypairUtil};
     use xpz_program_interface::pubkey::Pubkey;
     use std;
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
 
     fn get_stake(_id: Pubkey) -> f64 {
         1.0
@@ -280,4 +363,80 @@ mod tests {
 
         assert_eq!(result, DEFAULT_WEIGHT);
     }
+
+    fn node_info(pubkey: Pubkey) -> NodeInfo {
+        NodeInfo::new_with_pubkey_socketaddr(pubkey, &"127.0.0.1:1234".parse().unwrap())
+    }
+
+    #[test]
+    fn test_random_strategy_honors_exclusions() {
+        logger::setup();
+
+        let key1 = Keypair::new().pubkey();
+        let key2 = Keypair::new().pubkey();
+        let node1 = node_info(key1);
+        let node2 = node_info(key2);
+
+        let mut exclude = HashSet::new();
+        exclude.insert(key1);
+
+        let random = || 0u64;
+        let strategy = ChooseRandomPeerStrategy::new(&random);
+        let chosen = strategy
+            .choose_peer(vec![&node1, &node2], &exclude)
+            .unwrap();
+        assert_eq!(chosen.id, key2);
+    }
+
+    #[test]
+    fn test_random_strategy_falls_back_when_exclusion_empties_options() {
+        logger::setup();
+
+        let key1 = Keypair::new().pubkey();
+        let node1 = node_info(key1);
+
+        let mut exclude = HashSet::new();
+        exclude.insert(key1);
+
+        let random = || 0u64;
+        let strategy = ChooseRandomPeerStrategy::new(&random);
+        let chosen = strategy.choose_peer(vec![&node1], &exclude).unwrap();
+        assert_eq!(chosen.id, key1);
+    }
+
+    #[test]
+    fn test_composite_strategy_never_starves_low_liveness_peer() {
+        logger::setup();
+
+        let key1 = Keypair::new().pubkey();
+        let key2 = Keypair::new().pubkey();
+        let node1 = node_info(key1);
+        let node2 = node_info(key2);
+
+        let remote: HashMap<Pubkey, u64> = HashMap::new();
+        let mut external_liveness: HashMap<Pubkey, HashMap<Pubkey, u64>> = HashMap::new();
+        let mut rumors: HashMap<Pubkey, u64> = HashMap::new();
+        rumors.insert(Keypair::new().pubkey(), 1000);
+        external_liveness.insert(key1, rumors);
+        // key2 has no liveness rumors at all, so on a pure weighted draw it
+        // would only ever get DEFAULT_WEIGHT against key1's huge score.
+
+        let weighted = ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake);
+        let composite = ChooseCompositePeerStrategy::new(&weighted, 0.8);
+
+        let exclude = HashSet::new();
+        let mut saw_key2 = false;
+        for _ in 0..200 {
+            if composite
+                .choose_peer(vec![&node1, &node2], &exclude)
+                .unwrap()
+                .id
+                == key2
+            {
+                saw_key2 = true;
+                break;
+            }
+        }
+        assert!(saw_key2, "uniform exploration share should surface key2 eventually");
+    }
 }
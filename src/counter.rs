@@ -0,0 +1,217 @@
+//! The `counter` module provides counters and histograms that accumulate
+//! locally and only pay for a log line and a metrics point once every
+//! `lograte` calls, so a hot code path can track its own throughput and
+//! latency without stalling on I/O for every single event.
+
+use log::Level;
+use metrics;
+use metrics::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const DEFAULT_LOG_RATE: usize = 1000;
+const NUM_SUBBUCKETS: usize = 4;
+
+pub struct Counter {
+    pub name: &'static str,
+    pub counts: AtomicUsize,
+    pub times: AtomicUsize,
+    pub lograte: usize,
+}
+
+impl Counter {
+    pub fn new(name: &'static str) -> Self {
+        Self::new_with_lograte(name, DEFAULT_LOG_RATE)
+    }
+
+    pub fn new_with_lograte(name: &'static str, lograte: usize) -> Self {
+        Counter {
+            name,
+            counts: AtomicUsize::new(0),
+            times: AtomicUsize::new(0),
+            lograte,
+        }
+    }
+
+    pub fn inc(&self, level: Level, events: usize) {
+        let counts = self.counts.fetch_add(events, Ordering::Relaxed) + events;
+        let times = self.times.fetch_add(1, Ordering::Relaxed) + 1;
+        if times % self.lograte == 0 {
+            log!(
+                level,
+                "COUNTER:{{\"name\": \"{}\", \"counts\": {}, \"samples\": {}}}",
+                self.name,
+                counts,
+                times
+            );
+            metrics::submit(
+                metrics::Point::new(&format!("counter-{}", self.name))
+                    .add_field("count", Value::Integer(counts as i64))
+                    .to_owned(),
+                Some(level),
+            );
+        }
+    }
+}
+
+/// A log-linear histogram: buckets double in width at every power of two
+/// (each doubling is split into `NUM_SUBBUCKETS` linear sub-buckets), keyed
+/// off the position of a value's highest set bit. This keeps relative error
+/// bounded across a huge dynamic range (microseconds to seconds) without
+/// needing a bucket per distinct value.
+pub struct Histogram {
+    pub name: &'static str,
+    buckets: Vec<AtomicUsize>,
+    samples: AtomicUsize,
+    lograte: usize,
+}
+
+impl Histogram {
+    pub fn new(name: &'static str) -> Self {
+        Self::new_with_lograte(name, DEFAULT_LOG_RATE)
+    }
+
+    pub fn new_with_lograte(name: &'static str, lograte: usize) -> Self {
+        let num_buckets = 64 * NUM_SUBBUCKETS;
+        Histogram {
+            name,
+            buckets: (0..num_buckets).map(|_| AtomicUsize::new(0)).collect(),
+            samples: AtomicUsize::new(0),
+            lograte,
+        }
+    }
+
+    fn bucket_for(value: u64) -> usize {
+        if value == 0 {
+            return 0;
+        }
+        let log_bucket = 63 - value.leading_zeros() as usize;
+        let base = 1u64 << log_bucket;
+        let sub_bucket = ((value - base) * NUM_SUBBUCKETS as u64) >> log_bucket;
+        log_bucket * NUM_SUBBUCKETS + sub_bucket as usize
+    }
+
+    fn value_for_bucket(bucket: usize) -> u64 {
+        if bucket == 0 {
+            return 0;
+        }
+        let log_bucket = bucket / NUM_SUBBUCKETS;
+        let sub_bucket = (bucket % NUM_SUBBUCKETS) as u64;
+        (1u64 << log_bucket) + ((sub_bucket << log_bucket) / NUM_SUBBUCKETS as u64)
+    }
+
+    pub fn record(&self, value: u64) {
+        self.buckets[Self::bucket_for(value)].fetch_add(1, Ordering::Relaxed);
+        let samples = self.samples.fetch_add(1, Ordering::Relaxed) + 1;
+        if samples % self.lograte == 0 {
+            self.submit(samples);
+        }
+    }
+
+    fn percentile(&self, fraction: f64) -> u64 {
+        let counts: Vec<usize> = self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        let total: usize = counts.iter().sum();
+        if total == 0 {
+            return 0;
+        }
+        let target = (total as f64 * fraction).ceil() as usize;
+        let mut seen = 0;
+        for (bucket, count) in counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return Self::value_for_bucket(bucket);
+            }
+        }
+        Self::value_for_bucket(self.buckets.len() - 1)
+    }
+
+    pub fn mean(&self) -> u64 {
+        let mut total = 0u64;
+        let mut samples = 0u64;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            let n = count.load(Ordering::Relaxed) as u64;
+            total += Self::value_for_bucket(bucket) * n;
+            samples += n;
+        }
+        if samples == 0 {
+            0
+        } else {
+            total / samples
+        }
+    }
+
+    fn submit(&self, samples: usize) {
+        debug!(
+            "HISTOGRAM:{{\"name\": \"{}\", \"p50\": {}, \"p90\": {}, \"p99\": {}, \"samples\": {}}}",
+            self.name,
+            self.percentile(0.5),
+            self.percentile(0.9),
+            self.percentile(0.99),
+            samples
+        );
+        metrics::submit(
+            metrics::Point::new(&format!("histogram-{}", self.name))
+                .add_field("p50", Value::Integer(self.percentile(0.5) as i64))
+                .add_field("p90", Value::Integer(self.percentile(0.9) as i64))
+                .add_field("p99", Value::Integer(self.percentile(0.99) as i64))
+                .add_field("max", Value::Integer(self.percentile(1.0) as i64))
+                .add_field("mean", Value::Integer(self.mean() as i64))
+                .to_owned(),
+            Some(Level::Debug),
+        );
+    }
+}
+
+#[macro_export]
+macro_rules! inc_new_counter {
+    ($name:expr, $count:expr, $level:expr, $lograte:expr) => {{
+        use std::sync::{Once, ONCE_INIT};
+        static INIT: Once = ONCE_INIT;
+        static mut COUNTER: Option<$crate::counter::Counter> = None;
+        unsafe {
+            INIT.call_once(|| {
+                COUNTER = Some($crate::counter::Counter::new_with_lograte($name, $lograte));
+            });
+            if let Some(ref counter) = COUNTER {
+                counter.inc($level, $count);
+            }
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! inc_new_counter_info {
+    ($name:expr, $count:expr) => {
+        if log_enabled!(Level::Info) {
+            inc_new_counter!($name, $count, Level::Info, 1000);
+        }
+    };
+}
+
+/// Build a standalone `Histogram`, for code that wants to own one on a
+/// struct rather than record through the call-site-static `inc_histogram!`.
+#[macro_export]
+macro_rules! create_histogram {
+    ($name:expr) => {
+        $crate::counter::Histogram::new($name)
+    };
+    ($name:expr, $lograte:expr) => {
+        $crate::counter::Histogram::new_with_lograte($name, $lograte)
+    };
+}
+
+#[macro_export]
+macro_rules! inc_histogram {
+    ($name:expr, $value:expr) => {{
+        use std::sync::{Once, ONCE_INIT};
+        static INIT: Once = ONCE_INIT;
+        static mut HISTOGRAM: Option<$crate::counter::Histogram> = None;
+        unsafe {
+            INIT.call_once(|| {
+                HISTOGRAM = Some($crate::counter::Histogram::new($name));
+            });
+            if let Some(ref histogram) = HISTOGRAM {
+                histogram.record($value);
+            }
+        }
+    }};
+}
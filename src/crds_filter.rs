@@ -0,0 +1,220 @@
+//! Compact anti-entropy for the weighted gossip path. Rather than a full
+//! table exchange, a node builds a Bloom filter over the hashes of the
+//! `NodeInfo`/vote values it already holds and ships that as a
+//! `PullRequest`; the peer only needs to reply with values whose hashes
+//! miss the filter. A single filter can't cover an arbitrarily large
+//! table under a fixed byte budget, so `build_crds_filters` partitions the
+//! key space by the high bits of the value hash and returns one
+//! `CrdsFilter` per partition, to be sent one partition per round.
+
+use hash::Hash;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+
+/// Target false-positive rate used when sizing a filter from the
+/// cardinality of the partition it covers.
+const FALSE_POSITIVE_RATE: f64 = 0.1;
+
+/// A Bloom filter over one partition of the local value-hash table, plus
+/// enough bookkeeping (`partition`/`mask_bits`) for the peer to know which
+/// slice of its own table to check it against. `bits` is packed 8 bits per
+/// byte (see `num_bits`/`get_bit`/`set_bit`) rather than stored as
+/// `Vec<bool>`, which both in memory and under serde serializes one full
+/// byte per element — 8x the size `build_crds_filters` budgets for.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CrdsFilter {
+    bits: Vec<u8>,
+    num_bits: usize,
+    keys: Vec<u64>,
+    partition: u64,
+    mask_bits: u32,
+}
+
+impl CrdsFilter {
+    fn new(items: &[Hash], partition: u64, mask_bits: u32) -> Self {
+        let num_bits = optimal_num_bits(items.len());
+        let num_hashes = optimal_num_hashes(num_bits, items.len());
+        let keys: Vec<u64> = (0..num_hashes).map(|_| rand::random()).collect();
+
+        let mut filter = CrdsFilter {
+            bits: vec![0u8; (num_bits + 7) / 8],
+            num_bits,
+            keys,
+            partition,
+            mask_bits,
+        };
+        for item in items {
+            filter.insert(item);
+        }
+        filter
+    }
+
+    fn get_bit(&self, index: usize) -> bool {
+        self.bits[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    fn set_bit(&mut self, index: usize) {
+        self.bits[index / 8] |= 1 << (index % 8);
+    }
+
+    fn hash_indexes(&self, value: &Hash) -> Vec<usize> {
+        self.keys
+            .iter()
+            .map(|&key| {
+                let mut hasher = DefaultHasher::new();
+                hasher.write_u64(key);
+                hasher.write(value.as_ref());
+                (hasher.finish() as usize) % self.num_bits
+            }).collect()
+    }
+
+    fn insert(&mut self, value: &Hash) {
+        for index in self.hash_indexes(value) {
+            self.set_bit(index);
+        }
+    }
+
+    /// False means `value` is definitely not present; true means it
+    /// probably is (subject to `FALSE_POSITIVE_RATE`).
+    pub fn contains(&self, value: &Hash) -> bool {
+        self.hash_indexes(value).into_iter().all(|index| self.get_bit(index))
+    }
+
+    /// Whether `value` falls into the partition this filter covers.
+    pub fn matches_partition(&self, value: &Hash) -> bool {
+        partition_index(value, self.mask_bits) as u64 == self.partition
+    }
+}
+
+/// Sent to a peer chosen by a `ChooseGossipPeerStrategy` to ask it for
+/// whichever values in this filter's partition the sender doesn't already
+/// have; the peer replies only with the misses.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PullRequest {
+    pub filter: CrdsFilter,
+}
+
+fn optimal_num_bits(num_items: usize) -> usize {
+    let n = num_items.max(1) as f64;
+    let m = -(n * FALSE_POSITIVE_RATE.ln()) / (2f64.ln() * 2f64.ln());
+    (m.ceil() as usize).max(64)
+}
+
+fn optimal_num_hashes(num_bits: usize, num_items: usize) -> u32 {
+    let m = num_bits as f64;
+    let n = num_items.max(1) as f64;
+    (((m / n) * 2f64.ln()).ceil() as u32).max(1)
+}
+
+/// Partition index is just the top `mask_bits` bits of the value's hash.
+fn partition_index(value: &Hash, mask_bits: u32) -> usize {
+    if mask_bits == 0 {
+        return 0;
+    }
+    let bits = mask_bits.min(8);
+    let top_byte = value.as_ref()[0] as u32;
+    (top_byte >> (8 - bits)) as usize
+}
+
+/// Split `values` into partitions, sized so each partition's filter fits
+/// within `max_bytes`, and build one `CrdsFilter` per partition.
+pub fn build_crds_filters(values: &[Hash], max_bytes: usize) -> Vec<CrdsFilter> {
+    let mut mask_bits = 0u32;
+    while mask_bits < 8
+        && (optimal_num_bits(values.len() >> mask_bits.min(8)) + 7) / 8 > max_bytes
+    {
+        mask_bits += 1;
+    }
+    let num_partitions = 1usize << mask_bits;
+
+    let mut buckets: Vec<Vec<Hash>> = vec![Vec::new(); num_partitions];
+    for &value in values {
+        buckets[partition_index(&value, mask_bits)].push(value);
+    }
+
+    buckets
+        .into_iter()
+        .enumerate()
+        .map(|(partition, items)| CrdsFilter::new(&items, partition as u64, mask_bits))
+        .collect()
+}
+
+/// The values from `table` that `filter` indicates its sender is missing:
+/// those in `filter`'s partition whose hash the filter doesn't contain.
+pub fn filter_crds_values<V: Clone>(filter: &CrdsFilter, table: &HashMap<Hash, V>) -> Vec<(Hash, V)> {
+    table
+        .iter()
+        .filter(|&(hash, _)| filter.matches_partition(hash) && !filter.contains(hash))
+        .map(|(&hash, value)| (hash, value.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::serialize;
+    use hash::hash;
+
+    fn test_hash(seed: u8) -> Hash {
+        let mut bytes = [0u8; 32];
+        bytes[0] = seed;
+        hash(&bytes)
+    }
+
+    #[test]
+    fn test_filter_contains_inserted_values() {
+        let values: Vec<Hash> = (0..50).map(test_hash).collect();
+        let filters = build_crds_filters(&values, 4096);
+        for value in &values {
+            let partition = filters.iter().find(|filter| filter.matches_partition(value)).unwrap();
+            assert!(partition.contains(value));
+        }
+    }
+
+    #[test]
+    fn test_filter_crds_values_excludes_known_values() {
+        let values: Vec<Hash> = (0..20).map(test_hash).collect();
+        let filters = build_crds_filters(&values, 4096);
+
+        let mut table = HashMap::new();
+        for (i, value) in values.iter().enumerate() {
+            table.insert(*value, i);
+        }
+        // a value the filter's owner doesn't have at all
+        let unknown = test_hash(200);
+        table.insert(unknown, 999);
+
+        for filter in &filters {
+            let missing = filter_crds_values(filter, &table);
+            // everything the owner already holds (a value in `values`)
+            // must never come back as missing.
+            for (hash, _) in &missing {
+                assert!(!values.contains(hash));
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_crds_filters_respects_byte_budget() {
+        let values: Vec<Hash> = (0..255).map(|i| test_hash(i as u8)).collect();
+        let filters = build_crds_filters(&values, 64);
+        assert!(filters.len() > 1);
+    }
+
+    #[test]
+    fn test_build_crds_filters_serialized_size_fits_budget() {
+        let values: Vec<Hash> = (0..4000).map(|i| test_hash((i % 256) as u8)).collect();
+        let max_bytes = 512;
+        let filters = build_crds_filters(&values, max_bytes);
+        for filter in &filters {
+            let bytes = serialize(filter).unwrap();
+            assert!(
+                bytes.len() <= max_bytes,
+                "filter serialized to {} bytes, budget was {}",
+                bytes.len(),
+                max_bytes
+            );
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! The `faucet` module implements the airdrop service used by local and
+//! test networks: it countersigns and forwards a `SystemTransaction` moving
+//! tokens out of its own mint keypair whenever a `DroneRequest::GetAirdrop`
+//! comes in, subject to both a global per-time-slice cap and a per-client
+//! IP token bucket so a single abusive peer can't exhaust the whole window.
+
+use bs58;
+use builtin_tansaction::SystemTransaction;
+use bincode::serialize;
+use hash::Hash;
+use log::Level;
+use reqwest;
+use rpc::RPC_PORT;
+use serde_json::{self, Value};
+use signature::{Keypair, Signature};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use transaction::Transaction;
+use xpz_program_interface::pubkey::Pubkey;
+
+pub const DRONE_PORT: u16 = 9900;
+
+const DEFAULT_TIME_SLICE_SECS: u64 = 60;
+const DEFAULT_REQUEST_CAP: u64 = 150_000_000_000;
+
+/// Per-IP request-count cap/refill-rate for `TokenBucket`, denominated in
+/// requests rather than `request_cap`'s lamport scale — each request only
+/// ever deducts a single token, so sizing the bucket off `request_cap`
+/// would let one IP through ~150 billion requests before ever throttling.
+const PER_IP_REQUEST_CAP: u64 = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum DroneRequest {
+    GetAirdrop {
+        airdrop_request_amount: u64,
+        client_pubkey: Pubkey,
+    },
+}
+
+#[derive(Debug)]
+pub enum DroneError {
+    RequestLimitReached,
+    PerIpLimitReached,
+    Io(io::Error),
+}
+
+impl From<io::Error> for DroneError {
+    fn from(err: io::Error) -> Self {
+        DroneError::Io(err)
+    }
+}
+
+fn duration_to_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1_000_000_000.0
+}
+
+/// A per-client-IP allowance that refills linearly over time rather than
+/// resetting in a single lump at the top of every time slice, so a client
+/// that requests steadily can't be starved by one that bursts right before
+/// the window turns over.
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(cap: u64) -> Self {
+        TokenBucket {
+            tokens: cap as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, refill_rate_per_sec: f64, cap: u64) {
+        let now = Instant::now();
+        let elapsed = duration_to_secs(now.duration_since(self.last_refill));
+        self.tokens = (self.tokens + elapsed * refill_rate_per_sec).min(cap as f64);
+        self.last_refill = now;
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_refill.elapsed()
+    }
+}
+
+pub struct Drone {
+    mint_keypair: Keypair,
+    addr: SocketAddr,
+    network: SocketAddr,
+    pub time_slice: Duration,
+    request_cap: u64,
+    request_current: u64,
+    ip_buckets: HashMap<IpAddr, TokenBucket>,
+}
+
+impl Drone {
+    pub fn new(
+        mint_keypair: Keypair,
+        addr: SocketAddr,
+        network: SocketAddr,
+        time_slice: Option<u64>,
+        request_cap: Option<u64>,
+    ) -> Self {
+        Drone {
+            mint_keypair,
+            addr,
+            network,
+            time_slice: Duration::new(time_slice.unwrap_or(DEFAULT_TIME_SLICE_SECS), 0),
+            request_cap: request_cap.unwrap_or(DEFAULT_REQUEST_CAP),
+            request_current: 0,
+            ip_buckets: HashMap::new(),
+        }
+    }
+
+    /// Check and consume one token from `client_ip`'s bucket, refilling it
+    /// first for however long it's been since the last check. Returns
+    /// `false` (without consuming a token) once the bucket runs dry.
+    pub fn check_rate_limit(&mut self, client_ip: IpAddr) -> bool {
+        let cap = PER_IP_REQUEST_CAP;
+        let refill_rate_per_sec = cap as f64 / duration_to_secs(self.time_slice);
+        let bucket = self
+            .ip_buckets
+            .entry(client_ip)
+            .or_insert_with(|| TokenBucket::new(cap));
+        bucket.refill(refill_rate_per_sec, cap);
+
+        if bucket.tokens < 1.0 {
+            inc_new_counter_info!("drone-reject-per-ip-limit", 1);
+            false
+        } else {
+            bucket.tokens -= 1.0;
+            true
+        }
+    }
+
+    /// Reset the global per-time-slice request count and drop any per-IP
+    /// buckets that haven't been touched in a full time slice, so the map
+    /// doesn't grow without bound as distinct clients come and go.
+    pub fn clear_request_count(&mut self) {
+        self.request_current = 0;
+        let time_slice = self.time_slice;
+        self.ip_buckets
+            .retain(|_, bucket| bucket.idle_for() < time_slice);
+    }
+
+    fn get_last_id(&self) -> Result<Hash, DroneError> {
+        let mut rpc_addr = self.network;
+        rpc_addr.set_port(RPC_PORT);
+        let client = reqwest::Client::new();
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "getLastId",
+        });
+        let response: Value = client
+            .post(&format!("http://{}", rpc_addr))
+            .json(&request)
+            .send()
+            .map_err(|e| DroneError::Io(io::Error::new(io::ErrorKind::Other, format!("{:?}", e))))?
+            .json()
+            .map_err(|e| DroneError::Io(io::Error::new(io::ErrorKind::Other, format!("{:?}", e))))?;
+        let last_id_str = response["result"].as_str().ok_or_else(|| {
+            DroneError::Io(io::Error::new(io::ErrorKind::Other, "bad getLastId response"))
+        })?;
+        let last_id_vec = bs58::decode(last_id_str)
+            .into_vec()
+            .map_err(|_| DroneError::Io(io::Error::new(io::ErrorKind::Other, "bad last_id encoding")))?;
+        Ok(Hash::new(&last_id_vec))
+    }
+
+    /// Sign and forward an airdrop transaction out of the mint keypair,
+    /// subject to the global per-time-slice request cap. Per-IP limiting is
+    /// enforced by the caller via `check_rate_limit` before this is called.
+    pub fn send_airdrop(&mut self, req: DroneRequest) -> Result<Signature, DroneError> {
+        let DroneRequest::GetAirdrop {
+            airdrop_request_amount,
+            client_pubkey,
+        } = req;
+
+        if self.request_current + airdrop_request_amount > self.request_cap {
+            return Err(DroneError::RequestLimitReached);
+        }
+
+        let last_id = self.get_last_id()?;
+        let tx = Transaction::system_new(
+            &self.mint_keypair,
+            client_pubkey,
+            airdrop_request_amount as i64,
+            last_id,
+        );
+        let tx_bytes = serialize(&tx)
+            .map_err(|e| DroneError::Io(io::Error::new(io::ErrorKind::Other, format!("{:?}", e))))?;
+
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.send_to(&tx_bytes, self.network)?;
+
+        self.request_current += airdrop_request_amount;
+        Ok(tx.signature)
+    }
+}
@@ -0,0 +1,177 @@
+//! The `fetch_stage` batches input from UdpSockets into Packets, handing them
+//! off to whatever stage comes next. When given a set of forwarding sockets
+//! and a `BlockThread` to consult, it instead relays packets on to the
+//! current leader's TPU whenever this node isn't leader itself, rather than
+//! letting them flow into local processing.
+
+use blockthread::BlockThread;
+use packet::Packets;
+use service::Service;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, Builder, JoinHandle};
+use std::time::Duration;
+use streamer;
+use xpz_program_interface::pubkey::Pubkey;
+
+pub struct FetchStage {
+    thread_hdls: Vec<JoinHandle<()>>,
+}
+
+impl FetchStage {
+    /// Spawn one receiver thread per socket in `sockets`, all funneling
+    /// batches into a single channel. Every packet is handed straight to the
+    /// caller; there is no leader-forwarding.
+    pub fn new(sockets: Vec<Arc<UdpSocket>>, exit: Arc<AtomicBool>) -> (Self, Receiver<Arc<RwLock<Packets>>>) {
+        let (sender, receiver) = channel();
+        let thread_hdls = Self::spawn_receivers(sockets, exit, sender);
+        (FetchStage { thread_hdls }, receiver)
+    }
+
+    /// Like `new`, but consults `blockthread` on every batch: when this node
+    /// (identified by `my_id`) is the current leader the batch is handed to
+    /// the caller as usual, otherwise it's relayed out `forward_sockets` to
+    /// the leader's advertised TPU address instead of being handed off
+    /// locally. Which path a batch takes is decided fresh each time, so the
+    /// node follows leader rotation without any separate mode switch.
+    pub fn new_with_forwarder(
+        sockets: Vec<Arc<UdpSocket>>,
+        forward_sockets: Vec<Arc<UdpSocket>>,
+        my_id: Pubkey,
+        blockthread: Arc<RwLock<BlockThread>>,
+        exit: Arc<AtomicBool>,
+    ) -> (Self, Receiver<Arc<RwLock<Packets>>>) {
+        let (raw_sender, raw_receiver) = channel();
+        let mut thread_hdls = Self::spawn_receivers(sockets, exit.clone(), raw_sender);
+
+        let (packet_sender, packet_receiver) = channel();
+        let (forward_sender, forward_receiver) = channel();
+        thread_hdls.push(Self::spawn_router(
+            raw_receiver,
+            packet_sender,
+            forward_sender,
+            my_id,
+            blockthread.clone(),
+            exit.clone(),
+        ));
+        thread_hdls.extend(Self::spawn_forwarders(
+            forward_sockets,
+            forward_receiver,
+            blockthread,
+            exit,
+        ));
+
+        (FetchStage { thread_hdls }, packet_receiver)
+    }
+
+    fn spawn_receivers(
+        sockets: Vec<Arc<UdpSocket>>,
+        exit: Arc<AtomicBool>,
+        sender: Sender<Arc<RwLock<Packets>>>,
+    ) -> Vec<JoinHandle<()>> {
+        sockets
+            .into_iter()
+            .map(|socket| streamer::receiver(socket, exit.clone(), sender.clone(), "fetch_stage"))
+            .collect()
+    }
+
+    /// Decides, per incoming batch, whether this node is the leader right
+    /// now and routes the batch to `packet_sender` if so, `forward_sender`
+    /// otherwise.
+    fn spawn_router(
+        raw_receiver: Receiver<Arc<RwLock<Packets>>>,
+        packet_sender: Sender<Arc<RwLock<Packets>>>,
+        forward_sender: Sender<Arc<RwLock<Packets>>>,
+        my_id: Pubkey,
+        blockthread: Arc<RwLock<BlockThread>>,
+        exit: Arc<AtomicBool>,
+    ) -> JoinHandle<()> {
+        Builder::new()
+            .name("hypercube-fetch-stage-router".to_string())
+            .spawn(move || loop {
+                match raw_receiver.recv_timeout(Duration::from_millis(100)) {
+                    Ok(msgs) => {
+                        let am_leader = blockthread
+                            .read()
+                            .unwrap()
+                            .leader_data()
+                            .map(|leader| leader.id == my_id)
+                            .unwrap_or(false);
+                        let sent = if am_leader {
+                            packet_sender.send(msgs)
+                        } else {
+                            forward_sender.send(msgs)
+                        };
+                        if sent.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if exit.load(Ordering::Relaxed) {
+                    break;
+                }
+            }).unwrap()
+    }
+
+    /// Drains `forward_receiver` and relays each packet's bytes to the
+    /// current leader's TPU address over `forward_sockets`. Packets that
+    /// arrive while no leader is known are dropped rather than buffered.
+    fn spawn_forwarders(
+        forward_sockets: Vec<Arc<UdpSocket>>,
+        forward_receiver: Receiver<Arc<RwLock<Packets>>>,
+        blockthread: Arc<RwLock<BlockThread>>,
+        exit: Arc<AtomicBool>,
+    ) -> Vec<JoinHandle<()>> {
+        let forward_receiver = Arc::new(Mutex::new(forward_receiver));
+        forward_sockets
+            .into_iter()
+            .map(|socket| {
+                let forward_receiver = forward_receiver.clone();
+                let blockthread = blockthread.clone();
+                let exit = exit.clone();
+                Builder::new()
+                    .name("hypercube-fetch-stage-forward".to_string())
+                    .spawn(move || loop {
+                        let msgs = match forward_receiver.lock().unwrap().recv_timeout(Duration::from_millis(100)) {
+                            Ok(msgs) => msgs,
+                            Err(RecvTimeoutError::Timeout) => {
+                                if exit.load(Ordering::Relaxed) {
+                                    break;
+                                }
+                                continue;
+                            }
+                            Err(RecvTimeoutError::Disconnected) => break,
+                        };
+                        if let Some(leader) = blockthread.read().unwrap().leader_data() {
+                            let packets = msgs.read().unwrap();
+                            for packet in &packets.packets {
+                                let _ = socket.send_to(
+                                    &packet.data[..packet.meta.size],
+                                    leader.contact_info.tx_creator,
+                                );
+                            }
+                        }
+                        if exit.load(Ordering::Relaxed) {
+                            break;
+                        }
+                    }).unwrap()
+            }).collect()
+    }
+
+    pub fn close(&self) {}
+}
+
+impl Service for FetchStage {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        for thread_hdl in self.thread_hdls {
+            thread_hdl.join()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use chrono::prelude::*;
+use trx_out::{Payment, Witness};
+use xpz_program_interface::pubkey::Pubkey;
+
+/// A condition that gates a branch of a `FinPlan`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum Condition {
+    /// Unlocked once a timestamp witness at or after `dt`, asserted by
+    /// `pubkey`, has been applied.
+    Timestamp(DateTime<Utc>, Pubkey),
+
+    /// Unlocked once a signature witness from `pubkey` has been applied.
+    Signature(Pubkey),
+}
+
+impl Condition {
+    fn is_satisfied(&self, witness: &Witness, from: &Pubkey) -> bool {
+        match (self, witness) {
+            (Condition::Timestamp(dt, pubkey), Witness::Timestamp(now)) => {
+                now >= dt && from == pubkey
+            }
+            (Condition::Signature(pubkey), Witness::Signature(from)) => from == pubkey,
+            _ => false,
+        }
+    }
+}
+
+/// A pending financial plan. A transaction declares one of these against a
+/// contract account; applying witnesses reduces it to a final `Payment`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum FinPlan {
+    /// Unconditionally pay.
+    Pay(Payment),
+
+    /// Run the nested plan once `Condition` is satisfied.
+    After(Condition, Box<FinPlan>),
+
+    /// Run whichever side's `Condition` is satisfied first.
+    Or((Condition, Box<FinPlan>), (Condition, Box<FinPlan>)),
+
+    /// Pay once `threshold` distinct conditions have been matched,
+    /// or immediately pay out `cancel`'s branch if its condition is satisfied
+    /// first, letting the originator reclaim funds before then.
+    MultiSig {
+        conditions: Vec<Condition>,
+        threshold: usize,
+        payment: Payment,
+        cancel: Option<(Condition, Payment)>,
+    },
+}
+
+impl FinPlan {
+    /// Return true if every branch of the plan spends exactly `spendable_tokens`.
+    pub fn verify(&self, spendable_tokens: i64) -> bool {
+        match self {
+            FinPlan::Pay(payment) => payment.tokens == spendable_tokens,
+            FinPlan::After(_, plan) => plan.verify(spendable_tokens),
+            FinPlan::Or((_, plan0), (_, plan1)) => {
+                plan0.verify(spendable_tokens) && plan1.verify(spendable_tokens)
+            }
+            FinPlan::MultiSig {
+                payment, cancel, ..
+            } => {
+                payment.tokens == spendable_tokens
+                    && cancel
+                        .as_ref()
+                        .map_or(true, |(_, refund)| refund.tokens == spendable_tokens)
+            }
+        }
+    }
+
+    /// Apply a witness asserted by `from`, collapsing the plan toward its
+    /// final payment. Leaves the plan unchanged if nothing is satisfied.
+    pub fn apply_witness(&mut self, witness: &Witness, from: &Pubkey) {
+        let collapse_to = match self {
+            FinPlan::Pay(_) => None,
+            FinPlan::After(condition, plan) => {
+                if condition.is_satisfied(witness, from) {
+                    Some((**plan).clone())
+                } else {
+                    None
+                }
+            }
+            FinPlan::Or((condition0, plan0), (condition1, plan1)) => {
+                if condition0.is_satisfied(witness, from) {
+                    Some((**plan0).clone())
+                } else if condition1.is_satisfied(witness, from) {
+                    Some((**plan1).clone())
+                } else {
+                    None
+                }
+            }
+            FinPlan::MultiSig {
+                conditions,
+                threshold,
+                payment,
+                cancel,
+            } => {
+                let canceled = cancel
+                    .as_ref()
+                    .filter(|(condition, _)| condition.is_satisfied(witness, from))
+                    .map(|(_, refund)| refund.clone());
+                if let Some(idx) = conditions.iter().position(|c| c.is_satisfied(witness, from)) {
+                    conditions.remove(idx);
+                    *threshold -= 1;
+                }
+                canceled.or_else(|| {
+                    if *threshold == 0 {
+                        Some(payment.clone())
+                    } else {
+                        None
+                    }
+                }).map(FinPlan::Pay)
+            }
+        };
+        if let Some(plan) = collapse_to {
+            *self = plan;
+        }
+    }
+
+    /// Return the `Payment` once the plan has been reduced to `Pay`.
+    pub fn final_payment(&self) -> Option<Payment> {
+        match self {
+            FinPlan::Pay(payment) => Some(payment.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_witness_collapses_after() {
+        let to = Pubkey::new(&[1u8; 32]);
+        let from = Pubkey::new(&[2u8; 32]);
+        let payment = Payment { tokens: 42, to };
+        let mut plan = FinPlan::After(
+            Condition::Signature(from),
+            Box::new(FinPlan::Pay(payment.clone())),
+        );
+
+        plan.apply_witness(&Witness::Signature(Pubkey::new(&[3u8; 32])), &from);
+        assert_eq!(plan.final_payment(), None);
+
+        plan.apply_witness(&Witness::Signature(from), &from);
+        assert_eq!(plan.final_payment(), Some(payment));
+    }
+
+    #[test]
+    fn test_timestamp_witness_picks_or_branch() {
+        let to0 = Pubkey::new(&[1u8; 32]);
+        let to1 = Pubkey::new(&[2u8; 32]);
+        let dt_pubkey = Pubkey::new(&[3u8; 32]);
+        let witness_pubkey = Pubkey::new(&[4u8; 32]);
+        let dt = Utc.ymd(2018, 1, 1).and_hms(0, 0, 0);
+        let payment0 = Payment { tokens: 42, to: to0 };
+        let payment1 = Payment {
+            tokens: 42,
+            to: to1,
+        };
+        let mut plan = FinPlan::Or(
+            (
+                Condition::Timestamp(dt, dt_pubkey),
+                Box::new(FinPlan::Pay(payment0.clone())),
+            ),
+            (
+                Condition::Signature(witness_pubkey),
+                Box::new(FinPlan::Pay(payment1.clone())),
+            ),
+        );
+
+        plan.apply_witness(&Witness::Timestamp(dt), &dt_pubkey);
+        assert_eq!(plan.final_payment(), Some(payment0));
+    }
+
+    #[test]
+    fn test_multisig_collapses_after_threshold() {
+        let to = Pubkey::new(&[1u8; 32]);
+        let signer0 = Pubkey::new(&[2u8; 32]);
+        let signer1 = Pubkey::new(&[3u8; 32]);
+        let signer2 = Pubkey::new(&[4u8; 32]);
+        let payment = Payment { tokens: 42, to };
+        let mut plan = FinPlan::MultiSig {
+            conditions: vec![
+                Condition::Signature(signer0),
+                Condition::Signature(signer1),
+                Condition::Signature(signer2),
+            ],
+            threshold: 2,
+            payment: payment.clone(),
+            cancel: None,
+        };
+
+        assert!(plan.verify(42));
+
+        plan.apply_witness(&Witness::Signature(signer0), &signer0);
+        assert_eq!(plan.final_payment(), None);
+
+        // A repeated signature from the same signer must not count twice.
+        plan.apply_witness(&Witness::Signature(signer0), &signer0);
+        assert_eq!(plan.final_payment(), None);
+
+        plan.apply_witness(&Witness::Signature(signer1), &signer1);
+        assert_eq!(plan.final_payment(), Some(payment));
+    }
+
+    #[test]
+    fn test_multisig_cancel_wins_before_threshold() {
+        let to = Pubkey::new(&[1u8; 32]);
+        let originator = Pubkey::new(&[2u8; 32]);
+        let signer0 = Pubkey::new(&[3u8; 32]);
+        let signer1 = Pubkey::new(&[4u8; 32]);
+        let payment = Payment { tokens: 42, to };
+        let refund = Payment {
+            tokens: 42,
+            to: originator,
+        };
+        let mut plan = FinPlan::MultiSig {
+            conditions: vec![Condition::Signature(signer0), Condition::Signature(signer1)],
+            threshold: 2,
+            payment,
+            cancel: Some((Condition::Signature(originator), refund.clone())),
+        };
+
+        plan.apply_witness(&Witness::Signature(signer0), &signer0);
+        assert_eq!(plan.final_payment(), None);
+
+        plan.apply_witness(&Witness::Signature(originator), &originator);
+        assert_eq!(plan.final_payment(), Some(refund));
+    }
+}
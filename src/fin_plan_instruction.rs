@@ -1,4 +1,4 @@
-use fin_plan::FinPlan;
+use trx_out::Plan;
 use chrono::prelude::{DateTime, Utc};
 
 
@@ -6,26 +6,16 @@ use chrono::prelude::{DateTime, Utc};
 pub struct Contract {
 
     pub tokens: i64,
-    pub fin_plan: FinPlan,
+    pub plan: Plan,
 }
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
-pub struct Vote {
-    pub version: u64,
-    pub contact_info_version: u64,
-}
-
-
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub enum Instruction {
-    
+
     NewContract(Contract),
 
-    
+
     ApplyTimestamp(DateTime<Utc>),
 
-    
-    ApplySignature,
 
-    
-    NewVote(Vote),
+    ApplySignature,
 }
@@ -0,0 +1,96 @@
+use bincode::{deserialize, serialize};
+use fin_plan_instruction::Instruction;
+use transaction::Transaction;
+use trx_out::{Plan, Witness};
+use xpz_program_interface::account::Account;
+use xpz_program_interface::pubkey::Pubkey;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum FinPlanError {
+    InvalidInstruction,
+    InvalidPlan,
+    UninitializedContract,
+}
+
+/// On-chain state for a fin-plan contract account: a pending `Plan`, funded
+/// up front by a `SystemProgram::Move` into the same account, that pays out
+/// once a witness satisfies it. `NewContract` records the plan; `ApplyTimestamp`
+/// and `ApplySignature` each apply one witness and, the moment the plan
+/// resolves to a final payment, move its tokens straight out of the contract
+/// account rather than leaving them pending anywhere else.
+pub struct FinPlanState;
+
+impl FinPlanState {
+    pub fn id() -> Pubkey {
+        Pubkey::new(&[3u8; 32])
+    }
+
+    pub fn check_id(program_id: &Pubkey) -> bool {
+        *program_id == Self::id()
+    }
+
+    pub fn get_balance(account: &Account) -> i64 {
+        account.tokens
+    }
+
+    /// Locate and decode this transaction's instruction against the
+    /// fin-plan program, the same way `FinPlanTransaction::instruction`
+    /// does for callers building these transactions.
+    fn decode_instruction(tx: &Transaction) -> Result<Instruction, FinPlanError> {
+        let program_index = tx
+            .program_ids
+            .iter()
+            .position(Self::check_id)
+            .ok_or(FinPlanError::InvalidInstruction)?;
+        let ix = tx
+            .instructions
+            .iter()
+            .find(|ix| ix.program_ids_index as usize == program_index)
+            .ok_or(FinPlanError::InvalidInstruction)?;
+        deserialize(&ix.userdata).map_err(|_| FinPlanError::InvalidInstruction)
+    }
+
+    /// Collapse the pending plan stored on `accounts[1]` against `witness`,
+    /// asserted by `from`, paying `accounts[2]` out of the contract account
+    /// the moment it resolves, or re-storing the still-pending plan
+    /// otherwise.
+    fn apply_witness(
+        accounts: &mut [Account],
+        witness: &Witness,
+        from: &Pubkey,
+    ) -> Result<(), FinPlanError> {
+        let mut plan: Plan = deserialize(&accounts[1].userdata)
+            .map_err(|_| FinPlanError::UninitializedContract)?;
+        plan.apply_witness(witness, from);
+        if let Some(payment) = plan.final_payment() {
+            accounts[1].tokens -= payment.tokens;
+            accounts[1].userdata = Vec::new();
+            accounts[2].tokens += payment.tokens;
+        } else {
+            accounts[1].userdata = serialize(&plan).map_err(|_| FinPlanError::InvalidPlan)?;
+        }
+        Ok(())
+    }
+
+    /// Record a new contract's plan, or resolve an existing one against an
+    /// incoming witness, in place.
+    pub fn process_transaction(tx: &Transaction, accounts: &mut [Account]) -> Result<(), FinPlanError> {
+        match Self::decode_instruction(tx)? {
+            Instruction::NewContract(contract) => {
+                if !contract.plan.verify(contract.tokens) {
+                    return Err(FinPlanError::InvalidPlan);
+                }
+                accounts[0].program_id = Self::id();
+                accounts[0].userdata =
+                    serialize(&contract.plan).map_err(|_| FinPlanError::InvalidPlan)?;
+                Ok(())
+            }
+            Instruction::ApplyTimestamp(dt) => {
+                Self::apply_witness(accounts, &Witness::Timestamp(dt), tx.from())
+            }
+            Instruction::ApplySignature => {
+                Self::apply_witness(accounts, &Witness::Signature(*tx.from()), tx.from())
+            }
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use bincode::{deserialize, serialize};
+use builtin_pgm::SystemProgram;
 use fin_plan::{FinPlan, Condition};
-use fin_plan_instruction::{Contract, Instruction, Vote};
+use fin_plan_instruction::{Contract, Instruction};
 use fin_plan_program::FinPlanState;
 use chrono::prelude::*;
 use hash::Hash;
-use trx_out::Payment;
-use signature::Keypair;
+use trx_out::{Payment, Plan, PaymentPlan};
+use signature::{Keypair, KeypairUtil};
+use vote_program::{Vote, VoteInstruction, VoteState};
 use xpz_program_interface::pubkey::Pubkey;
 use transaction::Transaction;
 
@@ -58,6 +60,29 @@ pub trait FinPlanTransaction {
         last_id: Hash,
     ) -> Self;
 
+    fn fin_plan_new_multisig(
+        from_keypair: &Keypair,
+        to: Pubkey,
+        contract: Pubkey,
+        witnesses: &[Pubkey],
+        threshold: usize,
+        cancelable: Option<Pubkey>,
+        tokens: i64,
+        last_id: Hash,
+    ) -> Self;
+
+    fn fin_plan_new_on_date_when_signed(
+        from_keypair: &Keypair,
+        to: Pubkey,
+        contract: Pubkey,
+        dt: DateTime<Utc>,
+        dt_pubkey: Pubkey,
+        witness: Pubkey,
+        cancelable: Option<Pubkey>,
+        tokens: i64,
+        last_id: Hash,
+    ) -> Self;
+
     fn vote(&self) -> Option<(Pubkey, Vote, Hash)>;
 
     fn instruction(&self) -> Option<Instruction>;
@@ -65,6 +90,35 @@ pub trait FinPlanTransaction {
     fn verify_plan(&self) -> bool;
 }
 
+/// Build the pair of instructions that fund a freshly-allocated contract
+/// account and declare its spending plan, both signed as one atomic
+/// transaction: a system `Move` against `keys[0, 1]` followed by a
+/// `NewContract` against `keys[1]`.
+fn new_funded_contract(
+    from_keypair: &Keypair,
+    contract: Pubkey,
+    fin_plan: FinPlan,
+    tokens: i64,
+    fee: i64,
+    last_id: Hash,
+) -> Transaction {
+    let move_instruction = serialize(&SystemProgram::Move(tokens)).expect("serialize instruction");
+    let plan = Plan::FinPlan(fin_plan);
+    let new_contract_instruction = serialize(&Instruction::NewContract(Contract { plan, tokens }))
+        .expect("serialize instruction");
+    Transaction::new_with_instructions(
+        from_keypair,
+        &[contract],
+        last_id,
+        fee,
+        vec![SystemProgram::id(), FinPlanState::id()],
+        vec![
+            (0, move_instruction, vec![0, 1]),
+            (1, new_contract_instruction, vec![1]),
+        ],
+    )
+}
+
 impl FinPlanTransaction for Transaction {
     /// Create and sign a new Transaction. Used for unit-testing.
     fn fin_plan_new_taxed(
@@ -74,21 +128,13 @@ impl FinPlanTransaction for Transaction {
         fee: i64,
         last_id: Hash,
     ) -> Self {
+        let contract = Keypair::new().pubkey();
         let payment = Payment {
             tokens: tokens - fee,
             to,
         };
         let fin_plan = FinPlan::Pay(payment);
-        let instruction = Instruction::NewContract(Contract { fin_plan, tokens });
-        let userdata = serialize(&instruction).unwrap();
-        Self::new(
-            from_keypair,
-            &[to],
-            FinPlanState::id(),
-            userdata,
-            last_id,
-            fee,
-        )
+        new_funded_contract(from_keypair, contract, fin_plan, tokens, fee, last_id)
     }
 
     /// Create and sign a new Transaction. Used for unit-testing.
@@ -136,9 +182,9 @@ impl FinPlanTransaction for Transaction {
     }
 
     fn fin_plan_new_vote(from_keypair: &Keypair, vote: Vote, last_id: Hash, fee: i64) -> Self {
-        let instruction = Instruction::NewVote(vote);
+        let instruction = VoteInstruction::NewVote(vote);
         let userdata = serialize(&instruction).expect("serialize instruction");
-        Self::new(from_keypair, &[], FinPlanState::id(), userdata, last_id, fee)
+        Self::new(from_keypair, &[], VoteState::id(), userdata, last_id, fee)
     }
 
     /// Create and sign a postdated Transaction. Used for unit-testing.
@@ -154,22 +200,22 @@ impl FinPlanTransaction for Transaction {
     ) -> Self {
         let fin_plan = if let Some(from) = cancelable {
             FinPlan::Or(
-                (Condition::Timestamp(dt, dt_pubkey), Payment { tokens, to }),
-                (Condition::Signature(from), Payment { tokens, to: from }),
+                (
+                    Condition::Timestamp(dt, dt_pubkey),
+                    Box::new(FinPlan::Pay(Payment { tokens, to })),
+                ),
+                (
+                    Condition::Signature(from),
+                    Box::new(FinPlan::Pay(Payment { tokens, to: from })),
+                ),
             )
         } else {
-            FinPlan::After(Condition::Timestamp(dt, dt_pubkey), Payment { tokens, to })
+            FinPlan::After(
+                Condition::Timestamp(dt, dt_pubkey),
+                Box::new(FinPlan::Pay(Payment { tokens, to })),
+            )
         };
-        let instruction = Instruction::NewContract(Contract { fin_plan, tokens });
-        let userdata = serialize(&instruction).expect("serialize instruction");
-        Self::new(
-            from_keypair,
-            &[contract],
-            FinPlanState::id(),
-            userdata,
-            last_id,
-            0,
-        )
+        new_funded_contract(from_keypair, contract, fin_plan, tokens, 0, last_id)
     }
     /// Create and sign a multisig Transaction.
     fn fin_plan_new_when_signed(
@@ -183,34 +229,99 @@ impl FinPlanTransaction for Transaction {
     ) -> Self {
         let fin_plan = if let Some(from) = cancelable {
             FinPlan::Or(
-                (Condition::Signature(witness), Payment { tokens, to }),
-                (Condition::Signature(from), Payment { tokens, to: from }),
+                (
+                    Condition::Signature(witness),
+                    Box::new(FinPlan::Pay(Payment { tokens, to })),
+                ),
+                (
+                    Condition::Signature(from),
+                    Box::new(FinPlan::Pay(Payment { tokens, to: from })),
+                ),
             )
         } else {
-            FinPlan::After(Condition::Signature(witness), Payment { tokens, to })
+            FinPlan::After(
+                Condition::Signature(witness),
+                Box::new(FinPlan::Pay(Payment { tokens, to })),
+            )
         };
-        let instruction = Instruction::NewContract(Contract { fin_plan, tokens });
-        let userdata = serialize(&instruction).expect("serialize instruction");
-        Self::new(
-            from_keypair,
-            &[contract],
-            FinPlanState::id(),
-            userdata,
-            last_id,
-            0,
-        )
+        new_funded_contract(from_keypair, contract, fin_plan, tokens, 0, last_id)
     }
 
+    /// Create and sign an N-of-M threshold multisig Transaction.
+    fn fin_plan_new_multisig(
+        from_keypair: &Keypair,
+        to: Pubkey,
+        contract: Pubkey,
+        witnesses: &[Pubkey],
+        threshold: usize,
+        cancelable: Option<Pubkey>,
+        tokens: i64,
+        last_id: Hash,
+    ) -> Self {
+        let conditions = witnesses.iter().map(|w| Condition::Signature(*w)).collect();
+        let cancel = cancelable.map(|from| (Condition::Signature(from), Payment { tokens, to: from }));
+        let fin_plan = FinPlan::MultiSig {
+            conditions,
+            threshold,
+            payment: Payment { tokens, to },
+            cancel,
+        };
+        new_funded_contract(from_keypair, contract, fin_plan, tokens, 0, last_id)
+    }
+
+    /// Create and sign a Transaction that only pays once both a timestamp
+    /// witness at or after `dt` and a signature witness from `witness` have
+    /// arrived, by requiring both conditions via a 2-of-2 `MultiSig`.
+    fn fin_plan_new_on_date_when_signed(
+        from_keypair: &Keypair,
+        to: Pubkey,
+        contract: Pubkey,
+        dt: DateTime<Utc>,
+        dt_pubkey: Pubkey,
+        witness: Pubkey,
+        cancelable: Option<Pubkey>,
+        tokens: i64,
+        last_id: Hash,
+    ) -> Self {
+        let conditions = vec![Condition::Timestamp(dt, dt_pubkey), Condition::Signature(witness)];
+        let cancel = cancelable.map(|from| (Condition::Signature(from), Payment { tokens, to: from }));
+        let fin_plan = FinPlan::MultiSig {
+            conditions,
+            threshold: 2,
+            payment: Payment { tokens, to },
+            cancel,
+        };
+        new_funded_contract(from_keypair, contract, fin_plan, tokens, 0, last_id)
+    }
+
+    /// Recognize this transaction as a vote by its program id, and decode its
+    /// `Vote` payload directly, rather than risking a mis-parse through the
+    /// fin-plan `Instruction` enum.
     fn vote(&self) -> Option<(Pubkey, Vote, Hash)> {
-        if let Some(Instruction::NewVote(vote)) = self.instruction() {
+        let program_index = self.program_ids.iter().position(|id| VoteState::check_id(id))?;
+        let ix = self
+            .instructions
+            .iter()
+            .find(|ix| ix.program_ids_index as usize == program_index)?;
+        if let Ok(VoteInstruction::NewVote(vote)) = deserialize(&ix.userdata) {
             Some((*self.from(), vote, self.last_id))
         } else {
             None
         }
     }
 
+    /// Locate this transaction's instruction against the fin-plan program,
+    /// if any, among its (possibly several) instructions.
     fn instruction(&self) -> Option<Instruction> {
-        deserialize(&self.userdata).ok()
+        let program_index = self
+            .program_ids
+            .iter()
+            .position(|id| FinPlanState::check_id(id))?;
+        let ix = self
+            .instructions
+            .iter()
+            .find(|ix| ix.program_ids_index as usize == program_index)?;
+        deserialize(&ix.userdata).ok()
     }
 
     /// Verify only the payment plan.
@@ -218,7 +329,7 @@ impl FinPlanTransaction for Transaction {
         if let Some(Instruction::NewContract(contract)) = self.instruction() {
             self.fee >= 0
                 && self.fee <= contract.tokens
-                && contract.fin_plan.verify(contract.tokens - self.fee)
+                && contract.plan.verify(contract.tokens - self.fee)
         } else {
             true
         }
@@ -231,6 +342,22 @@ mod tests {
     use bincode::{deserialize, serialize};
     use signature::KeypairUtil;
 
+    /// Replace the fin-plan program's instruction payload on a transaction
+    /// built by `new_funded_contract`, for attack-simulation tests.
+    fn set_fin_plan_instruction(tx: &mut Transaction, instruction: &Instruction) {
+        let program_index = tx
+            .program_ids
+            .iter()
+            .position(|id| FinPlanState::check_id(id))
+            .unwrap();
+        let ix = tx
+            .instructions
+            .iter_mut()
+            .find(|ix| ix.program_ids_index as usize == program_index)
+            .unwrap();
+        ix.userdata = serialize(instruction).unwrap();
+    }
+
     #[test]
     fn test_claim() {
         let keypair = Keypair::new();
@@ -259,22 +386,24 @@ mod tests {
         assert!(!Transaction::fin_plan_new_taxed(&keypair0, pubkey1, 1, -1, zero).verify_plan());
     }
 
+    #[test]
+    fn test_multisig() {
+        let zero = Hash::default();
+        let keypair0 = Keypair::new();
+        let to = Keypair::new().pubkey();
+        let contract = Keypair::new().pubkey();
+        let witnesses = [Keypair::new().pubkey(), Keypair::new().pubkey()];
+        let tx = Transaction::fin_plan_new_multisig(
+            &keypair0, to, contract, &witnesses, 2, None, 42, zero,
+        );
+        assert!(tx.verify_plan());
+    }
+
     #[test]
     fn test_serialize_claim() {
-        let fin_plan = FinPlan::Pay(Payment {
-            tokens: 0,
-            to: Default::default(),
-        });
-        let instruction = Instruction::NewContract(Contract { fin_plan, tokens: 0 });
-        let userdata = serialize(&instruction).unwrap();
-        let claim0 = Transaction {
-            keys: vec![],
-            last_id: Default::default(),
-            signature: Default::default(),
-            program_id: Default::default(),
-            fee: 0,
-            userdata,
-        };
+        let keypair = Keypair::new();
+        let zero = Hash::default();
+        let claim0 = Transaction::fin_plan_new(&keypair, keypair.pubkey(), 0, zero);
         let buf = serialize(&claim0).unwrap();
         let claim1: Transaction = deserialize(&buf).unwrap();
         assert_eq!(claim1, claim0);
@@ -289,11 +418,11 @@ mod tests {
         let mut instruction = tx.instruction().unwrap();
         if let Instruction::NewContract(ref mut contract) = instruction {
             contract.tokens = 1_000_000; // <-- attack, part 1!
-            if let FinPlan::Pay(ref mut payment) = contract.fin_plan {
+            if let Plan::FinPlan(FinPlan::Pay(ref mut payment)) = contract.plan {
                 payment.tokens = contract.tokens; // <-- attack, part 2!
             }
         }
-        tx.userdata = serialize(&instruction).unwrap();
+        set_fin_plan_instruction(&mut tx, &instruction);
         assert!(tx.verify_plan());
         assert!(!tx.verify_signature());
     }
@@ -308,11 +437,11 @@ mod tests {
         let mut tx = Transaction::fin_plan_new(&keypair0, pubkey1, 42, zero);
         let mut instruction = tx.instruction();
         if let Some(Instruction::NewContract(ref mut contract)) = instruction {
-            if let FinPlan::Pay(ref mut payment) = contract.fin_plan {
+            if let Plan::FinPlan(FinPlan::Pay(ref mut payment)) = contract.plan {
                 payment.to = thief_keypair.pubkey(); // <-- attack!
             }
         }
-        tx.userdata = serialize(&instruction).unwrap();
+        set_fin_plan_instruction(&mut tx, &instruction.unwrap());
         assert!(tx.verify_plan());
         assert!(!tx.verify_signature());
     }
@@ -325,21 +454,21 @@ mod tests {
         let mut tx = Transaction::fin_plan_new(&keypair0, keypair1.pubkey(), 1, zero);
         let mut instruction = tx.instruction().unwrap();
         if let Instruction::NewContract(ref mut contract) = instruction {
-            if let FinPlan::Pay(ref mut payment) = contract.fin_plan {
+            if let Plan::FinPlan(FinPlan::Pay(ref mut payment)) = contract.plan {
                 payment.tokens = 2; // <-- attack!
             }
         }
-        tx.userdata = serialize(&instruction).unwrap();
+        set_fin_plan_instruction(&mut tx, &instruction);
         assert!(!tx.verify_plan());
 
         // Also, ensure all branchs of the plan spend all tokens
         let mut instruction = tx.instruction().unwrap();
         if let Instruction::NewContract(ref mut contract) = instruction {
-            if let FinPlan::Pay(ref mut payment) = contract.fin_plan {
+            if let Plan::FinPlan(FinPlan::Pay(ref mut payment)) = contract.plan {
                 payment.tokens = 0; // <-- whoops!
             }
         }
-        tx.userdata = serialize(&instruction).unwrap();
+        set_fin_plan_instruction(&mut tx, &instruction);
         assert!(!tx.verify_plan());
     }
 }
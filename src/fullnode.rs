@@ -1,23 +1,28 @@
 //! The `fullnode` module hosts all the fullnode microservices.
 
 use transaction_processor::TransactionProcessor;
-use broadcast_stage::BroadcastStage;
+use broadcast_stage::BroadcastStageType;
 use blockthread::{BlockThread, Node, NodeInfo};
 use drone::DRONE_PORT;
 use entry::Entry;
+use hash::Hash;
 use ledger::read_ledger;
 use ncp::Ncp;
 use rpc::{JsonRpcService, RPC_PORT};
-use rpu::Rpu;
+use rpc_pubsub::{PubSubService, RpcSubscriptions};
 use service::Service;
 use signature::{Keypair, KeypairUtil};
+use sigverify_stage::{DisabledSigVerifier, SigVerifier, TransactionSigVerifier};
+use storage_stage::StorageState;
 use xpz_program_interface::pubkey::Pubkey;
 use std::net::UdpSocket;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, RwLock};
 use std::thread::Result;
-use tx_creator::{TxCreator, TxCreatorReturnType};
+use tpu_forwarder::TpuForwarder;
+use tx_creator::{RotationSignal, TxCreator, TxCreatorReturnType};
 use tx_signer::{TxSigner, TxSignerReturnType};
 use untrusted::Input;
 use window;
@@ -29,19 +34,14 @@ pub enum NodeRole {
 
 pub struct LeaderServices {
     tx_creator: TxCreator,
-    broadcast_stage: BroadcastStage,
 }
 
 impl LeaderServices {
-    fn new(tx_creator: TxCreator, broadcast_stage: BroadcastStage) -> Self {
-        LeaderServices {
-            tx_creator,
-            broadcast_stage,
-        }
+    fn new(tx_creator: TxCreator) -> Self {
+        LeaderServices { tx_creator }
     }
 
     pub fn join(self) -> Result<Option<TxCreatorReturnType>> {
-        self.broadcast_stage.join()?;
         self.tx_creator.join()
     }
 
@@ -52,45 +52,59 @@ impl LeaderServices {
 
 pub struct ValidatorServices {
     tx_signer: TxSigner,
+    tpu_forwarder: TpuForwarder,
 }
 
 impl ValidatorServices {
-    fn new(tx_signer: TxSigner) -> Self {
-        ValidatorServices { tx_signer }
+    fn new(tx_signer: TxSigner, tpu_forwarder: TpuForwarder) -> Self {
+        ValidatorServices {
+            tx_signer,
+            tpu_forwarder,
+        }
     }
 
     pub fn join(self) -> Result<Option<TxSignerReturnType>> {
+        self.tpu_forwarder.close()?;
         self.tx_signer.join()
     }
 
     pub fn exit(&self) -> () {
+        self.tpu_forwarder.exit();
         self.tx_signer.exit()
     }
+
+    pub fn storage_state(&self) -> StorageState {
+        self.tx_signer.storage_state()
+    }
 }
 
 pub enum FullnodeReturnType {
-    LeaderRotation,
+    LeaderToValidatorRotation,
+    ValidatorToLeaderRotation,
 }
 
 pub struct Fullnode {
     pub node_role: Option<NodeRole>,
     keypair: Arc<Keypair>,
     exit: Arc<AtomicBool>,
-    rpu: Option<Rpu>,
     rpc_service: JsonRpcService,
+    pubsub_service: PubSubService,
     ncp: Ncp,
     transaction_processor: Arc<TransactionProcessor>,
     blockthread: Arc<RwLock<BlockThread>>,
     ledger_path: String,
     sigverify_disabled: bool,
+    shred_version: u16,
     shared_window: window::SharedWindow,
-    replicate_socket: Vec<UdpSocket>,
-    repair_socket: UdpSocket,
-    retransmit_socket: UdpSocket,
-    transaction_sockets: Vec<UdpSocket>,
-    broadcast_socket: UdpSocket,
-    requests_socket: UdpSocket,
-    respond_socket: UdpSocket,
+    replicate_socket: Vec<Arc<UdpSocket>>,
+    repair_socket: Arc<UdpSocket>,
+    retransmit_socket: Arc<UdpSocket>,
+    storage_socket: Arc<UdpSocket>,
+    transaction_sockets: Vec<Arc<UdpSocket>>,
+    tpu_forwards_sockets: Vec<Arc<UdpSocket>>,
+    broadcast_socket: Arc<UdpSocket>,
+    to_fullnode_sender: Sender<RotationSignal>,
+    rotation_receiver: Receiver<RotationSignal>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -122,6 +136,7 @@ impl Fullnode {
         keypair: Keypair,
         leader_addr: Option<SocketAddr>,
         sigverify_disabled: bool,
+        shred_version: u16,
         leader_rotation_interval: Option<u64>,
     ) -> Self {
         info!("creating transaction_processor...");
@@ -147,8 +162,10 @@ impl Fullnode {
             leader_info.as_ref(),
             ledger_path,
             sigverify_disabled,
+            shred_version,
             leader_rotation_interval,
             None,
+            None,
         );
 
         match leader_addr {
@@ -229,8 +246,10 @@ impl Fullnode {
         leader_info: Option<&NodeInfo>,
         ledger_path: &str,
         sigverify_disabled: bool,
+        shred_version: u16,
         leader_rotation_interval: Option<u64>,
         rpc_port: Option<u16>,
+        pubsub_port: Option<u16>,
     ) -> Self {
         if leader_info.is_none() {
             node.info.leader_id = node.info.id;
@@ -238,17 +257,20 @@ impl Fullnode {
         let exit = Arc::new(AtomicBool::new(false));
         let transaction_processor = Arc::new(transaction_processor);
 
-        let rpu = Some(Rpu::new(
-            &transaction_processor,
-            node.sockets
-                .requests
-                .try_clone()
-                .expect("Failed to clone requests socket"),
-            node.sockets
-                .respond
-                .try_clone()
-                .expect("Failed to clone respond socket"),
-        ));
+        // Own each socket exactly once as an `Arc`, so every stage that
+        // needs it (including across leader/validator rotations) just
+        // clones the `Arc` instead of risking a fallible `try_clone` that
+        // duplicates the underlying file descriptor.
+        let replicate_socket: Vec<Arc<UdpSocket>> =
+            node.sockets.replicate.into_iter().map(Arc::new).collect();
+        let repair_socket = Arc::new(node.sockets.repair);
+        let retransmit_socket = Arc::new(node.sockets.retransmit);
+        let storage_socket = Arc::new(node.sockets.storage);
+        let transaction_sockets: Vec<Arc<UdpSocket>> =
+            node.sockets.transaction.into_iter().map(Arc::new).collect();
+        let tpu_forwards_sockets: Vec<Arc<UdpSocket>> =
+            node.sockets.tpu_forwards.into_iter().map(Arc::new).collect();
+        let broadcast_socket = Arc::new(node.sockets.broadcast);
 
         // TODO: this code assumes this node is the leader
         let mut drone_addr = node.info.contact_info.tx_creator;
@@ -267,6 +289,17 @@ impl Fullnode {
             exit.clone(),
         );
 
+        // Same override convention as `rpc_port`, but defaulting to
+        // `rpc_addr.port() + 1` instead of a fixed well-known port, since a
+        // pubsub service only ever makes sense alongside its JSON-RPC
+        // counterpart.
+        let pubsub_addr = SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::from(0)),
+            pubsub_port.unwrap_or_else(|| rpc_addr.port() + 1),
+        );
+        let rpc_subscriptions = Arc::new(RpcSubscriptions::new(&transaction_processor));
+        let pubsub_service = PubSubService::new(&rpc_subscriptions, pubsub_addr);
+
         let window = window::new_window_from_entries(ledger_tail, entry_height, &node.info);
         let shared_window = Arc::new(RwLock::new(window));
 
@@ -284,6 +317,11 @@ impl Fullnode {
             exit.clone(),
         );
 
+        // Stages push a `RotationSignal` here the instant they detect the
+        // scheduled-leader boundary, so `handle_role_transition` can react
+        // without waiting on `Service::join` to unwind the outgoing stage.
+        let (to_fullnode_sender, rotation_receiver) = channel();
+
         let keypair = Arc::new(keypair);
         let node_role;
         match leader_info {
@@ -297,53 +335,46 @@ impl Fullnode {
                     entry_height,
                     blockthread.clone(),
                     shared_window.clone(),
-                    node.sockets
-                        .replicate
-                        .iter()
-                        .map(|s| s.try_clone().expect("Failed to clone replicate sockets"))
-                        .collect(),
-                    node.sockets
-                        .repair
-                        .try_clone()
-                        .expect("Failed to clone repair socket"),
-                    node.sockets
-                        .retransmit
-                        .try_clone()
-                        .expect("Failed to clone retransmit socket"),
+                    replicate_socket.clone(),
+                    repair_socket.clone(),
+                    retransmit_socket.clone(),
+                    storage_socket.clone(),
                     Some(ledger_path),
+                    sigverify_disabled,
+                    to_fullnode_sender.clone(),
                 );
-                let validator_state = ValidatorServices::new(tx_signer);
+                let tpu_forwarder = TpuForwarder::new(
+                    transaction_sockets.clone(),
+                    tpu_forwards_sockets.clone(),
+                    blockthread.clone(),
+                );
+                let validator_state = ValidatorServices::new(tx_signer, tpu_forwarder);
                 node_role = Some(NodeRole::Validator(validator_state));
             }
             None => {
                 // Start in leader mode.
-                let (tx_creator, entry_receiver, tx_creator_exit) = TxCreator::new(
+                let max_tick_height =
+                    Some(entry_height + blockthread.read().unwrap().leader_rotation_interval());
+                let (tx_creator, _tx_creator_exit) = TxCreator::new(
                     keypair.clone(),
                     &transaction_processor,
                     &blockthread,
+                    shared_window.clone(),
                     Default::default(),
-                    node.sockets
-                        .transaction
-                        .iter()
-                        .map(|s| s.try_clone().expect("Failed to clone transaction sockets"))
-                        .collect(),
+                    transaction_sockets.clone(),
+                    tpu_forwards_sockets.clone(),
+                    vec![broadcast_socket.clone()],
+                    shred_version,
+                    BroadcastStageType::Standard,
                     ledger_path,
-                    sigverify_disabled,
+                    Self::make_sigverifier(sigverify_disabled),
                     entry_height,
+                    max_tick_height,
+                    None,
+                    to_fullnode_sender.clone(),
                 );
 
-                let broadcast_stage = BroadcastStage::new(
-                    node.sockets
-                        .broadcast
-                        .try_clone()
-                        .expect("Failed to clone broadcast socket"),
-                    blockthread.clone(),
-                    shared_window.clone(),
-                    entry_height,
-                    entry_receiver,
-                    tx_creator_exit,
-                );
-                let leader_state = LeaderServices::new(tx_creator, broadcast_stage);
+                let leader_state = LeaderServices::new(tx_creator);
                 node_role = Some(NodeRole::Leader(leader_state));
             }
         }
@@ -354,122 +385,160 @@ impl Fullnode {
             shared_window,
             transaction_processor,
             sigverify_disabled,
-            rpu,
+            shred_version,
             ncp,
             rpc_service,
+            pubsub_service,
             node_role,
             ledger_path: ledger_path.to_owned(),
             exit,
-            replicate_socket: node.sockets.replicate,
-            repair_socket: node.sockets.repair,
-            retransmit_socket: node.sockets.retransmit,
-            transaction_sockets: node.sockets.transaction,
-            broadcast_socket: node.sockets.broadcast,
-            requests_socket: node.sockets.requests,
-            respond_socket: node.sockets.respond,
+            replicate_socket,
+            repair_socket,
+            retransmit_socket,
+            storage_socket,
+            transaction_sockets,
+            tpu_forwards_sockets,
+            broadcast_socket,
+            to_fullnode_sender,
+            rotation_receiver,
         }
     }
 
-    fn leader_to_validator(&mut self) -> Result<()> {
-        // TODO: We can avoid building the transaction_processor again once RecordStage is
-        // integrated with TransactionProcessoringStage
-        let (transaction_processor, entry_height, _) = Self::new_transaction_processor_from_ledger(&self.ledger_path);
-        self.transaction_processor = Arc::new(transaction_processor);
-
+    /// Tear down the leader stage and stand up a validator one at
+    /// `entry_height`, reusing the already-warm `transaction_processor`
+    /// instead of rebuilding it from the ledger on disk: the bank already
+    /// holds the processed state up to `entry_height`, so re-reading the
+    /// whole ledger here would only add an O(ledger) stall to every
+    /// rotation for no benefit.
+    fn leader_to_validator(&mut self, entry_height: u64) -> Result<()> {
         {
+            let transaction_processor = &self.transaction_processor;
             let mut wblockthread = self.blockthread.write().unwrap();
-            let scheduled_leader = wblockthread.get_scheduled_leader(entry_height);
-            match scheduled_leader {
-                //TODO: Handle the case where we don't know who the next
-                //scheduled leader is
-                None => (),
-                Some(leader_id) => wblockthread.set_leader(leader_id),
+            // `get_scheduled_leader` consults `BlockThread`'s `LeaderScheduler`,
+            // which may come back empty if no staked/active pubkey is known
+            // for this epoch yet; leave the old leader set in that case
+            // rather than guessing.
+            let scheduled_leader = wblockthread.get_scheduled_leader(entry_height, |pubkey| {
+                transaction_processor.get_balance(pubkey).max(0) as u64
+            });
+            if let Some(leader_id) = scheduled_leader {
+                wblockthread.set_leader(leader_id);
             }
         }
 
-        // Make a new RPU to serve requests out of the new transaction_processor we've created
-        // instead of the old one
-        if self.rpu.is_some() {
-            let old_rpu = self.rpu.take().unwrap();
-            old_rpu.close()?;
-            self.rpu = Some(Rpu::new(
-                &self.transaction_processor,
-                self.requests_socket
-                    .try_clone()
-                    .expect("Failed to clone requests socket"),
-                self.respond_socket
-                    .try_clone()
-                    .expect("Failed to clone respond socket"),
-            ));
-        }
+        self.spawn_validator(entry_height)
+    }
 
+    /// Stand up a `TxSigner` at `entry_height`. Shared by `leader_to_validator`
+    /// and by `handle_role_transition` when the schedule refuses to promote
+    /// this node, in which case it just keeps validating instead.
+    fn spawn_validator(&mut self, entry_height: u64) -> Result<()> {
+        // `rpc_service` and `transaction_processor` are untouched: rotation no
+        // longer rebuilds either of them, so there is nothing to restart here.
         let tx_signer = TxSigner::new(
             self.keypair.clone(),
             &self.transaction_processor,
             entry_height,
             self.blockthread.clone(),
             self.shared_window.clone(),
-            self.replicate_socket
-                .iter()
-                .map(|s| s.try_clone().expect("Failed to clone replicate sockets"))
-                .collect(),
-            self.repair_socket
-                .try_clone()
-                .expect("Failed to clone repair socket"),
-            self.retransmit_socket
-                .try_clone()
-                .expect("Failed to clone retransmit socket"),
+            self.replicate_socket.clone(),
+            self.repair_socket.clone(),
+            self.retransmit_socket.clone(),
+            self.storage_socket.clone(),
             Some(&self.ledger_path),
+            self.sigverify_disabled,
+            self.to_fullnode_sender.clone(),
         );
-        let validator_state = ValidatorServices::new(tx_signer);
+        let tpu_forwarder = TpuForwarder::new(
+            self.transaction_sockets.clone(),
+            self.tpu_forwards_sockets.clone(),
+            self.blockthread.clone(),
+        );
+        let validator_state = ValidatorServices::new(tx_signer, tpu_forwarder);
         self.node_role = Some(NodeRole::Validator(validator_state));
         Ok(())
     }
 
-    fn validator_to_leader(&mut self, entry_height: u64) {
+    fn validator_to_leader(&mut self, tick_height: u64, entry_height: u64, last_entry_id: Hash) {
+        info!(
+            "validator_to_leader: resuming PoH at tick_height {} entry_height {} last_entry_id {}",
+            tick_height, entry_height, last_entry_id
+        );
         self.blockthread.write().unwrap().set_leader(self.keypair.pubkey());
-        let (tx_creator, blob_receiver, tx_creator_exit) = TxCreator::new(
+        let max_tick_height =
+            Some(entry_height + self.blockthread.read().unwrap().leader_rotation_interval());
+        let (tx_creator, _tx_creator_exit) = TxCreator::new(
             self.keypair.clone(),
             &self.transaction_processor,
             &self.blockthread,
+            self.shared_window.clone(),
             Default::default(),
-            self.transaction_sockets
-                .iter()
-                .map(|s| s.try_clone().expect("Failed to clone transaction sockets"))
-                .collect(),
+            self.transaction_sockets.clone(),
+            self.tpu_forwards_sockets.clone(),
+            vec![self.broadcast_socket.clone()],
+            self.shred_version,
+            BroadcastStageType::Standard,
             &self.ledger_path,
-            self.sigverify_disabled,
+            Self::make_sigverifier(self.sigverify_disabled),
             entry_height,
+            max_tick_height,
+            None,
+            self.to_fullnode_sender.clone(),
         );
 
-        let broadcast_stage = BroadcastStage::new(
-            self.broadcast_socket
-                .try_clone()
-                .expect("Failed to clone broadcast socket"),
-            self.blockthread.clone(),
-            self.shared_window.clone(),
-            entry_height,
-            blob_receiver,
-            tx_creator_exit,
-        );
-        let leader_state = LeaderServices::new(tx_creator, broadcast_stage);
+        let leader_state = LeaderServices::new(tx_creator);
         self.node_role = Some(NodeRole::Leader(leader_state));
     }
 
     pub fn handle_role_transition(&mut self) -> Result<Option<FullnodeReturnType>> {
         let node_role = self.node_role.take();
         match node_role {
-            Some(NodeRole::Leader(leader_services)) => match leader_services.join()? {
-                Some(TxCreatorReturnType::LeaderRotation) => {
-                    self.leader_to_validator()?;
-                    Ok(Some(FullnodeReturnType::LeaderRotation))
+            Some(NodeRole::Leader(leader_services)) => {
+                // If the write stage already told us a rotation is due, or
+                // `TransactionProcessoringStage` hit its tick ceiling first,
+                // signal the leader stage to wind down immediately rather
+                // than waiting for it to hit the boundary and return that
+                // same information from a blocking `join()`.
+                let max_height_entry = match self.rotation_receiver.try_recv() {
+                    Ok(RotationSignal::LeaderRotation { .. }) => {
+                        leader_services.exit();
+                        None
+                    }
+                    Ok(RotationSignal::MaxHeightReached { entry_height }) => {
+                        leader_services.exit();
+                        Some(entry_height)
+                    }
+                    Err(_) => None,
+                };
+                match leader_services.join()? {
+                    Some(TxCreatorReturnType::LeaderRotation(entry_height)) => {
+                        self.leader_to_validator(entry_height)?;
+                        Ok(Some(FullnodeReturnType::LeaderToValidatorRotation))
+                    }
+                    None => if let Some(entry_height) = max_height_entry {
+                        self.leader_to_validator(entry_height)?;
+                        Ok(Some(FullnodeReturnType::LeaderToValidatorRotation))
+                    } else {
+                        Ok(None)
+                    },
                 }
-                _ => Ok(None),
-            },
+            }
             Some(NodeRole::Validator(validator_services)) => match validator_services.join()? {
-                Some(TxSignerReturnType::LeaderRotation(entry_height)) => {
-                    self.validator_to_leader(entry_height);
-                    Ok(Some(FullnodeReturnType::LeaderRotation))
+                Some(TxSignerReturnType::LeaderRotation(tick_height, entry_height, last_entry_id)) => {
+                    let transaction_processor = &self.transaction_processor;
+                    let scheduled_leader =
+                        self.blockthread.write().unwrap().get_scheduled_leader(entry_height, |pubkey| {
+                            transaction_processor.get_balance(pubkey).max(0) as u64
+                        });
+                    if scheduled_leader == Some(self.keypair.pubkey()) {
+                        self.validator_to_leader(tick_height, entry_height, last_entry_id);
+                        Ok(Some(FullnodeReturnType::ValidatorToLeaderRotation))
+                    } else {
+                        // The schedule doesn't name us as the next leader;
+                        // refuse the promotion and keep validating instead.
+                        self.spawn_validator(entry_height)?;
+                        Ok(None)
+                    }
                 }
                 _ => Ok(None),
             },
@@ -480,9 +549,6 @@ impl Fullnode {
     //used for notifying many nodes in parallel to exit
     pub fn exit(&self) {
         self.exit.store(true, Ordering::Relaxed);
-        if let Some(ref rpu) = self.rpu {
-            rpu.exit();
-        }
         match self.node_role {
             Some(NodeRole::Leader(ref leader_services)) => leader_services.exit(),
             Some(NodeRole::Validator(ref validator_services)) => validator_services.exit(),
@@ -495,8 +561,11 @@ impl Fullnode {
         self.join()
     }
 
-    // TODO: only used for testing, get rid of this once we have actual
-    // leader scheduling
+    /// Override the `LeaderScheduler`'s computed leader for the epoch
+    /// containing `entry_height`. Real rotation now comes from
+    /// `BlockThread`'s `LeaderScheduler`; this only exists so tests can pin
+    /// down a schedule without standing up a full staked/active peer set.
+    #[cfg(test)]
     pub fn set_scheduled_leader(&self, leader_id: Pubkey, entry_height: u64) {
         self.blockthread
             .write()
@@ -504,6 +573,29 @@ impl Fullnode {
             .set_scheduled_leader(entry_height, leader_id);
     }
 
+    /// Current replication proof state, if this node is validating. `None`
+    /// while leading, since only `TxSigner` runs a `StorageStage`.
+    pub fn storage_state(&self) -> Option<StorageState> {
+        match self.node_role {
+            Some(NodeRole::Validator(ref validator_services)) => {
+                Some(validator_services.storage_state())
+            }
+            _ => None,
+        }
+    }
+
+    /// Pick the `SigVerifier` TxCreator should verify incoming packets with.
+    /// `sigverify_disabled` is a coarse CLI/test knob; anyone needing a
+    /// different policy (GPU, rate-limited, sampling, ...) can construct
+    /// their own `Box<SigVerifier>` and pass it to `TxCreator::new` directly.
+    fn make_sigverifier(sigverify_disabled: bool) -> Box<SigVerifier> {
+        if sigverify_disabled {
+            Box::new(DisabledSigVerifier)
+        } else {
+            Box::new(TransactionSigVerifier)
+        }
+    }
+
     fn new_transaction_processor_from_ledger(ledger_path: &str) -> (TransactionProcessor, u64, Vec<Entry>) {
         let transaction_processor = TransactionProcessor::new_default(false);
         let entries = read_ledger(ledger_path, true).expect("opening ledger");
@@ -522,21 +614,19 @@ impl Service for Fullnode {
     type JoinReturnType = Option<FullnodeReturnType>;
 
     fn join(self) -> Result<Option<FullnodeReturnType>> {
-        if let Some(rpu) = self.rpu {
-            rpu.join()?;
-        }
         self.ncp.join()?;
         self.rpc_service.join()?;
+        self.pubsub_service.close();
 
         match self.node_role {
             Some(NodeRole::Validator(validator_service)) => {
-                if let Some(TxSignerReturnType::LeaderRotation(_)) = validator_service.join()? {
-                    return Ok(Some(FullnodeReturnType::LeaderRotation));
+                if let Some(TxSignerReturnType::LeaderRotation(_, _, _)) = validator_service.join()? {
+                    return Ok(Some(FullnodeReturnType::ValidatorToLeaderRotation));
                 }
             }
             Some(NodeRole::Leader(leader_service)) => {
-                if let Some(TxCreatorReturnType::LeaderRotation) = leader_service.join()? {
-                    return Ok(Some(FullnodeReturnType::LeaderRotation));
+                if let Some(TxCreatorReturnType::LeaderRotation(_)) = leader_service.join()? {
+                    return Ok(Some(FullnodeReturnType::LeaderToValidatorRotation));
                 }
             }
             _ => (),
@@ -549,10 +639,12 @@ impl Service for Fullnode {
 #[cfg(test)]
 mod tests {
     use transaction_processor::TransactionProcessor;
+    use bincode::{deserialize, serialize};
     use blockthread::Node;
     use fullnode::{Fullnode, FullnodeReturnType};
     use ledger::genesis;
     use packet::make_consecutive_blobs;
+    use repair_service::RepairRequest;
     use service::Service;
     use signature::{Keypair, KeypairUtil};
     use std::cmp;
@@ -560,7 +652,11 @@ mod tests {
     use std::net::UdpSocket;
     use std::sync::mpsc::channel;
     use std::sync::Arc;
+    use std::thread;
+    use std::thread::sleep;
+    use std::time::Duration;
     use streamer::responder;
+    use transaction::Transaction;
 
     #[test]
     fn validator_exit() {
@@ -578,8 +674,10 @@ mod tests {
             Some(&entry),
             &validator_ledger_path,
             false,
+            0,
             None,
             Some(0),
+            Some(0),
         );
         v.close().unwrap();
         remove_dir_all(validator_ledger_path).unwrap();
@@ -606,8 +704,10 @@ mod tests {
                     Some(&entry),
                     &validator_ledger_path,
                     false,
+                    0,
                     None,
                     Some(0),
+                    Some(0),
                 )
             }).collect();
 
@@ -644,6 +744,7 @@ mod tests {
             validator_keypair,
             Some(leader_ncp),
             false,
+            0,
             Some(leader_rotation_interval),
         );
 
@@ -695,7 +796,7 @@ mod tests {
 
         // Wait for validator to shut down tx_signer and restart tx_creator
         match validator.handle_role_transition().unwrap() {
-            Some(FullnodeReturnType::LeaderRotation) => (),
+            Some(FullnodeReturnType::ValidatorToLeaderRotation) => (),
             _ => panic!("Expected reason for exit to be leader rotation"),
         }
 
@@ -712,4 +813,244 @@ mod tests {
         validator.close().unwrap();
         remove_dir_all(&validator_ledger_path).unwrap();
     }
+
+    #[test]
+    fn test_leader_rotates_out_at_max_tick_height() {
+        // Start a bootstrap leader with a tiny rotation interval, so that a
+        // handful of transactions is enough to walk it past its max tick
+        // height.
+        let leader_rotation_interval = 2;
+        let leader_keypair = Keypair::new();
+        let leader_node = Node::new_localhost_with_pubkey(leader_keypair.pubkey());
+        let tx_creator_addr = leader_node.info.contact_info.tx_creator;
+        let (mint, leader_ledger_path) =
+            genesis("test_leader_rotates_out_at_max_tick_height", 10_000);
+        let mut leader = Fullnode::new(
+            leader_node,
+            &leader_ledger_path,
+            leader_keypair,
+            None,
+            false,
+            0,
+            Some(leader_rotation_interval),
+        );
+
+        // Feed it one more transaction than its max tick height allows.
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let last_id = mint.last_id();
+        for _ in 0..leader_rotation_interval + 1 {
+            let tx = Transaction::system_new(&mint.keypair(), Keypair::new().pubkey(), 1, last_id);
+            socket
+                .send_to(&serialize(&tx).unwrap(), tx_creator_addr)
+                .unwrap();
+        }
+        sleep(Duration::from_millis(500));
+
+        match leader.handle_role_transition().unwrap() {
+            Some(FullnodeReturnType::LeaderToValidatorRotation) => (),
+            _ => panic!("Expected reason for exit to be leader rotation"),
+        }
+
+        // Check the ledger rotated out at exactly the expected entry height.
+        let (_, entry_height, _) = Fullnode::new_transaction_processor_from_ledger(&leader_ledger_path);
+        assert_eq!(entry_height, leader_rotation_interval);
+
+        leader.close().unwrap();
+        remove_dir_all(&leader_ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_leader_validator_leader_rotation() {
+        // A single node oscillates leader -> validator -> leader, reusing
+        // the same sockets and ledger path across both transitions.
+        let leader_rotation_interval = 2;
+        let keypair = Keypair::new();
+        let node = Node::new_localhost_with_pubkey(keypair.pubkey());
+        let my_id = node.info.id;
+        let my_contact_info = node.info.contact_info.clone();
+        let (mint, ledger_path) = genesis("test_leader_validator_leader_rotation", 10_000);
+        let mut fullnode = Fullnode::new(
+            node,
+            &ledger_path,
+            keypair,
+            None,
+            false,
+            0,
+            Some(leader_rotation_interval),
+        );
+
+        // Phase 1: start as leader and walk it past its max tick height.
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        for _ in 0..leader_rotation_interval + 1 {
+            let tx = Transaction::system_new(&mint.keypair(), Keypair::new().pubkey(), 1, mint.last_id());
+            socket
+                .send_to(&serialize(&tx).unwrap(), my_contact_info.tx_creator)
+                .unwrap();
+        }
+        sleep(Duration::from_millis(500));
+
+        match fullnode.handle_role_transition().unwrap() {
+            Some(FullnodeReturnType::LeaderToValidatorRotation) => (),
+            _ => panic!("Expected leader to step down after its max tick height"),
+        }
+
+        let (_, entry_height_after_leader, ledger_tail) =
+            Fullnode::new_transaction_processor_from_ledger(&ledger_path);
+        assert_eq!(entry_height_after_leader, leader_rotation_interval);
+
+        // Phase 2: pin the schedule to name us leader again at the next
+        // epoch boundary, then have a mock peer carry us there with blobs.
+        fullnode.set_scheduled_leader(my_id, leader_rotation_interval);
+
+        let mock_leader_keypair = Keypair::new();
+        let mock_leader_node = Node::new_localhost_with_pubkey(mock_leader_keypair.pubkey());
+        let mock_leader_id = mock_leader_node.info.id;
+        let tip_id = ledger_tail
+            .last()
+            .expect("ledger tail should have at least one entry")
+            .id;
+
+        let t_responder = {
+            let (s_responder, r_responder) = channel();
+            let blob_sockets: Vec<Arc<UdpSocket>> = mock_leader_node
+                .sockets
+                .replicate
+                .into_iter()
+                .map(Arc::new)
+                .collect();
+            let t_responder = responder(
+                "test_leader_validator_leader_rotation",
+                blob_sockets[0].clone(),
+                r_responder,
+            );
+
+            let extra_blobs = cmp::max(leader_rotation_interval / 3, 1);
+            let total_blobs_to_send = leader_rotation_interval + extra_blobs;
+            let msgs = make_consecutive_blobs(
+                mock_leader_id,
+                total_blobs_to_send,
+                tip_id,
+                &my_contact_info.tx_signer,
+            ).into_iter()
+                .rev()
+                .collect();
+            s_responder.send(msgs).expect("send");
+            t_responder
+        };
+
+        match fullnode.handle_role_transition().unwrap() {
+            Some(FullnodeReturnType::ValidatorToLeaderRotation) => (),
+            _ => panic!("Expected promotion back to leader"),
+        }
+
+        let (_, entry_height_after_validator, _) =
+            Fullnode::new_transaction_processor_from_ledger(&ledger_path);
+        assert_eq!(entry_height_after_validator, leader_rotation_interval * 2);
+        assert!(entry_height_after_validator > entry_height_after_leader);
+
+        t_responder.join().expect("responder thread join");
+        fullnode.close().unwrap();
+        remove_dir_all(&ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_validator_repairs_missing_blobs() {
+        // Make a leader identity. Its NCP is only used as the validator's
+        // entry point; blob delivery and repair are both driven by hand
+        // below so we can withhold one blob and serve it back out only
+        // once the validator's repair service asks for it.
+        let leader_keypair = Keypair::new();
+        let leader_node = Node::new_localhost_with_pubkey(leader_keypair.pubkey());
+        let leader_id = leader_node.info.id;
+        let leader_ncp = leader_node.info.contact_info.ncp;
+        let leader_repair_socket = Arc::new(leader_node.sockets.repair);
+
+        // Start the validator node
+        let leader_rotation_interval = 10;
+        let (mint, validator_ledger_path) = genesis("test_validator_repairs_missing_blobs", 10_000);
+        let validator_keypair = Keypair::new();
+        let validator_node = Node::new_localhost_with_pubkey(validator_keypair.pubkey());
+        let validator_info = validator_node.info.clone();
+        let mut validator = Fullnode::new(
+            validator_node,
+            &validator_ledger_path,
+            validator_keypair,
+            Some(leader_ncp),
+            false,
+            0,
+            Some(leader_rotation_interval),
+        );
+
+        // Name the validator itself as the next leader, once it catches up.
+        validator.set_scheduled_leader(validator_info.id, 0);
+
+        // Build the blobs the leader would have broadcast, then withhold
+        // one interior blob so the validator's window has a gap only
+        // repair can fill.
+        let genesis_entries = mint.create_entries();
+        let last_id = genesis_entries
+            .last()
+            .expect("expected at least one genesis entry")
+            .id;
+        let tx_signer_address = &validator_info.contact_info.tx_signer;
+        let mut blobs = make_consecutive_blobs(
+            leader_id,
+            leader_rotation_interval,
+            last_id,
+            &tx_signer_address,
+        );
+        let missing_index = leader_rotation_interval / 2;
+        let missing_blob = blobs.remove(missing_index as usize);
+
+        let t_responder = {
+            let (s_responder, r_responder) = channel();
+            let blob_sockets: Vec<Arc<UdpSocket>> = leader_node
+                .sockets
+                .replicate
+                .into_iter()
+                .map(Arc::new)
+                .collect();
+            let t_responder = responder(
+                "test_validator_repairs_missing_blobs",
+                blob_sockets[0].clone(),
+                r_responder,
+            );
+            s_responder.send(blobs).expect("send");
+            t_responder
+        };
+
+        // Stand in for the leader's own `RepairService` responder: answer
+        // the one `WindowIndexRequest` we expect for `missing_index`.
+        let t_repair_responder = {
+            let leader_repair_socket = leader_repair_socket.clone();
+            thread::Builder::new()
+                .name("test_validator_repairs_missing_blobs-repair".to_string())
+                .spawn(move || {
+                    let mut buf = [0; 1024];
+                    if let Ok((size, from)) = leader_repair_socket.recv_from(&mut buf) {
+                        if let Ok(RepairRequest::WindowIndexRequest(index)) = deserialize(&buf[..size]) {
+                            if index == missing_index {
+                                let blob = missing_blob.read().unwrap();
+                                let _ = leader_repair_socket.send_to(&blob.data[..blob.meta.size], from);
+                            }
+                        }
+                    }
+                }).unwrap()
+        };
+
+        // The validator can only reach `leader_rotation_interval` once
+        // repair fills the gap we withheld above.
+        match validator.handle_role_transition().unwrap() {
+            Some(FullnodeReturnType::ValidatorToLeaderRotation) => (),
+            _ => panic!("Expected reason for exit to be leader rotation"),
+        }
+
+        let (_, entry_height, _) = Fullnode::new_transaction_processor_from_ledger(&validator_ledger_path);
+        assert_eq!(entry_height, leader_rotation_interval);
+
+        t_responder.join().expect("responder thread join");
+        t_repair_responder.join().expect("repair responder thread join");
+        validator.close().unwrap();
+        remove_dir_all(&validator_ledger_path).unwrap();
+    }
 }
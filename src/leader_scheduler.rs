@@ -0,0 +1,186 @@
+//! `LeaderScheduler` assigns one leader pubkey to each entry height within a
+//! `leader_rotation_interval`-sized epoch. In `LeaderSchedulerConfig::ActiveStake`
+//! mode the schedule is drawn, stake-weighted, from the set of pubkeys that
+//! have voted within `active_window` entries of the epoch boundary, so every
+//! node that has seen the same votes and the same stake balances arrives at
+//! the same schedule without any additional coordination. `Bootstrap` mode
+//! skips all of that and just repeats one fixed leader, which is all a
+//! single-node cluster (or a test standing up one fullnode with nobody else
+//! to vote) ever needs.
+
+use hash::hash;
+use std::collections::HashMap;
+use xpz_program_interface::pubkey::Pubkey;
+
+pub const DEFAULT_LEADER_ROTATION_INTERVAL: u64 = 100;
+
+/// Entries a pubkey's last vote can age before it drops out of the active set.
+pub const DEFAULT_ACTIVE_WINDOW: u64 = 1000;
+
+/// How `LeaderScheduler` derives each epoch's schedule.
+#[derive(Clone)]
+pub enum LeaderSchedulerConfig {
+    /// Always the same leader, ignoring the active set entirely.
+    Bootstrap(Pubkey),
+    /// Stake-weighted sampling from the active, voting set.
+    ActiveStake,
+}
+
+pub struct LeaderScheduler {
+    config: LeaderSchedulerConfig,
+    leader_rotation_interval: u64,
+    active_window: u64,
+
+    /// Height of the most recent vote seen from each pubkey, used to decide
+    /// who counts as active as of a given epoch boundary.
+    last_vote_height: HashMap<Pubkey, u64>,
+
+    /// Computed schedules, one leader per slot in the epoch, memoized so
+    /// repeated lookups against the same epoch (the common case, since
+    /// `get_scheduled_leader` is consulted on every entry) don't resample
+    /// the active set each time.
+    schedule_cache: HashMap<u64, Vec<Pubkey>>,
+
+    /// Test-only escape hatch: an explicitly assigned leader for an epoch,
+    /// checked before falling back to the computed schedule.
+    #[cfg(test)]
+    overrides: HashMap<u64, Pubkey>,
+}
+
+impl LeaderScheduler {
+    pub fn new(config: LeaderSchedulerConfig, leader_rotation_interval: u64) -> Self {
+        LeaderScheduler {
+            config,
+            leader_rotation_interval,
+            active_window: DEFAULT_ACTIVE_WINDOW,
+            last_vote_height: HashMap::new(),
+            schedule_cache: HashMap::new(),
+            #[cfg(test)]
+            overrides: HashMap::new(),
+        }
+    }
+
+    pub fn leader_rotation_interval(&self) -> u64 {
+        self.leader_rotation_interval
+    }
+
+    pub fn set_leader_rotation_interval(&mut self, leader_rotation_interval: u64) {
+        self.leader_rotation_interval = leader_rotation_interval;
+        self.schedule_cache.clear();
+    }
+
+    pub fn set_active_window(&mut self, active_window: u64) {
+        self.active_window = active_window;
+    }
+
+    pub fn epoch(&self, entry_height: u64) -> u64 {
+        entry_height / self.leader_rotation_interval
+    }
+
+    /// Record that `pubkey` voted as of `entry_height`. Meant to be called
+    /// once per vote transaction as entries are replayed, mirroring how
+    /// `replicate_stage` already folds `entries.votes()` into `BlockThread`.
+    pub fn push_vote(&mut self, pubkey: Pubkey, entry_height: u64) {
+        let last = self.last_vote_height.entry(pubkey).or_insert(0);
+        if entry_height > *last {
+            *last = entry_height;
+        }
+    }
+
+    /// Pubkeys whose latest vote is within `active_window` entries of `entry_height`.
+    fn active_pubkeys(&self, entry_height: u64) -> Vec<Pubkey> {
+        self.last_vote_height
+            .iter()
+            .filter(|&(_, &last)| entry_height.saturating_sub(last) <= self.active_window)
+            .map(|(pubkey, _)| *pubkey)
+            .collect()
+    }
+
+    /// Who leads at `entry_height`, computing (and caching) the whole
+    /// epoch's schedule the first time any height in it is asked for.
+    /// `stake` looks up a pubkey's current stake in the bank; only
+    /// consulted in `ActiveStake` mode, and only while building a fresh
+    /// schedule.
+    pub fn get_scheduled_leader<F>(&mut self, entry_height: u64, stake: F) -> Option<Pubkey>
+    where
+        F: Fn(&Pubkey) -> u64,
+    {
+        let epoch = self.epoch(entry_height);
+
+        #[cfg(test)]
+        {
+            if let Some(leader_id) = self.overrides.get(&epoch) {
+                return Some(*leader_id);
+            }
+        }
+
+        let slot = (entry_height % self.leader_rotation_interval) as usize;
+        if let Some(schedule) = self.schedule_cache.get(&epoch) {
+            return schedule.get(slot).cloned();
+        }
+
+        let schedule = self.generate_schedule(epoch, stake);
+        let leader_id = schedule.get(slot).cloned();
+        self.schedule_cache.insert(epoch, schedule);
+        leader_id
+    }
+
+    /// Produce one leader per slot in the epoch.
+    fn generate_schedule<F>(&self, epoch: u64, stake: F) -> Vec<Pubkey>
+    where
+        F: Fn(&Pubkey) -> u64,
+    {
+        match self.config {
+            LeaderSchedulerConfig::Bootstrap(leader_id) => {
+                vec![leader_id; self.leader_rotation_interval as usize]
+            }
+            LeaderSchedulerConfig::ActiveStake => {
+                let epoch_start = epoch * self.leader_rotation_interval;
+                let mut active: Vec<(Pubkey, u64)> = self
+                    .active_pubkeys(epoch_start)
+                    .into_iter()
+                    .map(|pubkey| {
+                        let staked = stake(&pubkey);
+                        (pubkey, staked)
+                    }).filter(|&(_, staked)| staked > 0)
+                    .collect();
+                if active.is_empty() {
+                    return Vec::new();
+                }
+                // `HashMap` iteration order isn't stable across processes;
+                // sort so every node builds the schedule from the same order.
+                active.sort_by_key(|&(pubkey, _)| pubkey);
+                let total_stake: u64 = active.iter().map(|&(_, staked)| staked).sum();
+
+                // Chain a hash forward one link per slot, the same way
+                // `storage_stage::chain_proof_hash` derives each sample's
+                // offset from the one before it: nobody can predict slot
+                // N's leader without having derived slot N-1's first.
+                let mut seed = hash(&epoch.to_le_bytes());
+                let mut schedule = Vec::with_capacity(self.leader_rotation_interval as usize);
+                for _ in 0..self.leader_rotation_interval {
+                    let roll = seed.as_ref().iter().fold(0u64, |acc, byte| {
+                        acc.wrapping_mul(256).wrapping_add(*byte as u64)
+                    }) % total_stake;
+                    let mut cumulative = 0u64;
+                    let leader_id = active
+                        .iter()
+                        .find(|&&(_, staked)| {
+                            cumulative += staked;
+                            roll < cumulative
+                        }).map(|&(pubkey, _)| pubkey)
+                        .unwrap_or_else(|| active.last().unwrap().0);
+                    schedule.push(leader_id);
+                    seed = hash(seed.as_ref());
+                }
+                schedule
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub fn set_scheduled_leader(&mut self, entry_height: u64, leader_id: Pubkey) {
+        let epoch = self.epoch(entry_height);
+        self.overrides.insert(epoch, leader_id);
+    }
+}
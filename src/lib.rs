@@ -3,6 +3,7 @@
 #![cfg_attr(feature = "unstable", feature(test))]
 #[macro_use]
 pub mod counter;
+pub mod accounts;
 pub mod transaction_processor;
 pub mod transaction_processoring_stage;
 pub mod blob_fetch_stage;
@@ -12,6 +13,7 @@ pub mod fin_plan_instruction;
 pub mod fin_plan_transaction;
 pub mod choose_gossip_peer_strategy;
 pub mod client;
+pub mod crds_filter;
 #[macro_use]
 pub mod blockthread;
 pub mod fin_plan_program;
@@ -24,31 +26,36 @@ pub mod erasure;
 pub mod fetch_stage;
 pub mod fullnode;
 pub mod hash;
+pub mod leader_scheduler;
 pub mod ledger;
+pub mod loader_program;
 pub mod logger;
+#[macro_use]
 pub mod metrics;
 pub mod mint;
 pub mod ncp;
 pub mod netutil;
+pub mod nonce_program;
 pub mod packet;
 pub mod trx_out;
 pub mod pod;
 pub mod pod_recorder;
+pub mod push_active_set;
+pub mod record_stage;
 pub mod recvmmsg;
+pub mod repair_service;
 pub mod replicate_stage;
 pub mod replicator;
-pub mod request;
-pub mod request_processor;
-pub mod request_stage;
 pub mod result;
 pub mod retransmit_stage;
 pub mod rpc;
-pub mod rpu;
+pub mod rpc_pubsub;
 pub mod service;
 pub mod signature;
 pub mod sigverify;
 pub mod sigverify_stage;
 pub mod storage_program;
+pub mod storage_stage;
 pub mod store_ledger_stage;
 pub mod streamer;
 pub mod builtin_pgm;
@@ -59,7 +66,10 @@ pub mod tictactoe_program;
 pub mod timing;
 pub mod tx_creator;
 pub mod transaction;
+pub mod tpu_forwarder;
 pub mod tx_signer;
+pub mod vote_listener_stage;
+pub mod vote_program;
 pub mod vote_stage;
 pub mod qtc;
 pub mod window;
@@ -80,6 +90,7 @@ extern crate libloading;
 #[macro_use]
 extern crate log;
 extern crate nix;
+extern crate parking_lot;
 extern crate pnet_datalink;
 extern crate rayon;
 extern crate reqwest;
@@ -96,6 +107,8 @@ extern crate xpz_jsonrpc_core as jsonrpc_core;
 extern crate xpz_jsonrpc_http_server as jsonrpc_http_server;
 #[macro_use]
 extern crate xpz_jsonrpc_macros as jsonrpc_macros;
+extern crate xpz_jsonrpc_pubsub as jsonrpc_pubsub;
+extern crate xpz_jsonrpc_ws_server as jsonrpc_ws_server;
 extern crate xpz_program_interface;
 extern crate sys_info;
 extern crate tokio;
@@ -106,5 +119,4 @@ extern crate untrusted;
 #[macro_use]
 extern crate matches;
 
-extern crate influx_db_client;
 extern crate rand;
@@ -0,0 +1,29 @@
+use xpz_program_interface::pubkey::Pubkey;
+
+/// Chunk size used by `Deploy` when splitting a program's bytes into `Write`
+/// instructions.
+pub const CHUNK_SIZE: usize = 256;
+
+/// Instruction data understood by the on-chain loader: a program's bytes are
+/// uploaded in fixed-size chunks, each at an `offset` into the account's
+/// data, then the account is marked executable once fully written.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum LoaderInstruction {
+    Write { offset: u32, bytes: Vec<u8> },
+    Finalize,
+}
+
+pub struct LoaderState;
+
+impl LoaderState {
+    pub fn id() -> Pubkey {
+        Pubkey::new(&[
+            9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9,
+            9, 9, 9,
+        ])
+    }
+
+    pub fn check_id(program_id: &Pubkey) -> bool {
+        *program_id == Self::id()
+    }
+}
@@ -1,69 +1,362 @@
- 
 
-use influx_db_client as influxdb;
+
+use log::Level;
+use reqwest;
+use std::collections::{HashMap, VecDeque};
 use std::env;
-use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
 use std::sync::{Arc, Barrier, Mutex, Once, ONCE_INIT};
 use std::thread;
 use std::time::{Duration, Instant};
 use sys_info::hostname;
 use timing;
 
+/// Caps the agent's command channel so a stalled writer can't let an
+/// unbounded backlog of points accumulate in memory; once full, `submit`
+/// drops the point (counted in `dropped_points`) rather than blocking the
+/// hot path that's trying to report a metric.
+const INFLUX_WRITER_MAX_BUFFER: usize = 4096;
+
+/// How long a point that failed to write is retried before it's given up
+/// on, to keep a sustained InfluxDB outage from growing the retry buffer
+/// without bound.
+const DROP_DEADLINE: Duration = Duration::from_secs(30);
+
+static DROPPED_POINTS: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of points discarded so far, either because the command
+/// channel was full or because a point aged out of, or was evicted from a
+/// full, retry buffer.
+pub fn dropped_points() -> usize {
+    DROPPED_POINTS.load(Ordering::Relaxed)
+}
+
+static SKIPPED_VALUES: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of non-finite (`NaN`/infinite) field values dropped so far
+/// during serialization; InfluxDB has no representation for either, so
+/// these are omitted rather than sent in a batch that would be rejected.
+pub fn skipped_values() -> usize {
+    SKIPPED_VALUES.load(Ordering::Relaxed)
+}
+
+/// A single InfluxDB line-protocol field or tag value.
+#[derive(Debug, Clone)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+/// A measurement plus its tags/fields/timestamp, serialized to InfluxDB
+/// line protocol by the writer rather than handed to a client library.
+#[derive(Debug, Clone)]
+pub struct Point {
+    pub measurement: String,
+    pub tags: Vec<(String, Value)>,
+    pub fields: Vec<(String, Value)>,
+    pub timestamp: Option<i64>,
+}
+
+impl Point {
+    pub fn new(measurement: &str) -> Self {
+        Point {
+            measurement: measurement.to_string(),
+            tags: vec![],
+            fields: vec![],
+            timestamp: None,
+        }
+    }
+
+    pub fn add_tag(&mut self, key: &str, value: Value) -> &mut Self {
+        self.tags.push((key.to_string(), value));
+        self
+    }
+
+    pub fn add_field(&mut self, key: &str, value: Value) -> &mut Self {
+        self.fields.push((key.to_string(), value));
+        self
+    }
+}
+
+fn escape_key(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(' ', "\\ ")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+}
+
+fn escape_string_field(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Formats a field's value for line protocol, or `None` if it can't be
+/// represented: InfluxDB rejects `NaN` and has no representation for
+/// infinities, so non-finite floats are skipped rather than sent.
+fn format_field_value(value: &Value) -> Option<String> {
+    match *value {
+        Value::String(ref s) => Some(format!("\"{}\"", escape_string_field(s))),
+        Value::Integer(i) => Some(format!("{}i", i)),
+        Value::Float(f) => if f.is_finite() { Some(f.to_string()) } else { None },
+        Value::Boolean(b) => Some(b.to_string()),
+    }
+}
+
+fn format_tag_value(value: &Value) -> String {
+    match *value {
+        Value::String(ref s) => escape_key(s),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Boolean(b) => b.to_string(),
+    }
+}
+
+/// Coerces a numeric expression into an integer field value at compile
+/// time. Only implemented for actual integer types, so a stray non-numeric
+/// expression (a bool, a function name, ...) fails to compile instead of
+/// silently passing through an `as i64` cast.
+pub trait AsI64 {
+    fn as_i64(self) -> i64;
+}
+
+macro_rules! impl_as_i64 {
+    ($($t:ty),*) => {
+        $(impl AsI64 for $t {
+            fn as_i64(self) -> i64 { self as i64 }
+        })*
+    };
+}
+impl_as_i64!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+/// Coerces a numeric expression into a float field value at compile time;
+/// see `AsI64` for the rationale.
+pub trait AsF64 {
+    fn as_f64(self) -> f64;
+}
+
+macro_rules! impl_as_f64 {
+    ($($t:ty),*) => {
+        $(impl AsF64 for $t {
+            fn as_f64(self) -> f64 { self as f64 }
+        })*
+    };
+}
+impl_as_f64!(f32, f64);
+
+/// Build and submit a `Point` in one line, instead of hand-chaining
+/// `Point::new(...).add_tag(...).add_field(...)` at every call site.
+/// Clauses may appear in any order after the measurement name:
+///
+///   measure!("vote_stage-peer_count", int["total_peers"; ids.len()], tag["id"; id]);
+///
+/// - `tag[key; expr]`    — a string tag, `expr` coerced via `ToString`
+/// - `int[key; expr]`    — an integer field, `expr` coerced via `AsI64`
+/// - `float[key; expr]`  — a float field, `expr` coerced via `AsF64`
+/// - `string[key; expr]` — a string field, `expr` coerced via `ToString`
+/// - `bool[key; expr]`   — a boolean field
+/// - `time[expr]`        — overrides the point's timestamp (`AsI64`)
+#[macro_export]
+macro_rules! measure {
+    ($name:expr) => {{
+        $crate::metrics::submit($crate::metrics::Point::new($name), None);
+    }};
+    ($name:expr, $($clauses:tt)*) => {{
+        let mut point = $crate::metrics::Point::new($name);
+        measure!(@apply point, $($clauses)*);
+        $crate::metrics::submit(point, None);
+    }};
+    (@apply $point:ident,) => {};
+    (@apply $point:ident) => {};
+    (@apply $point:ident, tag[$key:expr; $val:expr]) => {
+        $point.add_tag($key, $crate::metrics::Value::String(($val).to_string()));
+    };
+    (@apply $point:ident, tag[$key:expr; $val:expr], $($rest:tt)*) => {
+        $point.add_tag($key, $crate::metrics::Value::String(($val).to_string()));
+        measure!(@apply $point, $($rest)*);
+    };
+    (@apply $point:ident, int[$key:expr; $val:expr]) => {
+        $point.add_field($key, $crate::metrics::Value::Integer($crate::metrics::AsI64::as_i64($val)));
+    };
+    (@apply $point:ident, int[$key:expr; $val:expr], $($rest:tt)*) => {
+        $point.add_field($key, $crate::metrics::Value::Integer($crate::metrics::AsI64::as_i64($val)));
+        measure!(@apply $point, $($rest)*);
+    };
+    (@apply $point:ident, float[$key:expr; $val:expr]) => {
+        $point.add_field($key, $crate::metrics::Value::Float($crate::metrics::AsF64::as_f64($val)));
+    };
+    (@apply $point:ident, float[$key:expr; $val:expr], $($rest:tt)*) => {
+        $point.add_field($key, $crate::metrics::Value::Float($crate::metrics::AsF64::as_f64($val)));
+        measure!(@apply $point, $($rest)*);
+    };
+    (@apply $point:ident, string[$key:expr; $val:expr]) => {
+        $point.add_field($key, $crate::metrics::Value::String(($val).to_string()));
+    };
+    (@apply $point:ident, string[$key:expr; $val:expr], $($rest:tt)*) => {
+        $point.add_field($key, $crate::metrics::Value::String(($val).to_string()));
+        measure!(@apply $point, $($rest)*);
+    };
+    (@apply $point:ident, bool[$key:expr; $val:expr]) => {
+        $point.add_field($key, $crate::metrics::Value::Boolean($val));
+    };
+    (@apply $point:ident, bool[$key:expr; $val:expr], $($rest:tt)*) => {
+        $point.add_field($key, $crate::metrics::Value::Boolean($val));
+        measure!(@apply $point, $($rest)*);
+    };
+    (@apply $point:ident, time[$val:expr]) => {
+        $point.timestamp = Some($crate::metrics::AsI64::as_i64($val));
+    };
+    (@apply $point:ident, time[$val:expr], $($rest:tt)*) => {
+        $point.timestamp = Some($crate::metrics::AsI64::as_i64($val));
+        measure!(@apply $point, $($rest)*);
+    };
+}
+
+/// Render a `Point` as a single InfluxDB line-protocol line, skipping any
+/// non-finite float fields along the way (see `format_field_value`). If
+/// every field ends up skipped, the whole point is dropped (`None`) rather
+/// than writing a fieldless line, which InfluxDB would reject anyway; this
+/// way one bad ratio can't sink an otherwise-healthy batch write.
+fn serialize_point(point: &Point) -> Option<String> {
+    let mut line = escape_key(&point.measurement);
+    for &(ref key, ref value) in &point.tags {
+        line.push_str(&format!(",{}={}", escape_key(key), format_tag_value(value)));
+    }
+    line.push(' ');
+
+    let mut skipped = 0;
+    let fields: Vec<String> = point
+        .fields
+        .iter()
+        .filter_map(|&(ref key, ref value)| match format_field_value(value) {
+            Some(formatted) => Some(format!("{}={}", escape_key(key), formatted)),
+            None => {
+                skipped += 1;
+                None
+            }
+        })
+        .collect();
+
+    if skipped > 0 {
+        SKIPPED_VALUES.fetch_add(skipped, Ordering::Relaxed);
+        debug!(
+            "dropped {} non-finite field value(s) from point \"{}\"",
+            skipped, point.measurement
+        );
+        if fields.is_empty() {
+            debug!(
+                "dropping point \"{}\": no fields left after skipping non-finite values",
+                point.measurement
+            );
+            return None;
+        }
+    }
+
+    line.push_str(&fields.join(","));
+    if let Some(timestamp) = point.timestamp {
+        line.push(' ');
+        line.push_str(&timestamp.to_string());
+    }
+    Some(line)
+}
+
+/// A cheap-to-submit counter sample. Unlike a full `Point`, many
+/// `CounterPoint`s for the same `name` falling in the same interval bucket
+/// are summed into a single aggregated point before ever reaching the
+/// writer, so hot-path call sites (packets processed, signatures verified,
+/// ...) can increment freely without flooding InfluxDB with one point per
+/// event.
+#[derive(Debug, Clone)]
+pub struct CounterPoint {
+    pub name: &'static str,
+    pub count: i64,
+    pub timestamp: i64,
+}
+
+impl CounterPoint {
+    pub fn new(name: &'static str) -> Self {
+        CounterPoint {
+            name,
+            count: 0,
+            timestamp: 0,
+        }
+    }
+}
+
 #[derive(Debug)]
 enum MetricsCommand {
-    Submit(influxdb::Point),
+    Submit(Point),
+    SubmitCounter(CounterPoint, Level, Duration),
     Flush(Arc<Barrier>),
 }
 
 struct MetricsAgent {
-    sender: Sender<MetricsCommand>,
+    sender: SyncSender<MetricsCommand>,
 }
 
 trait MetricsWriter {
- 
-    fn write(&self, points: Vec<influxdb::Point>);
+    /// Attempt to write `points`, returning whether the write succeeded.
+    fn write(&self, points: Vec<Point>) -> bool;
 }
 
 struct InfluxDbMetricsWriter {
-    client: Option<influxdb::Client>,
+    write_url: String,
+    username: String,
+    password: String,
+    client: reqwest::Client,
 }
 
 impl InfluxDbMetricsWriter {
     fn new() -> Self {
-        InfluxDbMetricsWriter {
-            client: Self::build_client(),
-        }
-    }
-
-    fn build_client() -> Option<influxdb::Client> {
         let host = env::var("INFLUX_HOST")
             .unwrap_or_else(|_| "https://metrics.hypercube-lab.org:8086".to_string());
         let db = env::var("INFLUX_DATABASE").unwrap_or_else(|_| "scratch".to_string());
         let username = env::var("INFLUX_USERNAME").unwrap_or_else(|_| "scratch_writer".to_string());
         let password = env::var("INFLUX_PASSWORD").unwrap_or_else(|_| "topsecret".to_string());
+        let write_url = format!("{}/write?db={}&precision=ms", host, db);
 
-        debug!("InfluxDB host={} db={} username={}", host, db, username);
-        let mut client = influxdb::Client::new_with_option(host, db, None)
-            .set_authentication(username, password);
-
-        client.set_read_timeout(1 /*second*/);
-        client.set_write_timeout(1 /*second*/);
+        debug!("InfluxDB write_url={} username={}", write_url, username);
 
-        debug!("InfluxDB version: {:?}", client.get_version());
-        Some(client)
+        InfluxDbMetricsWriter {
+            write_url,
+            username,
+            password,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(1))
+                .build()
+                .unwrap_or_else(|_| reqwest::Client::new()),
+        }
     }
 }
 
 impl MetricsWriter for InfluxDbMetricsWriter {
-    fn write(&self, points: Vec<influxdb::Point>) {
-        if let Some(ref client) = self.client {
-            debug!("submitting {} points", points.len());
-            if let Err(err) = client.write_points(
-                influxdb::Points { point: points },
-                Some(influxdb::Precision::Milliseconds),
-                None,
-            ) {
+    fn write(&self, points: Vec<Point>) -> bool {
+        if points.is_empty() {
+            return true;
+        }
+        debug!("submitting {} points", points.len());
+        let lines: Vec<String> = points.iter().filter_map(serialize_point).collect();
+        if lines.is_empty() {
+            return true;
+        }
+        let body = lines.join("\n");
+
+        match self
+            .client
+            .post(self.write_url.as_str())
+            .basic_auth(self.username.clone(), Some(self.password.clone()))
+            .body(body)
+            .send()
+        {
+            Ok(ref response) if response.status().is_success() => true,
+            Ok(response) => {
+                debug!("InfluxDbMetricsWriter write failed: {:?}", response.status());
+                false
+            }
+            Err(err) => {
                 debug!("InfluxDbMetricsWriter write error: {:?}", err);
+                false
             }
         }
     }
@@ -80,7 +373,7 @@ impl Default for MetricsAgent {
 
 impl MetricsAgent {
     fn new(writer: Arc<MetricsWriter + Send + Sync>, write_frequency: Duration) -> Self {
-        let (sender, receiver) = channel::<MetricsCommand>();
+        let (sender, receiver) = sync_channel::<MetricsCommand>(INFLUX_WRITER_MAX_BUFFER);
         thread::spawn(move || Self::run(&receiver, &writer, write_frequency));
         MetricsAgent { sender }
     }
@@ -93,23 +386,43 @@ impl MetricsAgent {
         trace!("run: enter");
         let mut last_write_time = Instant::now();
         let mut points = Vec::new();
+        let mut counters: HashMap<(&'static str, u64), CounterPoint> = HashMap::new();
+        let mut pending: VecDeque<(Instant, Point)> = VecDeque::new();
 
         loop {
             match receiver.recv_timeout(write_frequency / 2) {
                 Ok(cmd) => match cmd {
                     MetricsCommand::Flush(barrier) => {
                         debug!("metrics_thread: flush");
-                        if !points.is_empty() {
-                            writer.write(points);
-                            points = Vec::new();
-                            last_write_time = Instant::now();
-                        }
+                        Self::drain_counters(&mut counters, &mut points);
+                        Self::write_with_retry(writer, &mut pending, &mut points, Instant::now());
+                        last_write_time = Instant::now();
                         barrier.wait();
                     }
                     MetricsCommand::Submit(point) => {
                         debug!("run: submit {:?}", point);
                         points.push(point);
                     }
+                    MetricsCommand::SubmitCounter(counter, level, interval) => {
+                        if log_enabled!(level) {
+                            log!(
+                                level,
+                                "COUNTER:{{\"name\": \"{}\", \"count\": {}}}",
+                                counter.name,
+                                counter.count
+                            );
+                        }
+                        let bucket =
+                            counter.timestamp as u64 / timing::duration_as_ms(&interval).max(1);
+                        let entry = counters
+                            .entry((counter.name, bucket))
+                            .or_insert_with(|| CounterPoint {
+                                name: counter.name,
+                                count: 0,
+                                timestamp: counter.timestamp,
+                            });
+                        entry.count += counter.count;
+                    }
                 },
                 Err(RecvTimeoutError::Timeout) => {
                     trace!("run: receive timeout");
@@ -121,32 +434,105 @@ impl MetricsAgent {
             }
 
             let now = Instant::now();
-            if now.duration_since(last_write_time) >= write_frequency && !points.is_empty() {
-                debug!("run: writing {} points", points.len());
-                writer.write(points);
-                points = Vec::new();
+            if now.duration_since(last_write_time) >= write_frequency {
+                Self::drain_counters(&mut counters, &mut points);
+                Self::write_with_retry(writer, &mut pending, &mut points, now);
                 last_write_time = now;
             }
         }
         trace!("run: exit");
     }
 
-    pub fn submit(&self, mut point: influxdb::Point) {
+    /// Turn every accumulated `CounterPoint` bucket into a single aggregated
+    /// `Point` and append it to `points`, leaving `counters` empty.
+    fn drain_counters(
+        counters: &mut HashMap<(&'static str, u64), CounterPoint>,
+        points: &mut Vec<Point>,
+    ) {
+        for (_, counter) in counters.drain() {
+            let mut point = Point::new(counter.name)
+                .add_field("count", Value::Integer(counter.count))
+                .to_owned();
+            point.timestamp = Some(counter.timestamp);
+            points.push(point);
+        }
+    }
+
+    /// Write any retry-pending points ahead of the fresh `points`, as one
+    /// batch. On success, both are cleared. On failure, everything not
+    /// past `DROP_DEADLINE` is kept in `pending` for the next cycle (with
+    /// the oldest points dropped first if that would exceed
+    /// `INFLUX_WRITER_MAX_BUFFER`), and the rest are counted as dropped.
+    fn write_with_retry(
+        writer: &Arc<MetricsWriter + Send + Sync>,
+        pending: &mut VecDeque<(Instant, Point)>,
+        points: &mut Vec<Point>,
+        now: Instant,
+    ) {
+        let retried = pending.len();
+        let mut batch: Vec<(Instant, Point)> = pending.drain(..).collect();
+        for point in points.drain(..) {
+            batch.push((now, point));
+        }
+        if batch.is_empty() {
+            return;
+        }
+
+        let to_write: Vec<Point> = batch.iter().map(|&(_, ref point)| point.clone()).collect();
+        debug!("run: writing {} points ({} retried)", to_write.len(), retried);
+        if writer.write(to_write) {
+            return;
+        }
+
+        for (failed_at, point) in batch {
+            if now.duration_since(failed_at) > DROP_DEADLINE {
+                DROPPED_POINTS.fetch_add(1, Ordering::Relaxed);
+            } else {
+                pending.push_back((failed_at, point));
+            }
+        }
+        while pending.len() > INFLUX_WRITER_MAX_BUFFER {
+            pending.pop_front();
+            DROPPED_POINTS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn submit(&self, mut point: Point) {
         if point.timestamp.is_none() {
             point.timestamp = Some(timing::timestamp() as i64);
         }
         debug!("Submitting point: {:?}", point);
-        self.sender.send(MetricsCommand::Submit(point)).unwrap();
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(MetricsCommand::Submit(point)) {
+            DROPPED_POINTS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn submit_counter(&self, mut counter: CounterPoint, level: Level, interval: Duration) {
+        if counter.timestamp == 0 {
+            counter.timestamp = timing::timestamp() as i64;
+        }
+        debug!("Submitting counter: {:?}", counter);
+        if let Err(TrySendError::Full(_)) = self
+            .sender
+            .try_send(MetricsCommand::SubmitCounter(counter, level, interval))
+        {
+            DROPPED_POINTS.fetch_add(1, Ordering::Relaxed);
+        }
     }
 
     pub fn flush(&self) {
         debug!("Flush");
         let barrier = Arc::new(Barrier::new(2));
-        self.sender
+        // A full channel would make this indistinguishable from an
+        // ordinary dropped submission, so let a flush block briefly rather
+        // than silently skip flushing whatever's already queued.
+        if self
+            .sender
             .send(MetricsCommand::Flush(Arc::clone(&barrier)))
-            .unwrap();
-
-        barrier.wait();
+            .is_ok()
+        {
+            barrier.wait();
+        }
     }
 }
 
@@ -168,20 +554,71 @@ fn get_singleton_agent() -> Arc<Mutex<MetricsAgent>> {
     }
 }
 
- 
-pub fn submit(point: influxdb::Point) {
+
+/// Submit a point. `level` gates it against the process's log filter (via
+/// `RUST_LOG`), so verbose instrumentation can be left in production code
+/// and only dialed up when diagnosing an issue; pass `None` for points
+/// that should always go through (panics, one-off state transitions).
+///
+/// When `level` is given and `METRICS_SAMPLE_RATE` is set above 1, only a
+/// `1/rate` fraction of the points that pass the level check are actually
+/// kept. A kept point's `count` field (if it has one) is scaled up by the
+/// rate so sums stay unbiased, and a `sample_rate` field is added so other
+/// downstream queries can de-bias themselves too.
+pub fn submit(mut point: Point, level: Option<Level>) {
+    if let Some(level) = level {
+        if !log_enabled!(level) {
+            return;
+        }
+
+        let rate = metrics_sample_rate();
+        if rate > 1 {
+            if SAMPLE_COUNTER.fetch_add(1, Ordering::Relaxed) % rate != 0 {
+                return;
+            }
+            for &mut (ref key, ref mut value) in &mut point.fields {
+                if key == "count" {
+                    if let Value::Integer(count) = *value {
+                        *value = Value::Integer(count * rate as i64);
+                    }
+                }
+            }
+            point.add_field("sample_rate", Value::Integer(rate as i64));
+        }
+    }
+
     let agent_mutex = get_singleton_agent();
     let agent = agent_mutex.lock().unwrap();
     agent.submit(point);
 }
 
- 
+/// 1-in-N counter for the sampling decision in `submit`.
+static SAMPLE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn metrics_sample_rate() -> usize {
+    env::var("METRICS_SAMPLE_RATE")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|&rate| rate > 0)
+        .unwrap_or(1)
+}
+
+
 pub fn flush() {
     let agent_mutex = get_singleton_agent();
     let agent = agent_mutex.lock().unwrap();
     agent.flush();
 }
- 
+
+/// Submit a counter sample that will be summed with others of the same
+/// `name` falling in the same `interval` bucket, and emitted as a single
+/// aggregated point instead of one point per call.
+pub fn submit_counter(counter: CounterPoint, level: Level, interval: Duration) {
+    let agent_mutex = get_singleton_agent();
+    let agent = agent_mutex.lock().unwrap();
+    agent.submit_counter(counter, level, interval);
+}
+
 pub fn set_panic_hook(program: &'static str) {
     use std::panic;
     use std::sync::{Once, ONCE_INIT};
@@ -191,40 +628,37 @@ pub fn set_panic_hook(program: &'static str) {
         panic::set_hook(Box::new(move |ono| {
             default_hook(ono);
             submit(
-                influxdb::Point::new("panic")
-                    .add_tag("program", influxdb::Value::String(program.to_string()))
+                Point::new("panic")
+                    .add_tag("program", Value::String(program.to_string()))
                     .add_tag(
                         "thread",
-                        influxdb::Value::String(
-                            thread::current().name().unwrap_or("?").to_string(),
-                        ),
+                        Value::String(thread::current().name().unwrap_or("?").to_string()),
                     )
                     // The 'one' field exists to give Kapacitor Alerts a numerical value
                     // to filter on
-                    .add_field("one", influxdb::Value::Integer(1))
+                    .add_field("one", Value::Integer(1))
                     .add_field(
                         "message",
-                        influxdb::Value::String(
+                        Value::String(
                             // TODO: use ono.message() when it becomes stable
                             ono.to_string(),
                         ),
                     )
                     .add_field(
                         "location",
-                        influxdb::Value::String(match ono.location() {
+                        Value::String(match ono.location() {
                             Some(location) => location.to_string(),
                             None => "?".to_string(),
                         }),
                     )
                     .add_field(
                         "host",
-                        influxdb::Value::String(
-                            hostname().unwrap_or_else(|_| "?".to_string())
-                        ),
+                        Value::String(hostname().unwrap_or_else(|_| "?".to_string())),
                     )
                     .to_owned(),
+                None,
             );
-           
+
             flush();
         }));
     });
@@ -238,22 +672,44 @@ mod test {
 
     struct MockMetricsWriter {
         points_written: AtomicUsize,
+        points_attempted: AtomicUsize,
+        fail_writes: bool,
     }
     impl MockMetricsWriter {
         fn new() -> Self {
             MockMetricsWriter {
                 points_written: AtomicUsize::new(0),
+                points_attempted: AtomicUsize::new(0),
+                fail_writes: false,
             }
         }
 
+        fn failing() -> Self {
+            MockMetricsWriter {
+                points_written: AtomicUsize::new(0),
+                points_attempted: AtomicUsize::new(0),
+                fail_writes: true,
+            }
+        }
+
+        fn points_attempted(&self) -> usize {
+            self.points_attempted.load(Ordering::SeqCst)
+        }
+
         fn points_written(&self) -> usize {
             return self.points_written.load(Ordering::SeqCst);
         }
     }
 
     impl MetricsWriter for MockMetricsWriter {
-        fn write(&self, points: Vec<influxdb::Point>) {
+        fn write(&self, points: Vec<Point>) -> bool {
             assert!(!points.is_empty());
+            self.points_attempted
+                .fetch_add(points.len(), Ordering::SeqCst);
+
+            if self.fail_writes {
+                return false;
+            }
 
             self.points_written
                 .fetch_add(points.len(), Ordering::SeqCst);
@@ -263,6 +719,7 @@ mod test {
                 points.len(),
                 self.points_written.load(Ordering::SeqCst)
             );
+            true
         }
     }
 
@@ -272,7 +729,7 @@ mod test {
         let agent = MetricsAgent::new(writer.clone(), Duration::from_secs(10));
 
         for i in 0..42 {
-            agent.submit(influxdb::Point::new(&format!("measurement {}", i)));
+            agent.submit(Point::new(&format!("measurement {}", i)));
         }
 
         agent.flush();
@@ -284,7 +741,7 @@ mod test {
         let writer = Arc::new(MockMetricsWriter::new());
         let agent = MetricsAgent::new(writer.clone(), Duration::from_millis(100));
 
-        agent.submit(influxdb::Point::new("point 1"));
+        agent.submit(Point::new("point 1"));
         thread::sleep(Duration::from_secs(2));
         assert_eq!(writer.points_written(), 1);
     }
@@ -302,7 +759,7 @@ mod test {
         //
         let mut threads = Vec::new();
         for i in 0..42 {
-            let point = influxdb::Point::new(&format!("measurement {}", i));
+            let point = Point::new(&format!("measurement {}", i));
             let agent = Arc::clone(&agent);
             threads.push(thread::spawn(move || {
                 agent.lock().unwrap().submit(point);
@@ -317,31 +774,137 @@ mod test {
         assert_eq!(writer.points_written(), 42);
     }
 
+    #[test]
+    fn test_submit_counter_aggregates_within_bucket() {
+        let writer = Arc::new(MockMetricsWriter::new());
+        let agent = MetricsAgent::new(writer.clone(), Duration::from_secs(10));
+
+        let interval = Duration::from_secs(60);
+        for _ in 0..42 {
+            let mut counter = CounterPoint::new("packets");
+            counter.count = 1;
+            counter.timestamp = 1000;
+            agent.submit_counter(counter, Level::Info, interval);
+        }
+
+        agent.flush();
+        // 42 submissions in the same bucket collapse into a single point.
+        assert_eq!(writer.points_written(), 1);
+    }
+
+    #[test]
+    fn test_submit_counter_separates_buckets() {
+        let writer = Arc::new(MockMetricsWriter::new());
+        let agent = MetricsAgent::new(writer.clone(), Duration::from_secs(10));
+
+        let interval = Duration::from_secs(60);
+        let mut first = CounterPoint::new("packets");
+        first.count = 1;
+        first.timestamp = 1000;
+        agent.submit_counter(first, Level::Info, interval);
+
+        let mut second = CounterPoint::new("packets");
+        second.count = 1;
+        second.timestamp = 1000 + interval.as_secs() as i64 * 1000;
+        agent.submit_counter(second, Level::Info, interval);
+
+        agent.flush();
+        assert_eq!(writer.points_written(), 2);
+    }
+
     #[test]
     fn test_flush_before_drop() {
         let writer = Arc::new(MockMetricsWriter::new());
         {
             let agent = MetricsAgent::new(writer.clone(), Duration::from_secs(9999999));
-            agent.submit(influxdb::Point::new("point 1"));
+            agent.submit(Point::new("point 1"));
         }
 
         assert_eq!(writer.points_written(), 1);
     }
 
+    #[test]
+    fn test_failed_write_is_retried() {
+        let writer = Arc::new(MockMetricsWriter::failing());
+        let agent = MetricsAgent::new(writer.clone(), Duration::from_secs(10));
+
+        agent.submit(Point::new("point 1"));
+        agent.flush();
+        assert_eq!(writer.points_written(), 0);
+        assert_eq!(writer.points_attempted(), 1);
+
+        // The failed point stays pending and is offered again on the next flush.
+        agent.flush();
+        assert_eq!(writer.points_written(), 0);
+        assert_eq!(writer.points_attempted(), 2);
+    }
+
     #[test]
     fn test_live_submit() {
         let agent = MetricsAgent::default();
 
-        let point = influxdb::Point::new("live_submit_test")
-            .add_tag("test", influxdb::Value::Boolean(true))
-            .add_field(
-                "random_bool",
-                influxdb::Value::Boolean(random::<u8>() < 128),
-            ).add_field(
-                "random_int",
-                influxdb::Value::Integer(random::<u8>() as i64),
-            ).to_owned();
+        let point = Point::new("live_submit_test")
+            .add_tag("test", Value::Boolean(true))
+            .add_field("random_bool", Value::Boolean(random::<u8>() < 128))
+            .add_field("random_int", Value::Integer(random::<u8>() as i64))
+            .to_owned();
         agent.submit(point);
     }
 
+    #[test]
+    fn test_measure_macro() {
+        let count = 42usize;
+        let ratio = 0.5f32;
+        measure!(
+            "measure_macro_test",
+            tag["id"; "test-node"],
+            int["count"; count],
+            float["ratio"; ratio],
+            string["status"; "ok"],
+            bool["healthy"; true],
+            time[1_234_567i64]
+        );
+    }
+
+    #[test]
+    fn test_metrics_sample_rate_defaults_to_one() {
+        env::remove_var("METRICS_SAMPLE_RATE");
+        assert_eq!(metrics_sample_rate(), 1);
+    }
+
+    #[test]
+    fn test_metrics_sample_rate_reads_env_var() {
+        env::set_var("METRICS_SAMPLE_RATE", "10");
+        assert_eq!(metrics_sample_rate(), 10);
+        env::remove_var("METRICS_SAMPLE_RATE");
+    }
+
+    #[test]
+    fn test_serialize_point_skips_non_finite_fields() {
+        let mut point = Point::new("ratios");
+        point.add_field("good", Value::Float(1.5));
+        point.add_field("nan", Value::Float(::std::f64::NAN));
+        point.add_field("inf", Value::Float(::std::f64::INFINITY));
+        let line = serialize_point(&point).unwrap();
+        assert!(line.contains("good=1.5"));
+        assert!(!line.contains("nan"));
+        assert!(!line.contains("inf"));
+    }
+
+    #[test]
+    fn test_serialize_point_drops_point_with_no_finite_fields() {
+        let mut point = Point::new("ratios");
+        point.add_field("nan", Value::Float(::std::f64::NAN));
+        assert!(serialize_point(&point).is_none());
+    }
+
+    #[test]
+    fn test_metrics_sample_rate_ignores_invalid_values() {
+        env::set_var("METRICS_SAMPLE_RATE", "not-a-number");
+        assert_eq!(metrics_sample_rate(), 1);
+        env::set_var("METRICS_SAMPLE_RATE", "0");
+        assert_eq!(metrics_sample_rate(), 1);
+        env::remove_var("METRICS_SAMPLE_RATE");
+    }
+
 }
@@ -0,0 +1,84 @@
+use bincode::{deserialize, serialize};
+use hash::Hash;
+use transaction::Transaction;
+use xpz_program_interface::account::Account;
+use xpz_program_interface::pubkey::Pubkey;
+
+/// On-chain state for a durable nonce account: a stashed blockhash that can
+/// stand in for a live `last_id` so an offline-signed transaction doesn't
+/// expire, and the pubkey authorized to advance it to a fresh value.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct NonceAccount {
+    pub authority: Pubkey,
+    pub blockhash: Hash,
+}
+
+/// Instructions understood by the nonce program.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum NonceInstruction {
+    InitializeNonceAccount(Pubkey),
+    AdvanceNonceAccount,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum NonceError {
+    InvalidInstruction,
+    InvalidAccountData,
+    Unauthorized,
+}
+
+pub struct NonceState;
+
+impl NonceState {
+    pub fn id() -> Pubkey {
+        Pubkey::new(&[
+            9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9,
+            9, 9, 9,
+        ])
+    }
+
+    pub fn check_id(program_id: &Pubkey) -> bool {
+        *program_id == Self::id()
+    }
+
+    fn decode_instruction(tx: &Transaction) -> Result<NonceInstruction, NonceError> {
+        let program_index = tx
+            .program_ids
+            .iter()
+            .position(Self::check_id)
+            .ok_or(NonceError::InvalidInstruction)?;
+        let ix = tx
+            .instructions
+            .iter()
+            .find(|ix| ix.program_ids_index as usize == program_index)
+            .ok_or(NonceError::InvalidInstruction)?;
+        deserialize(&ix.userdata).map_err(|_| NonceError::InvalidInstruction)
+    }
+
+    /// Initialize or advance the stashed blockhash of the nonce account named
+    /// last among `accounts`, in place. Advancing is refused unless the
+    /// transaction is signed by the account's stored authority.
+    pub fn process_transaction(tx: &Transaction, accounts: &mut [Account]) -> Result<(), NonceError> {
+        let instruction = Self::decode_instruction(tx)?;
+        let account = accounts.last_mut().ok_or(NonceError::InvalidAccountData)?;
+        match instruction {
+            NonceInstruction::InitializeNonceAccount(authority) => {
+                account.userdata = serialize(&NonceAccount {
+                    authority,
+                    blockhash: tx.last_id,
+                }).map_err(|_| NonceError::InvalidAccountData)?;
+            }
+            NonceInstruction::AdvanceNonceAccount => {
+                let mut nonce_account: NonceAccount = deserialize(&account.userdata)
+                    .map_err(|_| NonceError::InvalidAccountData)?;
+                if *tx.from() != nonce_account.authority {
+                    return Err(NonceError::Unauthorized);
+                }
+                nonce_account.blockhash = tx.last_id;
+                account.userdata =
+                    serialize(&nonce_account).map_err(|_| NonceError::InvalidAccountData)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,23 +1,66 @@
- 
+
 
 use chrono::prelude::*;
+use fin_plan::FinPlan;
 use xpz_program_interface::pubkey::Pubkey;
 
- 
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub enum Witness {
- 
+
     Timestamp(DateTime<Utc>),
 
- 
-    Signature,
+
+    Signature(Pubkey),
 }
 
- 
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Payment {
- 
+
     pub tokens: i64,
- 
+
     pub to: Pubkey,
 }
+
+/// A condition-gated spending plan, expressed in some plan language, that
+/// ultimately resolves to a single `Payment`.
+pub trait PaymentPlan {
+    /// Return the `Payment` if the plan has unconditionally resolved.
+    fn final_payment(&self) -> Option<Payment>;
+
+    /// Return true if the plan spends exactly `spendable_tokens`.
+    fn verify(&self, spendable_tokens: i64) -> bool;
+
+    /// Apply a witness asserted by `from`, potentially reducing the plan
+    /// toward its final payment.
+    fn apply_witness(&mut self, witness: &Witness, from: &Pubkey);
+}
+
+/// Wraps the plan languages a `Contract` can be declared in. `FinPlan` is the
+/// only one today, but this leaves room to add others without touching the
+/// transaction layer.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum Plan {
+    FinPlan(FinPlan),
+}
+
+impl PaymentPlan for Plan {
+    fn final_payment(&self) -> Option<Payment> {
+        match self {
+            Plan::FinPlan(fin_plan) => fin_plan.final_payment(),
+        }
+    }
+
+    fn verify(&self, spendable_tokens: i64) -> bool {
+        match self {
+            Plan::FinPlan(fin_plan) => fin_plan.verify(spendable_tokens),
+        }
+    }
+
+    fn apply_witness(&mut self, witness: &Witness, from: &Pubkey) {
+        match self {
+            Plan::FinPlan(fin_plan) => fin_plan.apply_witness(witness, from),
+        }
+    }
+}
@@ -40,19 +40,14 @@ impl Pod {
         }
     }
 
-    // emissions of Ticks (i.e. PodEntries without a mixin) allows
-    //  validators to parallelize the work of catching up
-    pub fn tick(&mut self) -> PodEntry {
+    /// Advance the hash chain by one step without producing an entry. A run
+    /// of ticks with no intervening `record()` leaves no trace in the
+    /// ledger; their hash count simply accumulates in `num_hashes` until
+    /// whichever `record()` eventually closes them out folds it in, so PoH
+    /// never emits an empty entry just because no work happened to land on
+    /// a tick boundary.
+    pub fn tick(&mut self) {
         self.hash();
-
-        let num_hashes = self.num_hashes;
-        self.num_hashes = 0;
-
-        PodEntry {
-            num_hashes,
-            id: self.last_hash,
-            mixin: None,
-        }
     }
 }
 
@@ -4,10 +4,15 @@
 use transaction_processor::TransactionProcessor;
 use entry::Entry;
 use hash::Hash;
+use parking_lot::{Mutex, MutexGuard};
 use pod::Pod;
 use result::Result;
+use service::Service;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::thread::{self, sleep, Builder, JoinHandle};
+use std::time::{Duration, Instant};
 use transaction::Transaction;
 
 #[derive(Clone)]
@@ -18,45 +23,117 @@ pub struct PodRecorder {
 }
 
 impl PodRecorder {
- 
+
     pub fn new(transaction_processor: Arc<TransactionProcessor>, sender: Sender<Vec<Entry>>) -> Self {
         let pod = Arc::new(Mutex::new(Pod::new(transaction_processor.last_id())));
         PodRecorder { pod, transaction_processor, sender }
     }
 
+    /// Grab the pod lock without ever blocking: a `PodService` hash loop can
+    /// be holding it extremely briefly on every single hash, so a recorder
+    /// just retries rather than queuing up behind it.
+    fn lock_pod(&self) -> MutexGuard<Pod> {
+        loop {
+            if let Some(pod) = self.pod.try_lock() {
+                return pod;
+            }
+            thread::yield_now();
+        }
+    }
+
     pub fn hash(&self) {
- 
-        let mut pod = self.pod.lock().unwrap();
+
+        let mut pod = self.lock_pod();
         pod.hash()
     }
 
-    pub fn tick(&self) -> Result<()> {
- 
-        let mut pod = self.pod.lock().unwrap();
-        let tick = pod.tick();
-        self.transaction_processor.register_entry_id(&tick.id);
-        let entry = Entry {
-            num_hashes: tick.num_hashes,
-            id: tick.id,
-            transactions: vec![],
-        };
-        self.sender.send(vec![entry])?;
-        Ok(())
+    /// Advance the hash chain by one tick, with no entry emitted and no
+    /// last-id registered — a tick with no intervening `record()` should
+    /// leave no trace in the ledger. Its hash count simply accumulates
+    /// inside the locked `Pod` until the next `record()` folds it into that
+    /// entry's `num_hashes`.
+    pub fn tick(&self) {
+        let mut pod = self.lock_pod();
+        pod.tick()
     }
 
+    /// Advance the hash chain, emit the resulting `PodEntry` on `sender`, and
+    /// register its id with the `TransactionProcessor` — all while still
+    /// holding the pod lock, so no other `tick`/`record` call can interleave
+    /// a hash, an entry, or a last-id registration between these three steps.
+    /// That keeps the PoH chain, the entry stream, and the processor's
+    /// recent-id queue advancing in the same monotonic order. `num_hashes`
+    /// on the resulting entry covers this mixin's hash plus every tick
+    /// since the last recorded entry, so idle ticks never show up as
+    /// entries of their own.
     pub fn record(&self, mixin: Hash, txs: Vec<Transaction>) -> Result<()> {
- 
-        let mut pod = self.pod.lock().unwrap();
+
+        let mut pod = self.lock_pod();
         let tick = pod.record(mixin);
-        self.transaction_processor.register_entry_id(&tick.id);
         let entry = Entry {
             num_hashes: tick.num_hashes,
             id: tick.id,
             transactions: txs,
         };
         self.sender.send(vec![entry])?;
+        self.transaction_processor.register_entry_id(&tick.id);
         Ok(())
     }
+
+    /// Spawn a `PodService` that free-runs this recorder's hash chain on its
+    /// own thread, rather than relying on a caller to drive `hash`/`tick`.
+    pub fn service(&self, hashes_per_tick: u64, ticks_per_second: u64, exit: Arc<AtomicBool>) -> PodService {
+        PodService::new(self.clone(), hashes_per_tick, ticks_per_second, exit)
+    }
+}
+
+/// Generates PoH ticks on a dedicated thread at a configured rate, rather
+/// than waiting for an external caller to invoke `PodRecorder::hash`/`tick`
+/// on demand. `PodRecorder::record` can still inject transactions into the
+/// live hash stream at whatever height the service has reached.
+pub struct PodService {
+    thread_hdl: JoinHandle<()>,
+}
+
+impl PodService {
+    fn new(
+        pod_recorder: PodRecorder,
+        hashes_per_tick: u64,
+        ticks_per_second: u64,
+        exit: Arc<AtomicBool>,
+    ) -> Self {
+        let tick_duration = Duration::new(1, 0) / ticks_per_second as u32;
+
+        let thread_hdl = Builder::new()
+            .name("hypercube-pod-service".to_string())
+            .spawn(move || loop {
+                let tick_start = Instant::now();
+
+                for _ in 0..hashes_per_tick {
+                    pod_recorder.hash();
+                }
+
+                pod_recorder.tick();
+
+                if exit.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                if let Some(remaining) = tick_duration.checked_sub(tick_start.elapsed()) {
+                    sleep(remaining);
+                }
+            }).unwrap();
+
+        PodService { thread_hdl }
+    }
+}
+
+impl Service for PodService {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        self.thread_hdl.join()
+    }
 }
 
 #[cfg(test)]
@@ -74,17 +151,21 @@ mod tests {
         let (entry_sender, entry_receiver) = channel();
         let pod_recorder = PodRecorder::new(transaction_processor, entry_sender);
 
- 
         let h1 = hash(b"hello world!");
         assert!(pod_recorder.record(h1, vec![]).is_ok());
-        assert!(pod_recorder.tick().is_ok());
+        let entry = entry_receiver.recv().unwrap();
+        assert_eq!(entry[0].num_hashes, 1);
 
- 
-        let _ = entry_receiver.recv().unwrap();
-        let _ = entry_receiver.recv().unwrap();
+        // Idle ticks with no intervening record() don't emit entries of
+        // their own; their hash count folds into the next recorded entry.
+        pod_recorder.tick();
+        pod_recorder.tick();
+        let h2 = hash(b"goodbye!");
+        assert!(pod_recorder.record(h2, vec![]).is_ok());
+        let entry = entry_receiver.recv().unwrap();
+        assert_eq!(entry[0].num_hashes, 3);
 
- 
         drop(entry_receiver);
-        assert!(pod_recorder.tick().is_err());
+        assert!(pod_recorder.record(h2, vec![]).is_err());
     }
 }
@@ -0,0 +1,322 @@
+//! A Plumtree-style eager-push epidemic broadcast overlay, layered on top
+//! of a `ChooseGossipPeerStrategy` ranking. Every peer starts out `eager`
+//! (so fresh `NodeInfo`/vote updates propagate proactively along a live
+//! spanning tree) and gets pruned into `lazy` the first time it relays
+//! back a message we'd already delivered; `lazy` peers only ever see
+//! compact IHave digests, and get grafted back into `eager` if we have to
+//! pull a message from them because it never showed up on its own.
+
+use choose_gossip_peer_strategy::ChooseGossipPeerStrategy;
+use blockthread::NodeInfo;
+use hash::Hash;
+use xpz_program_interface::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// How long to wait for a message advertised by IHave before GRAFTing the
+/// peer that advertised it back into the eager set to pull it directly.
+pub const GRAFT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long a delivered message is remembered in `seen` for dedup/GRAFT
+/// purposes before it's evicted, so a long-running node's memory doesn't
+/// grow with the lifetime total of every message it's ever relayed.
+pub const SEEN_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Clone)]
+pub enum PushMessage<V> {
+    /// The full payload, forwarded along eager links.
+    Full(Hash, V),
+    /// A digest-only advertisement, sent to lazy peers.
+    IHave(Hash),
+    /// Sent back to a peer whose full message we'd already delivered, to
+    /// prune it out of our eager set.
+    Prune,
+    /// Sent to pull a message we only know about via an IHave.
+    Graft(Hash),
+}
+
+struct PendingGraft {
+    from: Pubkey,
+    deadline: Instant,
+}
+
+struct SeenEntry<V> {
+    value: V,
+    deadline: Instant,
+}
+
+pub struct PushActiveSet<V: Clone> {
+    self_id: Pubkey,
+    eager: HashSet<Pubkey>,
+    lazy: HashSet<Pubkey>,
+    seen: HashMap<Hash, SeenEntry<V>>,
+    pending_ihave: Vec<(Pubkey, Hash)>,
+    pending_grafts: HashMap<Hash, PendingGraft>,
+    outbox: Vec<(Pubkey, PushMessage<V>)>,
+}
+
+impl<V: Clone> PushActiveSet<V> {
+    /// Seed the eager set with up to `fanout` peers ranked by `strategy`;
+    /// everyone else in `options` starts out lazy.
+    pub fn new(
+        self_id: Pubkey,
+        fanout: usize,
+        mut options: Vec<&NodeInfo>,
+        strategy: &ChooseGossipPeerStrategy,
+    ) -> Self {
+        let mut eager = HashSet::new();
+        let no_exclusions = HashSet::new();
+        for _ in 0..fanout {
+            if options.is_empty() {
+                break;
+            }
+            match strategy.choose_peer(options.clone(), &no_exclusions) {
+                Ok(peer) => {
+                    eager.insert(peer.id);
+                    options.retain(|candidate| candidate.id != peer.id);
+                }
+                Err(_) => break,
+            }
+        }
+        let lazy = options.into_iter().map(|peer| peer.id).collect();
+
+        PushActiveSet {
+            self_id,
+            eager,
+            lazy,
+            seen: HashMap::new(),
+            pending_ihave: Vec::new(),
+            pending_grafts: HashMap::new(),
+            outbox: Vec::new(),
+        }
+    }
+
+    /// Handle a full message arriving from `from`. Returns `true` the first
+    /// time `id` is seen, meaning the caller should deliver `value` locally;
+    /// a duplicate prunes `from` out of the eager set instead.
+    pub fn process_message(&mut self, from: Pubkey, id: Hash, value: V) -> bool {
+        self.pending_grafts.remove(&id);
+
+        if self.seen.contains_key(&id) {
+            self.demote(from);
+            self.outbox.push((from, PushMessage::Prune));
+            return false;
+        }
+
+        self.seen.insert(
+            id,
+            SeenEntry {
+                value: value.clone(),
+                deadline: Instant::now() + SEEN_TTL,
+            },
+        );
+        for &peer in &self.eager {
+            if peer != from {
+                self.outbox.push((peer, PushMessage::Full(id, value.clone())));
+            }
+        }
+        for &peer in &self.lazy {
+            if peer != from {
+                self.pending_ihave.push((peer, id));
+            }
+        }
+        true
+    }
+
+    /// Handle an IHave digest for a message we may not hold yet; starts a
+    /// GRAFT timer that `drain_outbox` will fire if it expires unanswered.
+    pub fn process_ihave(&mut self, from: Pubkey, id: Hash) {
+        if self.seen.contains_key(&id) {
+            return;
+        }
+        self.pending_grafts.entry(id).or_insert_with(|| PendingGraft {
+            from,
+            deadline: Instant::now() + GRAFT_TIMEOUT,
+        });
+    }
+
+    /// Handle a PRUNE from `from`: demote it from eager to lazy.
+    pub fn process_prune(&mut self, from: Pubkey) {
+        self.demote(from);
+    }
+
+    /// Handle a GRAFT request from `from` for `id`: promote them into
+    /// eager, and hand back the payload to resend if we still hold it.
+    pub fn process_graft(&mut self, from: Pubkey, id: Hash) -> Option<V> {
+        self.promote(from);
+        self.seen.get(&id).map(|entry| entry.value.clone())
+    }
+
+    fn demote(&mut self, peer: Pubkey) {
+        if peer == self.self_id {
+            return;
+        }
+        if self.eager.remove(&peer) {
+            self.lazy.insert(peer);
+        }
+    }
+
+    fn promote(&mut self, peer: Pubkey) {
+        if peer == self.self_id {
+            return;
+        }
+        self.lazy.remove(&peer);
+        self.eager.insert(peer);
+    }
+
+    fn expire_grafts(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<Hash> = self.pending_grafts
+            .iter()
+            .filter(|&(_, pending)| pending.deadline <= now)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in expired {
+            if let Some(pending) = self.pending_grafts.remove(&id) {
+                self.promote(pending.from);
+                self.outbox.push((pending.from, PushMessage::Graft(id)));
+            }
+        }
+    }
+
+    /// Drop entries from `seen` older than `SEEN_TTL`, so the map doesn't
+    /// retain every message this node has ever delivered for the life of
+    /// the process.
+    fn expire_seen(&mut self) {
+        let now = Instant::now();
+        self.seen.retain(|_, entry| entry.deadline > now);
+    }
+
+    /// Drain this round's outgoing `(peer, message)` pairs: deliveries,
+    /// prunes and grafts queued since the last call, any IHave digests
+    /// queued for lazy peers, and any GRAFT timers that have expired.
+    pub fn drain_outbox(&mut self) -> Vec<(Pubkey, PushMessage<V>)> {
+        self.expire_grafts();
+        self.expire_seen();
+        let mut outbox = Vec::new();
+        outbox.append(&mut self.outbox);
+        for (peer, id) in self.pending_ihave.drain(..) {
+            outbox.push((peer, PushMessage::IHave(id)));
+        }
+        outbox
+    }
+
+    pub fn eager_peers(&self) -> &HashSet<Pubkey> {
+        &self.eager
+    }
+
+    pub fn lazy_peers(&self) -> &HashSet<Pubkey> {
+        &self.lazy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::Hash;
+    use signature::{Keypair, KeypairUtil};
+    use std::thread::sleep;
+
+    fn active_set(self_id: Pubkey, eager: &[Pubkey], lazy: &[Pubkey]) -> PushActiveSet<Vec<u8>> {
+        PushActiveSet {
+            self_id,
+            eager: eager.iter().cloned().collect(),
+            lazy: lazy.iter().cloned().collect(),
+            seen: HashMap::new(),
+            pending_ihave: Vec::new(),
+            pending_grafts: HashMap::new(),
+            outbox: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_new_message_forwards_eager_and_queues_ihave() {
+        let self_id = Keypair::new().pubkey();
+        let peer_a = Keypair::new().pubkey();
+        let peer_b = Keypair::new().pubkey();
+        let peer_c = Keypair::new().pubkey();
+        let mut set = active_set(self_id, &[peer_a, peer_b], &[peer_c]);
+
+        let id = Hash::default();
+        let delivered = set.process_message(peer_a, id, vec![1, 2, 3]);
+        assert!(delivered);
+
+        let outbox = set.drain_outbox();
+        assert!(outbox.iter().any(|&(peer, ref msg)| peer == peer_b && matches!(msg, &PushMessage::Full(..))));
+        assert!(!outbox.iter().any(|&(peer, _)| peer == peer_a));
+        assert!(outbox.iter().any(|&(peer, ref msg)| peer == peer_c && matches!(msg, &PushMessage::IHave(_))));
+    }
+
+    #[test]
+    fn test_duplicate_message_prunes_sender() {
+        let self_id = Keypair::new().pubkey();
+        let peer_a = Keypair::new().pubkey();
+        let mut set = active_set(self_id, &[peer_a], &[]);
+
+        let id = Hash::default();
+        assert!(set.process_message(peer_a, id, vec![1]));
+        assert!(!set.process_message(peer_a, id, vec![1]));
+
+        assert!(!set.eager_peers().contains(&peer_a));
+        assert!(set.lazy_peers().contains(&peer_a));
+
+        let outbox = set.drain_outbox();
+        assert!(outbox.iter().any(|&(peer, ref msg)| peer == peer_a && matches!(msg, &PushMessage::Prune)));
+    }
+
+    #[test]
+    fn test_ihave_timeout_grafts_peer() {
+        let self_id = Keypair::new().pubkey();
+        let peer_a = Keypair::new().pubkey();
+        let mut set = active_set(self_id, &[], &[peer_a]);
+
+        let id = Hash::default();
+        set.process_ihave(peer_a, id);
+        assert!(set.drain_outbox().is_empty());
+
+        sleep(GRAFT_TIMEOUT + Duration::from_millis(50));
+
+        let outbox = set.drain_outbox();
+        assert!(outbox.iter().any(|&(peer, ref msg)| peer == peer_a && matches!(msg, &PushMessage::Graft(_))));
+        assert!(set.eager_peers().contains(&peer_a));
+    }
+
+    #[test]
+    fn test_graft_returns_payload_if_held() {
+        let self_id = Keypair::new().pubkey();
+        let peer_a = Keypair::new().pubkey();
+        let mut set = active_set(self_id, &[], &[peer_a]);
+
+        let id = Hash::default();
+        set.seen.insert(
+            id,
+            SeenEntry {
+                value: vec![9, 9],
+                deadline: Instant::now() + SEEN_TTL,
+            },
+        );
+
+        let payload = set.process_graft(peer_a, id);
+        assert_eq!(payload, Some(vec![9, 9]));
+        assert!(set.eager_peers().contains(&peer_a));
+    }
+
+    #[test]
+    fn test_seen_entries_expire_after_ttl() {
+        let self_id = Keypair::new().pubkey();
+        let peer_a = Keypair::new().pubkey();
+        let mut set = active_set(self_id, &[peer_a], &[]);
+
+        let id = Hash::default();
+        set.seen.insert(
+            id,
+            SeenEntry {
+                value: vec![1, 2, 3],
+                deadline: Instant::now() - Duration::from_millis(1),
+            },
+        );
+
+        set.drain_outbox();
+        assert!(set.process_graft(peer_a, id).is_none());
+    }
+}
@@ -1,13 +1,19 @@
 use bincode::{deserialize, serialize};
 use bs58;
+use byteorder::{ByteOrder, LittleEndian};
 use fin_plan_program::FinPlanState;
 use fin_plan_transaction::FinPlanTransaction;
 use chrono::prelude::*;
 use clap::ArgMatches;
 use blockthread::NodeInfo;
-use faucet::DroneRequest;
+use builtin_pgm::SystemProgram;
+use faucet::{DroneRequest, DRONE_PORT};
 use fullnode::Config;
+use rpc::RPC_PORT;
 use hash::Hash;
+use loader_program::{LoaderInstruction, LoaderState, CHUNK_SIZE};
+use nonce_program::{NonceAccount, NonceInstruction, NonceState};
+use vote_program::{Vote, VoteInstruction, VoteProgram, VoteState};
 use reqwest;
 use reqwest::header::CONTENT_TYPE;
 use ring::rand::SystemRandom;
@@ -15,12 +21,15 @@ use ring::signature::Ed25519KeyPair;
 use serde_json::{self, Value};
 use signature::{Keypair, KeypairUtil, Signature};
 use xpz_program_interface::pubkey::Pubkey;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::prelude::*;
 use std::io::{Error, ErrorKind, Write};
 use std::mem::size_of;
 use std::net::{Ipv4Addr, SocketAddr, TcpStream};
 use std::path::Path;
+use std::rc::Rc;
 use std::thread::sleep;
 use std::time::Duration;
 use std::{error, fmt, mem};
@@ -31,9 +40,16 @@ use transaction::Transaction;
 pub enum QtcCommand {
     Address,
     AirDrop(i64),
-    Balance,
+    Balance(Option<Pubkey>),
+    AuthorizeVoter(Pubkey),
+    AdvanceNonceAccount(Pubkey),
     Cancel(Pubkey),
     Confirm(Signature),
+    CreateNonceAccount(Pubkey, Pubkey, i64),
+    CreateVoteAccount(Pubkey, Pubkey, u32, i64),
+    Deploy(String),
+    EstimateFee(u8),
+    GetTransactionCount,
     Pay(
         i64,
         Pubkey,
@@ -41,10 +57,18 @@ pub enum QtcCommand {
         Option<Pubkey>,
         Option<Vec<Pubkey>>,
         Option<Pubkey>,
+        BlockhashQuery,
+        bool,
+        Option<(Pubkey, Signature)>,
+        Option<usize>,
     ),
 
+    ShowVoteAccount(Pubkey),
+
     TimeElapsed(Pubkey, Pubkey, DateTime<Utc>),
 
+    Vote,
+
     Witness(Pubkey, Pubkey),
 }
 
@@ -77,6 +101,8 @@ pub struct QtcConfig {
     pub id: Keypair,
     pub faucet_addr: SocketAddr,
     pub rpc_addr: String,
+    pub rpc_tls: bool,
+    pub rpc_client: Rc<RpcRequestHandler>,
     pub command: QtcCommand,
 }
 
@@ -87,12 +113,34 @@ impl Default for QtcConfig {
             leader: NodeInfo::new_with_socketaddr(&default_addr),
             id: Keypair::new(),
             faucet_addr: default_addr,
-            rpc_addr: default_addr.to_string(),
-            command: QtcCommand::Balance,
+            rpc_addr: get_rpc_request_str(default_addr, false),
+            rpc_tls: false,
+            rpc_client: Rc::new(RpcClient),
+            command: QtcCommand::Balance(None),
         }
     }
 }
 
+impl QtcConfig {
+    /// Repoint `leader`, `faucet_addr`, and `rpc_addr` at a freshly
+    /// discovered leader, so the CLI can bootstrap against a moving leader
+    /// rather than requiring a hand-supplied leader file. `rpc_port`
+    /// overrides the leader's advertised RPC port when supplied, and `tls`
+    /// selects `https://` over the default `http://`.
+    pub fn update_leader_addrs(&mut self, leader: NodeInfo, rpc_port: Option<u16>, tls: bool) {
+        let mut faucet_addr = leader.contact_info.tx_creator;
+        faucet_addr.set_port(DRONE_PORT);
+        self.faucet_addr = faucet_addr;
+
+        self.rpc_tls = tls;
+        let mut rpc_addr = leader.contact_info.tx_creator;
+        rpc_addr.set_port(rpc_port.unwrap_or(RPC_PORT));
+        self.rpc_addr = get_rpc_request_str(rpc_addr, tls);
+
+        self.leader = leader;
+    }
+}
+
 pub fn parse_command(
     pubkey: Pubkey,
     matches: &ArgMatches,
@@ -103,7 +151,52 @@ pub fn parse_command(
             let tokens = airdrop_matches.value_of("tokens").unwrap().parse()?;
             Ok(QtcCommand::AirDrop(tokens))
         }
-        ("balance", Some(_balance_matches)) => Ok(QtcCommand::Balance),
+        ("authorize-voter", Some(authorize_voter_matches)) => {
+            let pubkey_vec = bs58::decode(authorize_voter_matches.value_of("vote-account").unwrap())
+                .into_vec()
+                .expect("base58-encoded public key");
+
+            if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+                eprintln!("{}", authorize_voter_matches.usage());
+                Err(QtcError::BadParameter(
+                    "Invalid vote account public key".to_string(),
+                ))?;
+            }
+            let vote_account_pubkey = Pubkey::new(&pubkey_vec);
+            Ok(QtcCommand::AuthorizeVoter(vote_account_pubkey))
+        }
+        ("balance", Some(balance_matches)) => {
+            let pubkey = if let Some(pubkey_str) = balance_matches.value_of("pubkey") {
+                let pubkey_vec = bs58::decode(pubkey_str)
+                    .into_vec()
+                    .expect("base58-encoded public key");
+
+                if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+                    eprintln!("{}", balance_matches.usage());
+                    Err(QtcError::BadParameter("Invalid public key".to_string()))?;
+                }
+                Some(Pubkey::new(&pubkey_vec))
+            } else {
+                None
+            };
+            Ok(QtcCommand::Balance(pubkey))
+        }
+        ("advance-nonce-account", Some(advance_nonce_account_matches)) => {
+            let pubkey_vec = bs58::decode(
+                advance_nonce_account_matches
+                    .value_of("nonce-account")
+                    .unwrap(),
+            ).into_vec()
+                .expect("base58-encoded public key");
+
+            if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+                eprintln!("{}", advance_nonce_account_matches.usage());
+                Err(QtcError::BadParameter(
+                    "Invalid nonce account public key".to_string(),
+                ))?;
+            }
+            Ok(QtcCommand::AdvanceNonceAccount(Pubkey::new(&pubkey_vec)))
+        }
         ("cancel", Some(cancel_matches)) => {
             let pubkey_vec = bs58::decode(cancel_matches.value_of("process-id").unwrap())
                 .into_vec()
@@ -129,6 +222,110 @@ pub fn parse_command(
                 Err(QtcError::BadParameter("Invalid signature".to_string()))
             }
         }
+        ("create-nonce-account", Some(create_nonce_account_matches)) => {
+            let pubkey_vec = bs58::decode(
+                create_nonce_account_matches
+                    .value_of("nonce-account")
+                    .unwrap(),
+            ).into_vec()
+                .expect("base58-encoded public key");
+
+            if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+                eprintln!("{}", create_nonce_account_matches.usage());
+                Err(QtcError::BadParameter(
+                    "Invalid nonce account public key".to_string(),
+                ))?;
+            }
+            let nonce_account_pubkey = Pubkey::new(&pubkey_vec);
+
+            let authority = if create_nonce_account_matches.is_present("authority") {
+                let pubkey_vec = bs58::decode(
+                    create_nonce_account_matches
+                        .value_of("authority")
+                        .unwrap(),
+                ).into_vec()
+                    .expect("base58-encoded public key");
+
+                if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+                    eprintln!("{}", create_nonce_account_matches.usage());
+                    Err(QtcError::BadParameter(
+                        "Invalid authority public key".to_string(),
+                    ))?;
+                }
+                Pubkey::new(&pubkey_vec)
+            } else {
+                pubkey
+            };
+
+            let tokens = create_nonce_account_matches
+                .value_of("tokens")
+                .unwrap()
+                .parse()?;
+            Ok(QtcCommand::CreateNonceAccount(
+                nonce_account_pubkey,
+                authority,
+                tokens,
+            ))
+        }
+        ("create-vote-account", Some(create_vote_account_matches)) => {
+            let pubkey_vec = bs58::decode(
+                create_vote_account_matches
+                    .value_of("vote-account")
+                    .unwrap(),
+            ).into_vec()
+                .expect("base58-encoded public key");
+
+            if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+                eprintln!("{}", create_vote_account_matches.usage());
+                Err(QtcError::BadParameter(
+                    "Invalid vote account public key".to_string(),
+                ))?;
+            }
+            let vote_account_pubkey = Pubkey::new(&pubkey_vec);
+
+            let pubkey_vec = bs58::decode(
+                create_vote_account_matches.value_of("node-pubkey").unwrap(),
+            ).into_vec()
+                .expect("base58-encoded public key");
+
+            if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+                eprintln!("{}", create_vote_account_matches.usage());
+                Err(QtcError::BadParameter(
+                    "Invalid node public key".to_string(),
+                ))?;
+            }
+            let node_pubkey = Pubkey::new(&pubkey_vec);
+
+            let commission = create_vote_account_matches
+                .value_of("commission")
+                .unwrap()
+                .parse()?;
+            let tokens = create_vote_account_matches
+                .value_of("tokens")
+                .unwrap()
+                .parse()?;
+            Ok(QtcCommand::CreateVoteAccount(
+                vote_account_pubkey,
+                node_pubkey,
+                commission,
+                tokens,
+            ))
+        }
+        ("deploy", Some(deploy_matches)) => {
+            let program_location = deploy_matches.value_of("program-location").unwrap();
+            Ok(QtcCommand::Deploy(program_location.to_string()))
+        }
+        ("estimate-fee", Some(estimate_fee_matches)) => {
+            let percentile = if estimate_fee_matches.is_present("percentile") {
+                estimate_fee_matches.value_of("percentile").unwrap().parse()?
+            } else {
+                50
+            };
+            Ok(QtcCommand::EstimateFee(percentile))
+        }
+        ("get-transaction-count", Some(_get_transaction_count_matches)) => {
+            Ok(QtcCommand::GetTransactionCount)
+        }
         ("pay", Some(pay_matches)) => {
             let tokens = pay_matches.value_of("tokens").unwrap().parse()?;
             let to = if pay_matches.is_present("to") {
@@ -197,6 +394,70 @@ pub fn parse_command(
             } else {
                 None
             };
+            let blockhash_query = if pay_matches.is_present("nonce-account") {
+                let pubkey_vec = bs58::decode(pay_matches.value_of("nonce-account").unwrap())
+                    .into_vec()
+                    .expect("base58-encoded public key");
+                if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+                    eprintln!("{}", pay_matches.usage());
+                    Err(QtcError::BadParameter(
+                        "Invalid nonce account public key".to_string(),
+                    ))?;
+                }
+                BlockhashQuery::Nonce(Pubkey::new(&pubkey_vec))
+            } else if pay_matches.is_present("blockhash") {
+                let blockhash_vec = bs58::decode(pay_matches.value_of("blockhash").unwrap())
+                    .into_vec()
+                    .expect("base58-encoded blockhash");
+                BlockhashQuery::Static(Hash::new(&blockhash_vec))
+            } else {
+                BlockhashQuery::Cluster
+            };
+            let sign_only = pay_matches.is_present("sign-only");
+            let signer = if pay_matches.is_present("signer") {
+                let parts: Vec<&str> = pay_matches.value_of("signer").unwrap().splitn(2, '=').collect();
+                if parts.len() != 2 {
+                    eprintln!("{}", pay_matches.usage());
+                    Err(QtcError::BadParameter(
+                        "Invalid signer, expected PUBKEY=SIGNATURE".to_string(),
+                    ))?;
+                }
+                let pubkey_vec = bs58::decode(parts[0])
+                    .into_vec()
+                    .expect("base58-encoded public key");
+                if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+                    eprintln!("{}", pay_matches.usage());
+                    Err(QtcError::BadParameter("Invalid signer public key".to_string()))?;
+                }
+                let signature_vec = bs58::decode(parts[1])
+                    .into_vec()
+                    .expect("base58-encoded signature");
+                if signature_vec.len() != mem::size_of::<Signature>() {
+                    eprintln!("{}", pay_matches.usage());
+                    Err(QtcError::BadParameter("Invalid signer signature".to_string()))?;
+                }
+                Some((Pubkey::new(&pubkey_vec), Signature::new(&signature_vec)))
+            } else {
+                None
+            };
+            let (blockhash_query, signer) = if pay_matches.is_present("reply") {
+                let (reply_blockhash, pubkey_sigs) =
+                    parse_sign_only_reply_string(pay_matches.value_of("reply").unwrap())?;
+                let (signer_pubkey, _) = pubkey_sigs.get(0).cloned().ok_or_else(|| {
+                    QtcError::BadParameter("Reply contains no signers".to_string())
+                })?;
+                let signature = presigner_from_pubkey_sigs(&pubkey_sigs, &signer_pubkey).ok_or_else(|| {
+                    QtcError::BadParameter("Reply contains no signers".to_string())
+                })?;
+                (BlockhashQuery::Static(reply_blockhash), Some((signer_pubkey, signature)))
+            } else {
+                (blockhash_query, signer)
+            };
+            let threshold = if pay_matches.is_present("threshold") {
+                Some(pay_matches.value_of("threshold").unwrap().parse()?)
+            } else {
+                None
+            };
 
             Ok(QtcCommand::Pay(
                 tokens,
@@ -205,6 +466,10 @@ pub fn parse_command(
                 timestamp_pubkey,
                 witness_vec,
                 cancelable,
+                blockhash_query,
+                sign_only,
+                signer,
+                threshold,
             ))
         }
         ("send-signature", Some(sig_matches)) => {
@@ -229,6 +494,23 @@ pub fn parse_command(
             let process_id = Pubkey::new(&pubkey_vec);
             Ok(QtcCommand::Witness(to, process_id))
         }
+        ("show-vote-account", Some(show_vote_account_matches)) => {
+            let pubkey_vec = bs58::decode(
+                show_vote_account_matches
+                    .value_of("vote-account")
+                    .unwrap(),
+            ).into_vec()
+                .expect("base58-encoded public key");
+
+            if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+                eprintln!("{}", show_vote_account_matches.usage());
+                Err(QtcError::BadParameter(
+                    "Invalid vote account public key".to_string(),
+                ))?;
+            }
+            let vote_account_pubkey = Pubkey::new(&pubkey_vec);
+            Ok(QtcCommand::ShowVoteAccount(vote_account_pubkey))
+        }
         ("send-timestamp", Some(timestamp_matches)) => {
             let pubkey_vec = bs58::decode(timestamp_matches.value_of("to").unwrap())
                 .into_vec()
@@ -266,6 +548,7 @@ pub fn parse_command(
             };
             Ok(QtcCommand::TimeElapsed(to, process_id, dt))
         }
+        ("vote", Some(_)) => Ok(QtcCommand::Vote),
         ("", None) => {
             eprintln!("{}", matches.usage());
             Err(QtcError::CommandNotRecognized(
@@ -282,49 +565,43 @@ pub fn process_command(config: &QtcConfig) -> Result<String, Box<error::Error>>
 
         QtcCommand::Address => Ok(format!("{}", config.id.pubkey())),
 
+        QtcCommand::AuthorizeVoter(vote_account_pubkey) => {
+            let last_id = get_last_id(&config)?;
+
+            let instruction = VoteInstruction::AuthorizeVoter(config.id.pubkey());
+            let userdata = serialize(&instruction).expect("serialize instruction");
+            let tx = Transaction::new(
+                &config.id,
+                &[vote_account_pubkey],
+                VoteState::id(),
+                userdata,
+                last_id,
+                0,
+            );
+            let signature_str = serialize_and_send_tx(&config, &tx)?;
+
+            Ok(signature_str.to_string())
+        }
+
         QtcCommand::AirDrop(tokens) => {
             println!(
                 "Requesting airdrop of {:?} tokens from {}",
                 tokens, config.faucet_addr
             );
-            let params = json!(format!("{}", config.id.pubkey()));
-            let previous_balance = match QtcRpcRequest::GetBalance
-                .make_rpc_request(&config.rpc_addr, 1, Some(params))?
-                .as_i64()
-            {
-                Some(tokens) => tokens,
-                None => Err(QtcError::RpcRequestError(
-                    "Received result of an unexpected type".to_string(),
-                ))?,
-            };
-            request_airdrop(&config.faucet_addr, &config.id.pubkey(), tokens as u64)?;
-
-
-            let mut current_balance = previous_balance;
-            for _ in 0..20 {
-                sleep(Duration::from_millis(500));
-                let params = json!(format!("{}", config.id.pubkey()));
-                current_balance = QtcRpcRequest::GetBalance
-                    .make_rpc_request(&config.rpc_addr, 1, Some(params))?
-                    .as_i64()
-                    .unwrap_or(previous_balance);
-
-                if previous_balance != current_balance {
-                    break;
-                }
-                println!(".");
-            }
-            if current_balance - previous_balance != tokens {
-                Err("Airdrop failed!")?;
-            }
+            let current_balance = request_and_confirm_airdrop(&config, tokens)?;
             Ok(format!("Your balance is: {:?}", current_balance))
         }
 
-        QtcCommand::Balance => {
+        QtcCommand::Balance(pubkey) => {
+            let pubkey = pubkey.unwrap_or_else(|| config.id.pubkey());
             println!("Balance requested...");
-            let params = json!(format!("{}", config.id.pubkey()));
-            let balance = QtcRpcRequest::GetBalance
-                .make_rpc_request(&config.rpc_addr, 1, Some(params))?
+            let params = json!(format!("{}", pubkey));
+            let balance = config.rpc_client.make_rpc_request(
+                &config.rpc_addr,
+                QtcRpcRequest::GetBalance,
+                1,
+                Some(params),
+            )?
                 .as_i64();
             match balance {
                 Some(0) => Ok("No account found! Request an airdrop to get started.".to_string()),
@@ -335,43 +612,258 @@ pub fn process_command(config: &QtcConfig) -> Result<String, Box<error::Error>>
             }
         }
 
+        QtcCommand::AdvanceNonceAccount(nonce_account_pubkey) => {
+            let last_id = get_last_id(&config)?;
+
+            let instruction = NonceInstruction::AdvanceNonceAccount;
+            let userdata = serialize(&instruction).expect("serialize instruction");
+            let tx = Transaction::new(
+                &config.id,
+                &[nonce_account_pubkey],
+                NonceState::id(),
+                userdata,
+                last_id,
+                0,
+            );
+            let signature_str = serialize_send_and_confirm_tx(&config, &tx)?;
+
+            Ok(signature_str.to_string())
+        }
+
         QtcCommand::Cancel(pubkey) => {
             let last_id = get_last_id(&config)?;
 
             let tx =
                 Transaction::fin_plan_new_signature(&config.id, pubkey, config.id.pubkey(), last_id);
-            let signature_str = serialize_and_send_tx(&config, &tx)?;
+            let signature_str = serialize_send_and_confirm_tx(&config, &tx)?;
 
             Ok(signature_str.to_string())
         }
 
         QtcCommand::Confirm(signature) => {
-            let params = json!(format!("{}", signature));
-            let confirmation = QtcRpcRequest::ConfirmTransaction
-                .make_rpc_request(&config.rpc_addr, 1, Some(params))?
-                .as_bool();
-            match confirmation {
-                Some(b) => {
-                    if b {
+            match poll_signature_status(&config, &signature, 1)? {
+                QtcSignatureStatus::Confirmed => {
+                    // A signature can be Confirmed before the transaction count it
+                    // landed at has been superseded by later ones, i.e. before it's
+                    // rooted. Poll get-transaction-count once more to tell the two
+                    // apart instead of reporting a bare "Confirmed".
+                    let count_at_confirm = get_transaction_count(&config)?;
+                    sleep(Duration::from_millis(200));
+                    let count_now = get_transaction_count(&config)?;
+                    if count_now > count_at_confirm {
                         Ok("Confirmed".to_string())
                     } else {
-                        Ok("Not found".to_string())
+                        Ok("Confirmed, not yet rooted".to_string())
                     }
                 }
-                None => Err(QtcError::RpcRequestError(
-                    "Received result of an unexpected type".to_string(),
-                ))?,
+                QtcSignatureStatus::SignatureNotFound => Ok("Not found".to_string()),
+                QtcSignatureStatus::ProgramRuntimeError => {
+                    Ok("Transaction failed: program runtime error".to_string())
+                }
+                QtcSignatureStatus::GenericFailure => Ok("Transaction failed".to_string()),
             }
         }
 
-        QtcCommand::Pay(tokens, to, timestamp, timestamp_pubkey, ref witnesses, cancelable) => {
+        QtcCommand::CreateNonceAccount(nonce_account_pubkey, authority, tokens) => {
+            let last_id = get_last_id(&config)?;
+            let tx = Transaction::system_create(
+                &config.id,
+                nonce_account_pubkey,
+                last_id,
+                tokens,
+                80, // large enough for the serialized NonceAccount state
+                NonceState::id(),
+                0,
+            );
+            let _signature_str = serialize_and_send_tx(&config, &tx)?;
+            poll_for_balance(&config, &nonce_account_pubkey, tokens)?;
+
+            let last_id = get_last_id(&config)?;
+            let instruction = NonceInstruction::InitializeNonceAccount(authority);
+            let userdata = serialize(&instruction).expect("serialize instruction");
+            let tx = Transaction::new(
+                &config.id,
+                &[nonce_account_pubkey],
+                NonceState::id(),
+                userdata,
+                last_id,
+                0,
+            );
+            let signature_str = serialize_and_send_tx(&config, &tx)?;
+
+            Ok(signature_str.to_string())
+        }
+
+        QtcCommand::CreateVoteAccount(vote_account_pubkey, node_pubkey, commission, tokens) => {
+            let last_id = get_last_id(&config)?;
+            let tx = Transaction::system_create(
+                &config.id,
+                vote_account_pubkey,
+                last_id,
+                tokens,
+                300,
+                VoteState::id(),
+                0,
+            );
+            let _signature_str = serialize_and_send_tx(&config, &tx)?;
+            poll_for_balance(&config, &vote_account_pubkey, tokens)?;
+
+            let last_id = get_last_id(&config)?;
+            let instruction = VoteInstruction::InitializeAccount {
+                node_id: node_pubkey,
+                commission,
+            };
+            let userdata = serialize(&instruction).expect("serialize instruction");
+            let tx = Transaction::new(
+                &config.id,
+                &[vote_account_pubkey],
+                VoteState::id(),
+                userdata,
+                last_id,
+                0,
+            );
+            let signature_str = serialize_and_send_tx(&config, &tx)?;
+
+            Ok(signature_str.to_string())
+        }
+
+        QtcCommand::Deploy(ref program_location) => {
+            let elf = fs::read(program_location)?;
+            let program_userdata = elf_section_bytes(&elf, ".text.entrypoint")?;
+            let program_account = Keypair::new();
+
+            let last_id = get_last_id(&config)?;
+            let tx = Transaction::system_create(
+                &config.id,
+                program_account.pubkey(),
+                last_id,
+                1,
+                program_userdata.len() as u64,
+                LoaderState::id(),
+                0,
+            );
+            let _signature_str = serialize_and_send_tx(&config, &tx)?;
+            poll_for_balance(&config, &program_account.pubkey(), 1)?;
+
+            for (i, chunk) in program_userdata.chunks(CHUNK_SIZE).enumerate() {
+                let offset = (i * CHUNK_SIZE) as u32;
+                let mut last_err = None;
+                let mut written = false;
+                for _ in 0..DEPLOY_WRITE_RETRIES {
+                    let last_id = get_last_id(&config)?;
+                    let instruction = LoaderInstruction::Write {
+                        offset,
+                        bytes: chunk.to_vec(),
+                    };
+                    let userdata = serialize(&instruction).expect("serialize instruction");
+                    let tx = Transaction::new(
+                        &program_account,
+                        &[],
+                        LoaderState::id(),
+                        userdata,
+                        last_id,
+                        0,
+                    );
+                    match serialize_send_and_confirm_tx(&config, &tx) {
+                        Ok(_) => {
+                            written = true;
+                            break;
+                        }
+                        Err(err) => last_err = Some(err),
+                    }
+                }
+                if !written {
+                    Err(last_err.unwrap_or_else(|| {
+                        QtcError::RpcRequestError(format!(
+                            "Failed to write program chunk at offset {}",
+                            offset
+                        )).into()
+                    }))?;
+                }
+            }
+
+            let deployed_len = get_account_userdata_len(&config, &program_account.pubkey())?;
+            if deployed_len != program_userdata.len() {
+                Err(QtcError::RpcRequestError(format!(
+                    "Deployed program size mismatch: on-chain account has {} bytes, expected {}",
+                    deployed_len,
+                    program_userdata.len()
+                )))?;
+            }
+
             let last_id = get_last_id(&config)?;
+            let instruction = LoaderInstruction::Finalize;
+            let userdata = serialize(&instruction).expect("serialize instruction");
+            let tx = Transaction::new(
+                &program_account,
+                &[],
+                LoaderState::id(),
+                userdata,
+                last_id,
+                0,
+            );
+            serialize_send_and_confirm_tx(&config, &tx)?;
+
+            Ok(json!({
+                "programId": format!("{}", program_account.pubkey()),
+            }).to_string())
+        }
+
+        QtcCommand::EstimateFee(percentile) => {
+            let fee = get_recommended_fee(&config, percentile)?;
+            Ok(format!("{:?}", fee))
+        }
+
+        QtcCommand::GetTransactionCount => {
+            let count = get_transaction_count(&config)?;
+            Ok(format!("{:?}", count))
+        }
 
+        QtcCommand::Pay(
+            tokens,
+            to,
+            timestamp,
+            timestamp_pubkey,
+            ref witnesses,
+            cancelable,
+            ref blockhash_query,
+            sign_only,
+            ref signer,
+            threshold,
+        ) => {
             if timestamp == None && *witnesses == None {
-                let tx = Transaction::system_new(&config.id, to, tokens, last_id);
-                let signature_str = serialize_and_send_tx(&config, &tx)?;
+                let last_id = blockhash_query.get_blockhash(&config)?;
+                let userdata = serialize(&SystemProgram::Move(tokens)).expect("serialize instruction");
+
+                if sign_only {
+                    let tx = Transaction::new(&config.id, &[to], SystemProgram::id(), userdata, last_id, 0);
+                    return Ok(json!({
+                        "blockhash": format!("{}", last_id),
+                        "signers": [format!("{}={}", tx.from(), tx.signature)],
+                    }).to_string());
+                }
+
+                let tx = if let Some((signer_pubkey, signature)) = signer {
+                    Transaction::new_presigned(
+                        *signer_pubkey,
+                        &[to],
+                        SystemProgram::id(),
+                        userdata,
+                        last_id,
+                        0,
+                        *signature,
+                    )
+                } else {
+                    Transaction::system_new(&config.id, to, tokens, last_id)
+                };
+                let signature_str = serialize_send_and_confirm_tx(&config, &tx)?;
                 Ok(signature_str.to_string())
+            } else if sign_only || signer.is_some() {
+                Err(QtcError::BadParameter(
+                    "Offline signing is only supported for simple payments".to_string(),
+                ))?
             } else if *witnesses == None {
+                let last_id = blockhash_query.get_blockhash(&config)?;
                 let dt = timestamp.unwrap();
                 let dt_pubkey = match timestamp_pubkey {
                     Some(pubkey) => pubkey,
@@ -417,15 +909,85 @@ pub fn process_command(config: &QtcConfig) -> Result<String, Box<error::Error>>
                     tokens,
                     last_id,
                 );
-                let signature_str = serialize_and_send_tx(&config, &tx)?;
+                let signature_str = serialize_send_and_confirm_tx(&config, &tx)?;
 
                 Ok(json!({
                     "signature": signature_str,
                     "processId": format!("{}", contract_state.pubkey()),
                 }).to_string())
             } else if timestamp == None {
-                let last_id = get_last_id(&config)?;
+                let last_id = blockhash_query.get_blockhash(&config)?;
+
+                let witness_vec = match *witnesses {
+                    Some(ref witness_vec) => witness_vec,
+                    None => Err(QtcError::BadParameter(
+                        "Could not parse required signature pubkey(s)".to_string(),
+                    ))?,
+                };
+
+                let contract_funds = Keypair::new();
+                let contract_state = Keypair::new();
+                let fin_plan_program_id = FinPlanState::id();
+
+
+                let tx = Transaction::system_create(
+                    &config.id,
+                    contract_funds.pubkey(),
+                    last_id,
+                    tokens,
+                    0,
+                    fin_plan_program_id,
+                    0,
+                );
+                let _signature_str = serialize_and_send_tx(&config, &tx)?;
 
+                let tx = Transaction::system_create(
+                    &config.id,
+                    contract_state.pubkey(),
+                    last_id,
+                    1,
+                    196,
+                    fin_plan_program_id,
+                    0,
+                );
+                let _signature_str = serialize_and_send_tx(&config, &tx)?;
+
+
+                let tx = if witness_vec.len() > 1 {
+                    Transaction::fin_plan_new_multisig(
+                        &contract_funds,
+                        to,
+                        contract_state.pubkey(),
+                        witness_vec,
+                        threshold.unwrap_or_else(|| witness_vec.len()),
+                        cancelable,
+                        tokens,
+                        last_id,
+                    )
+                } else {
+                    Transaction::fin_plan_new_when_signed(
+                        &contract_funds,
+                        to,
+                        contract_state.pubkey(),
+                        witness_vec[0],
+                        cancelable,
+                        tokens,
+                        last_id,
+                    )
+                };
+                let signature_str = serialize_send_and_confirm_tx(&config, &tx)?;
+
+                Ok(json!({
+                    "signature": signature_str,
+                    "processId": format!("{}", contract_state.pubkey()),
+                }).to_string())
+            } else {
+                let last_id = blockhash_query.get_blockhash(&config)?;
+                let dt = timestamp.unwrap();
+                let dt_pubkey = match timestamp_pubkey {
+                    Some(pubkey) => pubkey,
+                    None => config.id.pubkey(),
+                };
                 let witness = if let Some(ref witness_vec) = *witnesses {
                     witness_vec[0]
                 } else {
@@ -438,7 +1000,6 @@ pub fn process_command(config: &QtcConfig) -> Result<String, Box<error::Error>>
                 let contract_state = Keypair::new();
                 let fin_plan_program_id = FinPlanState::id();
 
-  
                 let tx = Transaction::system_create(
                     &config.id,
                     contract_funds.pubkey(),
@@ -461,31 +1022,64 @@ pub fn process_command(config: &QtcConfig) -> Result<String, Box<error::Error>>
                 );
                 let _signature_str = serialize_and_send_tx(&config, &tx)?;
 
-
-                let tx = Transaction::fin_plan_new_when_signed(
+                let tx = Transaction::fin_plan_new_on_date_when_signed(
                     &contract_funds,
                     to,
                     contract_state.pubkey(),
+                    dt,
+                    dt_pubkey,
                     witness,
                     cancelable,
                     tokens,
                     last_id,
                 );
-                let signature_str = serialize_and_send_tx(&config, &tx)?;
+                let signature_str = serialize_send_and_confirm_tx(&config, &tx)?;
 
                 Ok(json!({
                     "signature": signature_str,
                     "processId": format!("{}", contract_state.pubkey()),
                 }).to_string())
-            } else {
-                Ok("Combo transactions not yet handled".to_string())
             }
         }
 
+        QtcCommand::ShowVoteAccount(vote_account_pubkey) => {
+            let params = json!(format!("{}", vote_account_pubkey));
+            let account_info =
+                config.rpc_client.make_rpc_request(
+                    &config.rpc_addr,
+                    QtcRpcRequest::GetAccountInfo,
+                    1,
+                    Some(params),
+                )?;
+            let userdata: Vec<u8> = serde_json::from_value(
+                account_info
+                    .get("userdata")
+                    .cloned()
+                    .ok_or_else(|| QtcError::RpcRequestError("No vote account found".to_string()))?,
+            )?;
+            let vote_state: VoteProgram = deserialize(&userdata).or_else(|err| {
+                Err(QtcError::RpcRequestError(format!(
+                    "Unable to deserialize vote account state: {:?}",
+                    err
+                )))
+            })?;
+
+            Ok(json!({
+                "delegateId": format!("{}", vote_state.node_id),
+                "authorizedVoterId": format!("{}", vote_state.authorized_voter_id),
+                "commission": vote_state.commission,
+                "credits": vote_state.credits,
+            }).to_string())
+        }
+
         QtcCommand::TimeElapsed(to, pubkey, dt) => {
             let params = json!(format!("{}", config.id.pubkey()));
-            let balance = QtcRpcRequest::GetBalance
-                .make_rpc_request(&config.rpc_addr, 1, Some(params))?
+            let balance = config.rpc_client.make_rpc_request(
+                &config.rpc_addr,
+                QtcRpcRequest::GetBalance,
+                1,
+                Some(params),
+            )?
                 .as_i64();
             if let Some(0) = balance {
                 request_airdrop(&config.faucet_addr, &config.id.pubkey(), 1)?;
@@ -499,12 +1093,28 @@ pub fn process_command(config: &QtcConfig) -> Result<String, Box<error::Error>>
             Ok(signature_str.to_string())
         }
 
+        QtcCommand::Vote => {
+            let last_id = get_last_id(&config)?;
+            let vote = Vote {
+                version: 0,
+                contact_info_version: 0,
+            };
+            let tx = Transaction::fin_plan_new_vote(&config.id, vote, last_id, 0);
+            let signature_str = serialize_send_and_confirm_tx(&config, &tx)?;
+
+            Ok(signature_str.to_string())
+        }
+
         QtcCommand::Witness(to, pubkey) => {
             let last_id = get_last_id(&config)?;
 
             let params = json!(format!("{}", config.id.pubkey()));
-            let balance = QtcRpcRequest::GetBalance
-                .make_rpc_request(&config.rpc_addr, 1, Some(params))?
+            let balance = config.rpc_client.make_rpc_request(
+                &config.rpc_addr,
+                QtcRpcRequest::GetBalance,
+                1,
+                Some(params),
+            )?
                 .as_i64();
             if let Some(0) = balance {
                 request_airdrop(&config.faucet_addr, &config.id.pubkey(), 1)?;
@@ -561,62 +1171,224 @@ pub fn request_airdrop(
     Ok(signature)
 }
 
-pub fn gen_keypair_file(outfile: String) -> Result<String, Box<error::Error>> {
-    let rnd = SystemRandom::new();
-    let pkcs8_bytes = Ed25519KeyPair::generate_pkcs8(&rnd)?;
-    let serialized = serde_json::to_string(&pkcs8_bytes.to_vec())?;
+/// Request an airdrop and get back the faucet's own signed `Transaction` for
+/// it, so the client can submit and confirm it like any other transaction
+/// rather than trusting the faucet's fire-and-forget signature.
+pub fn request_airdrop_transaction(
+    faucet_addr: &SocketAddr,
+    id: &Pubkey,
+    tokens: u64,
+) -> Result<Transaction, Error> {
+    // TODO: make this async tokio client
+    let mut stream = TcpStream::connect(faucet_addr)?;
+    let req = DroneRequest::GetAirdrop {
+        airdrop_request_amount: tokens,
+        client_pubkey: *id,
+    };
+    let tx = serialize(&req).expect("serialize faucet request");
+    stream.write_all(&tx)?;
+    let mut buffer = vec![];
+    stream
+        .read_to_end(&mut buffer)
+        .or_else(|_| Err(Error::new(ErrorKind::Other, "Airdrop failed")))?;
+    deserialize(&buffer).or_else(|err| {
+        Err(Error::new(
+            ErrorKind::Other,
+            format!("deserialize transaction in request_airdrop_transaction: {:?}", err),
+        ))
+    })
+}
 
-    if outfile != "-" {
-        if let Some(outdir) = Path::new(&outfile).parent() {
-            fs::create_dir_all(outdir)?;
-        }
+/// Request an airdrop, submit the faucet's signed transaction for it, poll
+/// until the signature is confirmed, then check that the balance rose by
+/// exactly `tokens`, returning the resulting balance.
+fn request_and_confirm_airdrop(config: &QtcConfig, tokens: i64) -> Result<i64, Box<error::Error>> {
+    let params = json!(format!("{}", config.id.pubkey()));
+    let previous_balance = config.rpc_client.make_rpc_request(
+        &config.rpc_addr,
+        QtcRpcRequest::GetBalance,
+        1,
+        Some(params),
+    )?
+        .as_i64()
+        .ok_or_else(|| {
+            QtcError::RpcRequestError("Received result of an unexpected type".to_string())
+        })?;
+
+    let tx = request_airdrop_transaction(&config.faucet_addr, &config.id.pubkey(), tokens as u64)?;
+    serialize_send_and_confirm_tx(&config, &tx)?;
+
+    let params = json!(format!("{}", config.id.pubkey()));
+    let current_balance = config.rpc_client.make_rpc_request(
+        &config.rpc_addr,
+        QtcRpcRequest::GetBalance,
+        1,
+        Some(params),
+    )?
+        .as_i64()
+        .ok_or_else(|| {
+            QtcError::RpcRequestError("Received result of an unexpected type".to_string())
+        })?;
+
+    if current_balance < previous_balance {
+        Err(QtcError::RpcRequestError(
+            "current_balance < previous_balance".to_string(),
+        ))?
+    } else if current_balance - previous_balance != tokens {
+        Err(QtcError::RpcRequestError(format!(
+            "balance increased by {} instead of {}",
+            current_balance - previous_balance,
+            tokens
+        )))?
+    } else {
+        Ok(current_balance)
+    }
+}
+
+/// Walk a 64-bit ELF file's section header table and return the bytes of
+/// the section named `name`, so `Deploy` can upload just a program's code
+/// rather than the whole object file.
+fn elf_section_bytes(elf: &[u8], name: &str) -> Result<Vec<u8>, Error> {
+    if elf.len() < 64 || &elf[0..4] != b"\x7fELF" {
+        Err(Error::new(ErrorKind::Other, "Not an ELF file"))?;
+    }
+
+    let e_shoff = LittleEndian::read_u64(&elf[40..48]) as usize;
+    let e_shentsize = LittleEndian::read_u16(&elf[58..60]) as usize;
+    let e_shnum = LittleEndian::read_u16(&elf[60..62]) as usize;
+    let e_shstrndx = LittleEndian::read_u16(&elf[62..64]) as usize;
+
+    let shstrtab_hdr = e_shoff + e_shstrndx * e_shentsize;
+    let shstrtab_off = LittleEndian::read_u64(&elf[shstrtab_hdr + 24..shstrtab_hdr + 32]) as usize;
+
+    for i in 0..e_shnum {
+        let hdr = e_shoff + i * e_shentsize;
+        let sh_name = LittleEndian::read_u32(&elf[hdr..hdr + 4]) as usize;
+        let name_start = shstrtab_off + sh_name;
+        let name_end = elf[name_start..]
+            .iter()
+            .position(|&b| b == 0)
+            .map(|p| name_start + p)
+            .unwrap_or(name_start);
+        if &elf[name_start..name_end] == name.as_bytes() {
+            let sh_offset = LittleEndian::read_u64(&elf[hdr + 24..hdr + 32]) as usize;
+            let sh_size = LittleEndian::read_u64(&elf[hdr + 32..hdr + 40]) as usize;
+            return Ok(elf[sh_offset..sh_offset + sh_size].to_vec());
+        }
+    }
+
+    Err(Error::new(
+        ErrorKind::Other,
+        format!("ELF section not found: {}", name),
+    ))
+}
+
+pub fn gen_keypair_file(outfile: String) -> Result<String, Box<error::Error>> {
+    let rnd = SystemRandom::new();
+    let pkcs8_bytes = Ed25519KeyPair::generate_pkcs8(&rnd)?;
+    let serialized = serde_json::to_string(&pkcs8_bytes.to_vec())?;
+
+    if outfile != "-" {
+        if let Some(outdir) = Path::new(&outfile).parent() {
+            fs::create_dir_all(outdir)?;
+        }
         let mut f = File::create(outfile)?;
         f.write_all(&serialized.clone().into_bytes())?;
     }
     Ok(serialized)
 }
 
+/// The status of a submitted transaction's signature, as reported by
+/// `getSignatureStatus`. Distinguishes a transaction that failed during
+/// execution from one that was never seen at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QtcSignatureStatus {
+    Confirmed,
+    SignatureNotFound,
+    ProgramRuntimeError,
+    GenericFailure,
+}
+
+impl QtcSignatureStatus {
+    fn from_rpc_result(result: &Value) -> Self {
+        match result.as_str() {
+            Some("Confirmed") => QtcSignatureStatus::Confirmed,
+            Some("SignatureNotFound") => QtcSignatureStatus::SignatureNotFound,
+            Some("ProgramRuntimeError") => QtcSignatureStatus::ProgramRuntimeError,
+            Some(_) => QtcSignatureStatus::GenericFailure,
+            None => QtcSignatureStatus::SignatureNotFound,
+        }
+    }
+}
+
 pub enum QtcRpcRequest {
     ConfirmTransaction,
     GetAccountInfo,
     GetBalance,
     GetFinality,
     GetLastId,
+    GetRecommendedFee,
+    GetSignatureStatus,
     GetTransactionCount,
     RequestAirdrop,
     SendTransaction,
 }
 impl QtcRpcRequest {
-    fn make_rpc_request(
-        &self,
-        rpc_addr: &str,
-        id: u64,
-        params: Option<Value>,
-    ) -> Result<Value, Box<error::Error>> {
-        let jsonrpc = "2.0";
-        let method = match self {
+    fn as_str(&self) -> &'static str {
+        match self {
             QtcRpcRequest::ConfirmTransaction => "confirmTransaction",
             QtcRpcRequest::GetAccountInfo => "getAccountInfo",
             QtcRpcRequest::GetBalance => "getBalance",
             QtcRpcRequest::GetFinality => "getFinality",
             QtcRpcRequest::GetLastId => "getLastId",
+            QtcRpcRequest::GetRecommendedFee => "getRecommendedFee",
+            QtcRpcRequest::GetSignatureStatus => "getSignatureStatus",
             QtcRpcRequest::GetTransactionCount => "getTransactionCount",
             QtcRpcRequest::RequestAirdrop => "requestAirdrop",
             QtcRpcRequest::SendTransaction => "sendTransaction",
-        };
+        }
+    }
+}
+
+/// Issues a single JSON-RPC request and returns its `result` value. Lets
+/// `QtcConfig` swap in a `MockRpcClient` for tests that want to drive
+/// `process_command` against canned responses instead of a live node.
+pub trait RpcRequestHandler {
+    fn make_rpc_request(
+        &self,
+        rpc_addr: &str,
+        request: QtcRpcRequest,
+        id: u64,
+        params: Option<Value>,
+    ) -> Result<Value, Box<error::Error>>;
+}
+
+/// The real, reqwest-backed handler used outside of tests.
+pub struct RpcClient;
+
+impl RpcRequestHandler for RpcClient {
+    fn make_rpc_request(
+        &self,
+        rpc_addr: &str,
+        request: QtcRpcRequest,
+        id: u64,
+        params: Option<Value>,
+    ) -> Result<Value, Box<error::Error>> {
+        let jsonrpc = "2.0";
+        let method = request.as_str();
         let client = reqwest::Client::new();
-        let mut request = json!({
+        let mut rpc_request = json!({
            "jsonrpc": jsonrpc,
            "id": id,
            "method": method,
         });
         if let Some(param_string) = params {
-            request["params"] = json!(vec![param_string]);
+            rpc_request["params"] = json!(vec![param_string]);
         }
         let mut response = client
             .post(rpc_addr)
             .header(CONTENT_TYPE, "application/json")
-            .body(request.to_string())
+            .body(rpc_request.to_string())
             .send()?;
         let json: Value = serde_json::from_str(&response.text()?)?;
         if json["error"].is_object() {
@@ -629,8 +1401,91 @@ impl QtcRpcRequest {
     }
 }
 
+/// A scriptable `RpcRequestHandler` for unit tests: canned `result` values
+/// (or errors) keyed by RPC method name, with no network or running node
+/// involved. Every request made through it is recorded so tests can assert
+/// on the exact calls `process_command` issued.
+#[derive(Default)]
+pub struct MockRpcClient {
+    responses: RefCell<HashMap<String, Value>>,
+    errors: RefCell<HashMap<String, String>>,
+    requests: RefCell<Vec<(String, Option<Value>)>>,
+}
+
+impl MockRpcClient {
+    pub fn new() -> Self {
+        MockRpcClient::default()
+    }
+
+    /// Script a successful `result` value for the given RPC method.
+    pub fn set_response(&self, method: &str, response: Value) {
+        self.responses
+            .borrow_mut()
+            .insert(method.to_string(), response);
+    }
+
+    /// Script an error response for the given RPC method.
+    pub fn set_error(&self, method: &str, message: &str) {
+        self.errors
+            .borrow_mut()
+            .insert(method.to_string(), message.to_string());
+    }
+
+    /// The `(method, params)` of every request made so far, in order.
+    pub fn requests(&self) -> Vec<(String, Option<Value>)> {
+        self.requests.borrow().clone()
+    }
+}
+
+impl RpcRequestHandler for MockRpcClient {
+    fn make_rpc_request(
+        &self,
+        _rpc_addr: &str,
+        request: QtcRpcRequest,
+        _id: u64,
+        params: Option<Value>,
+    ) -> Result<Value, Box<error::Error>> {
+        let method = request.as_str();
+        self.requests
+            .borrow_mut()
+            .push((method.to_string(), params));
+
+        if let Some(message) = self.errors.borrow().get(method) {
+            Err(QtcError::RpcRequestError(message.clone()))?
+        }
+        self.responses.borrow().get(method).cloned().ok_or_else(|| {
+            QtcError::RpcRequestError(format!("no mock response configured for {}", method)).into()
+        })
+    }
+}
+
+/// Build the RPC endpoint URL for `rpc_addr`, using `https://` when `tls`
+/// is set and `http://` otherwise, so callers never hand-format one.
+pub fn get_rpc_request_str(rpc_addr: SocketAddr, tls: bool) -> String {
+    if tls {
+        format!("https://{}", rpc_addr)
+    } else {
+        format!("http://{}", rpc_addr)
+    }
+}
+
+/// Normalize a user-supplied RPC endpoint (e.g. from `--proxy`), prefixing
+/// it with `http://` if it wasn't given a scheme.
+pub fn normalize_rpc_addr(addr: &str) -> String {
+    if addr.starts_with("http://") || addr.starts_with("https://") {
+        addr.to_string()
+    } else {
+        format!("http://{}", addr)
+    }
+}
+
 fn get_last_id(config: &QtcConfig) -> Result<Hash, Box<error::Error>> {
-    let result = QtcRpcRequest::GetLastId.make_rpc_request(&config.rpc_addr, 1, None)?;
+    let result = config.rpc_client.make_rpc_request(
+        &config.rpc_addr,
+        QtcRpcRequest::GetLastId,
+        1,
+        None,
+    )?;
     if result.as_str().is_none() {
         Err(QtcError::RpcRequestError(
             "Received bad last_id".to_string(),
@@ -643,6 +1498,257 @@ fn get_last_id(config: &QtcConfig) -> Result<Hash, Box<error::Error>> {
     Ok(Hash::new(&last_id_vec))
 }
 
+fn get_transaction_count(config: &QtcConfig) -> Result<i64, Box<error::Error>> {
+    let result = config.rpc_client.make_rpc_request(
+        &config.rpc_addr,
+        QtcRpcRequest::GetTransactionCount,
+        1,
+        None,
+    )?;
+    result.as_i64().ok_or_else(|| {
+        QtcError::RpcRequestError("Received result of an unexpected type".to_string()).into()
+    })
+}
+
+fn get_recommended_fee(config: &QtcConfig, percentile: u8) -> Result<i64, Box<error::Error>> {
+    let result = config.rpc_client.make_rpc_request(
+        &config.rpc_addr,
+        QtcRpcRequest::GetRecommendedFee,
+        1,
+        Some(json!(percentile)),
+    )?;
+    result.as_i64().ok_or_else(|| {
+        QtcError::RpcRequestError("Received result of an unexpected type".to_string()).into()
+    })
+}
+
+/// Fetch the stored blockhash out of a durable nonce account so it can
+/// stand in for a live `last_id`.
+fn get_nonce_hash(config: &QtcConfig, nonce_account: &Pubkey) -> Result<Hash, Box<error::Error>> {
+    let params = json!(format!("{}", nonce_account));
+    let account_info =
+        config.rpc_client.make_rpc_request(
+            &config.rpc_addr,
+            QtcRpcRequest::GetAccountInfo,
+            1,
+            Some(params),
+        )?;
+    let userdata: Vec<u8> = serde_json::from_value(
+        account_info
+            .get("userdata")
+            .cloned()
+            .ok_or_else(|| QtcError::RpcRequestError("No nonce account found".to_string()))?,
+    )?;
+    let nonce_account: NonceAccount = deserialize(&userdata).or_else(|err| {
+        Err(QtcError::RpcRequestError(format!(
+            "Unable to deserialize nonce account state: {:?}",
+            err
+        )))
+    })?;
+    Ok(nonce_account.blockhash)
+}
+
+/// Selects where a transaction's recency blockhash comes from: fetched live
+/// from the cluster (the common case), a fixed value the caller already
+/// knows (e.g. one embedded in an offline-signed `--reply`), or the stored
+/// hash inside a durable nonce account, which doesn't expire until the
+/// account is advanced. Threading this through `Pay` lets the online and
+/// offline signing paths pick a blockhash the same way.
+#[derive(Debug, PartialEq, Clone)]
+pub enum BlockhashQuery {
+    Cluster,
+    Static(Hash),
+    Nonce(Pubkey),
+}
+
+impl BlockhashQuery {
+    fn get_blockhash(&self, config: &QtcConfig) -> Result<Hash, Box<error::Error>> {
+        match *self {
+            BlockhashQuery::Cluster => get_last_id(config),
+            BlockhashQuery::Static(hash) => Ok(hash),
+            BlockhashQuery::Nonce(ref nonce_account) => get_nonce_hash(config, nonce_account),
+        }
+    }
+}
+
+/// How many times `Deploy` retries a single chunk write before giving up,
+/// so a dropped or unconfirmed `Write` transaction doesn't fail an entire
+/// upload.
+const DEPLOY_WRITE_RETRIES: usize = 5;
+
+/// Fetch the length of `pubkey`'s on-chain userdata. Used by `Deploy` to
+/// confirm every chunk actually landed before sending `Finalize`, so a
+/// program that silently failed to fully upload isn't marked executable.
+fn get_account_userdata_len(
+    config: &QtcConfig,
+    pubkey: &Pubkey,
+) -> Result<usize, Box<error::Error>> {
+    let params = json!(format!("{}", pubkey));
+    let account_info = config.rpc_client.make_rpc_request(
+        &config.rpc_addr,
+        QtcRpcRequest::GetAccountInfo,
+        1,
+        Some(params),
+    )?;
+    let userdata: Vec<u8> = serde_json::from_value(
+        account_info
+            .get("userdata")
+            .cloned()
+            .ok_or_else(|| QtcError::RpcRequestError("No account found".to_string()))?,
+    )?;
+    Ok(userdata.len())
+}
+
+/// Poll `GetBalance` until `pubkey`'s account reflects at least
+/// `expected_minimum` tokens, so a deploy stage doesn't move on before its
+/// funding transaction has landed.
+fn poll_for_balance(
+    config: &QtcConfig,
+    pubkey: &Pubkey,
+    expected_minimum: i64,
+) -> Result<(), Box<error::Error>> {
+    for _ in 0..20 {
+        let params = json!(format!("{}", pubkey));
+        let balance = config.rpc_client.make_rpc_request(
+            &config.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )?
+            .as_i64()
+            .unwrap_or(0);
+        if balance >= expected_minimum {
+            return Ok(());
+        }
+        sleep(Duration::from_millis(500));
+    }
+    Err(QtcError::RpcRequestError(
+        "Timed out waiting for balance to update".to_string(),
+    ))?
+}
+
+/// Poll `getSignatureStatus` with backoff, up to `retries` times, returning
+/// the first status other than `SignatureNotFound`, or `SignatureNotFound`
+/// if it never resolves. Reused by `Confirm` and after every
+/// `serialize_and_send_tx`, so a slow or failed transaction doesn't
+/// silently drop.
+fn poll_signature_status(
+    config: &QtcConfig,
+    signature: &Signature,
+    retries: u64,
+) -> Result<QtcSignatureStatus, Box<error::Error>> {
+    for i in 0..retries {
+        let params = json!(format!("{}", signature));
+        let result = config.rpc_client.make_rpc_request(
+            &config.rpc_addr,
+            QtcRpcRequest::GetSignatureStatus,
+            1,
+            Some(params),
+        )?;
+        let status = QtcSignatureStatus::from_rpc_result(&result);
+        if status != QtcSignatureStatus::SignatureNotFound {
+            return Ok(status);
+        }
+        sleep(Duration::from_millis(200 * (i + 1)));
+    }
+    Ok(QtcSignatureStatus::SignatureNotFound)
+}
+
+/// Submit `tx`, then poll for its signature status and fail with a
+/// descriptive error if it didn't confirm, rather than just handing back a
+/// signature string that might name a transaction which failed to execute.
+fn serialize_send_and_confirm_tx(
+    config: &QtcConfig,
+    tx: &Transaction,
+) -> Result<String, Box<error::Error>> {
+    let signature_str = serialize_and_send_tx(config, tx)?;
+    let signature_vec = bs58::decode(&signature_str)
+        .into_vec()
+        .expect("base58-encoded signature");
+    let signature = Signature::new(&signature_vec);
+    match poll_signature_status(&config, &signature, 20)? {
+        QtcSignatureStatus::Confirmed => Ok(signature_str),
+        QtcSignatureStatus::SignatureNotFound => Err(QtcError::RpcRequestError(
+            "Timed out waiting for signature confirmation".to_string(),
+        ))?,
+        QtcSignatureStatus::ProgramRuntimeError => Err(QtcError::RpcRequestError(
+            "Transaction failed: program runtime error".to_string(),
+        ))?,
+        QtcSignatureStatus::GenericFailure => {
+            Err(QtcError::RpcRequestError("Transaction failed".to_string()))?
+        }
+    }
+}
+
+/// Parse the JSON reply printed by a `--sign-only` payment (see the
+/// `sign_only` branch of `QtcCommand::Pay` in `process_command`) back into
+/// its blockhash and `(pubkey, signature)` pairs, so a second, network-
+/// connected invocation can finalize and broadcast the transaction by
+/// passing the reply straight through `--reply` instead of the caller
+/// re-typing `--blockhash` and `--signer` by hand.
+fn parse_sign_only_reply_string(
+    reply: &str,
+) -> Result<(Hash, Vec<(Pubkey, Signature)>), Box<error::Error>> {
+    let object: Value = serde_json::from_str(reply)?;
+    let blockhash_str = object["blockhash"]
+        .as_str()
+        .ok_or_else(|| QtcError::BadParameter("Reply is missing a blockhash".to_string()))?;
+    let blockhash_vec = bs58::decode(blockhash_str)
+        .into_vec()
+        .expect("base58-encoded blockhash");
+    let blockhash = Hash::new(&blockhash_vec);
+
+    let signers = object["signers"]
+        .as_array()
+        .ok_or_else(|| QtcError::BadParameter("Reply is missing signers".to_string()))?;
+    let mut pubkey_sigs = Vec::new();
+    for signer in signers {
+        let signer_str = signer
+            .as_str()
+            .ok_or_else(|| QtcError::BadParameter("Invalid signer in reply".to_string()))?;
+        let parts: Vec<&str> = signer_str.splitn(2, '=').collect();
+        if parts.len() != 2 {
+            Err(QtcError::BadParameter(
+                "Invalid signer in reply, expected PUBKEY=SIGNATURE".to_string(),
+            ))?;
+        }
+        let pubkey_vec = bs58::decode(parts[0])
+            .into_vec()
+            .expect("base58-encoded public key");
+        if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+            Err(QtcError::BadParameter(
+                "Invalid signer public key in reply".to_string(),
+            ))?;
+        }
+        let signature_vec = bs58::decode(parts[1])
+            .into_vec()
+            .expect("base58-encoded signature");
+        if signature_vec.len() != mem::size_of::<Signature>() {
+            Err(QtcError::BadParameter(
+                "Invalid signer signature in reply".to_string(),
+            ))?;
+        }
+        pubkey_sigs.push((Pubkey::new(&pubkey_vec), Signature::new(&signature_vec)));
+    }
+
+    Ok((blockhash, pubkey_sigs))
+}
+
+/// Look up the signature a given `pubkey` contributed to an offline signing
+/// round. `Transaction` only carries a single signature, so today's callers
+/// only ever have one pair to search, but keeping the lookup generic over a
+/// list of pairs leaves room for a reply that bundles several identities'
+/// signatures before this transaction format grows a second signer slot.
+fn presigner_from_pubkey_sigs(
+    pubkey_sigs: &[(Pubkey, Signature)],
+    pubkey: &Pubkey,
+) -> Option<Signature> {
+    pubkey_sigs
+        .iter()
+        .find(|(candidate, _)| candidate == pubkey)
+        .map(|(_, signature)| *signature)
+}
+
 fn serialize_and_send_tx(
     config: &QtcConfig,
     tx: &Transaction,
@@ -650,7 +1756,12 @@ fn serialize_and_send_tx(
     let serialized = serialize(tx).unwrap();
     let params = json!(serialized);
     let signature =
-        QtcRpcRequest::SendTransaction.make_rpc_request(&config.rpc_addr, 2, Some(params))?;
+        config.rpc_client.make_rpc_request(
+            &config.rpc_addr,
+            QtcRpcRequest::SendTransaction,
+            2,
+            Some(params),
+        )?;
     if signature.as_str().is_none() {
         Err(QtcError::RpcRequestError(
             "Received result of an unexpected type".to_string(),
@@ -691,6 +1802,95 @@ mod tests {
         let test_commands = App::new("test")
             .subcommand(SubCommand::with_name("address").about("Get your public key"))
             .subcommand(
+                SubCommand::with_name("advance-nonce-account")
+                    .about("Advance a durable nonce account to a fresh blockhash")
+                    .arg(
+                        Arg::with_name("nonce-account")
+                            .index(1)
+                            .value_name("PUBKEY")
+                            .takes_value(true)
+                            .required(true)
+                            .help("The pubkey of the nonce account to advance"),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("authorize-voter")
+                    .about("Authorize a new voter for a vote account")
+                    .arg(
+                        Arg::with_name("vote-account")
+                            .index(1)
+                            .value_name("PUBKEY")
+                            .takes_value(true)
+                            .required(true)
+                            .help("The pubkey of the vote account"),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("create-nonce-account")
+                    .about("Create and initialize a durable nonce account")
+                    .arg(
+                        Arg::with_name("nonce-account")
+                            .index(1)
+                            .value_name("PUBKEY")
+                            .takes_value(true)
+                            .required(true)
+                            .help("The pubkey of the nonce account to create"),
+                    ).arg(
+                        Arg::with_name("tokens")
+                            .index(2)
+                            .value_name("NUM")
+                            .takes_value(true)
+                            .required(true)
+                            .help("The number of tokens to fund the nonce account with"),
+                    ).arg(
+                        Arg::with_name("authority")
+                            .long("authority")
+                            .value_name("PUBKEY")
+                            .takes_value(true)
+                            .help("The pubkey authorized to advance this nonce account; defaults to your own"),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("create-vote-account")
+                    .about("Create a vote account for a validator")
+                    .arg(
+                        Arg::with_name("vote-account")
+                            .index(1)
+                            .value_name("PUBKEY")
+                            .takes_value(true)
+                            .required(true)
+                            .help("The pubkey of the vote account to create"),
+                    ).arg(
+                        Arg::with_name("node-pubkey")
+                            .index(2)
+                            .value_name("PUBKEY")
+                            .takes_value(true)
+                            .required(true)
+                            .help("The pubkey of the validator node to delegate to"),
+                    ).arg(
+                        Arg::with_name("commission")
+                            .index(3)
+                            .value_name("NUM")
+                            .takes_value(true)
+                            .required(true)
+                            .help("The commission the vote account charges"),
+                    ).arg(
+                        Arg::with_name("tokens")
+                            .index(4)
+                            .value_name("NUM")
+                            .takes_value(true)
+                            .required(true)
+                            .help("The number of tokens to fund the vote account with"),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("show-vote-account")
+                    .about("Show the state of a vote account")
+                    .arg(
+                        Arg::with_name("vote-account")
+                            .index(1)
+                            .value_name("PUBKEY")
+                            .takes_value(true)
+                            .required(true)
+                            .help("The pubkey of the vote account"),
+                    ),
+            ).subcommand(
                 SubCommand::with_name("airdrop")
                     .about("Request a batch of tokens")
                     .arg(
@@ -701,8 +1901,17 @@ mod tests {
                             .required(true)
                             .help("The number of tokens to request"),
                     ),
-            ).subcommand(SubCommand::with_name("balance").about("Get your balance"))
-            .subcommand(
+            ).subcommand(
+                SubCommand::with_name("balance")
+                    .about("Get your balance, or an arbitrary account's")
+                    .arg(
+                        Arg::with_name("pubkey")
+                            .index(1)
+                            .value_name("PUBKEY")
+                            .takes_value(true)
+                            .help("The pubkey of the account to query; defaults to your own"),
+                    ),
+            ).subcommand(
                 SubCommand::with_name("cancel")
                     .about("Cancel a transfer")
                     .arg(
@@ -724,6 +1933,30 @@ mod tests {
                             .required(true)
                             .help("The transaction signature to confirm"),
                     ),
+            ).subcommand(
+                SubCommand::with_name("deploy")
+                    .about("Deploy a program")
+                    .arg(
+                        Arg::with_name("program-location")
+                            .index(1)
+                            .value_name("PATH")
+                            .takes_value(true)
+                            .required(true)
+                            .help("/path/to/program.o"),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("estimate-fee")
+                    .about("Estimate a fee from recent network demand")
+                    .arg(
+                        Arg::with_name("percentile")
+                            .long("percentile")
+                            .value_name("PERCENTILE")
+                            .takes_value(true)
+                            .help("Percentile of recently paid fees to target, 0-100 (default: 50)"),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("get-transaction-count")
+                    .about("Get current transaction count"),
             ).subcommand(
                 SubCommand::with_name("pay")
                     .about("Send a payment")
@@ -766,6 +1999,45 @@ mod tests {
                         Arg::with_name("cancelable")
                             .long("cancelable")
                             .takes_value(false),
+                    ).arg(
+                        Arg::with_name("blockhash")
+                            .long("blockhash")
+                            .value_name("BLOCKHASH")
+                            .takes_value(true)
+                            .conflicts_with("nonce-account")
+                            .help("Use the supplied blockhash instead of fetching a recent one"),
+                    ).arg(
+                        Arg::with_name("nonce-account")
+                            .long("nonce-account")
+                            .value_name("PUBKEY")
+                            .takes_value(true)
+                            .help("Use the stored blockhash from this durable nonce account instead of fetching a recent one"),
+                    ).arg(
+                        Arg::with_name("sign-only")
+                            .long("sign-only")
+                            .takes_value(false)
+                            .help("Sign the transaction offline and print the signature without sending it"),
+                    ).arg(
+                        Arg::with_name("signer")
+                            .long("signer")
+                            .value_name("PUBKEY=SIGNATURE")
+                            .takes_value(true)
+                            .conflicts_with("reply")
+                            .help("Provide a signature obtained from an offline signer"),
+                    ).arg(
+                        Arg::with_name("reply")
+                            .long("reply")
+                            .value_name("JSON")
+                            .takes_value(true)
+                            .conflicts_with("blockhash")
+                            .help("Finalize and broadcast the JSON reply printed by a --sign-only payment"),
+                    ).arg(
+                        Arg::with_name("threshold")
+                            .long("threshold")
+                            .value_name("NUM")
+                            .takes_value(true)
+                            .requires("witness")
+                            .help("Number of required signatures that must be collected before the payment clears (defaults to all of them)"),
                     ),
             ).subcommand(
                 SubCommand::with_name("send-signature")
@@ -809,6 +2081,9 @@ mod tests {
                             .takes_value(true)
                             .help("Optional arbitrary timestamp to apply"),
                     ),
+            ).subcommand(
+                SubCommand::with_name("vote")
+                    .about("Submit a vote from this identity"),
             );
         let pubkey = Keypair::new().pubkey();
         let pubkey_string = format!("{}", pubkey);
@@ -842,48 +2117,172 @@ mod tests {
 
 
 
-        let signature = Signature::new(&vec![1; 64]);
-        let signature_string = format!("{:?}", signature);
-        let test_confirm =
-            test_commands
-                .clone()
-                .get_matches_from(vec!["test", "confirm", &signature_string]);
+        let test_create_vote_account = test_commands.clone().get_matches_from(vec![
+            "test",
+            "create-vote-account",
+            &pubkey_string,
+            &witness0_string,
+            "5",
+            "100",
+        ]);
         assert_eq!(
-            parse_command(pubkey, &test_confirm).unwrap(),
-            QtcCommand::Confirm(signature)
+            parse_command(pubkey, &test_create_vote_account).unwrap(),
+            QtcCommand::CreateVoteAccount(pubkey, witness0, 5, 100)
         );
-        let test_bad_signature = test_commands
-            .clone()
-            .get_matches_from(vec!["test", "confirm", "deadbeef"]);
-        assert!(parse_command(pubkey, &test_bad_signature).is_err());
 
-        let test_pay =
-            test_commands
-                .clone()
-                .get_matches_from(vec!["test", "pay", &pubkey_string, "50"]);
+        let test_create_nonce_account = test_commands
+            .clone()
+            .get_matches_from(vec!["test", "create-nonce-account", &pubkey_string, "100"]);
         assert_eq!(
-            parse_command(pubkey, &test_pay).unwrap(),
-            QtcCommand::Pay(50, pubkey, None, None, None, None)
+            parse_command(pubkey, &test_create_nonce_account).unwrap(),
+            QtcCommand::CreateNonceAccount(pubkey, pubkey, 100)
         );
-        let test_bad_pubkey = test_commands
-            .clone()
-            .get_matches_from(vec!["test", "pay", "deadbeef", "50"]);
-        assert!(parse_command(pubkey, &test_bad_pubkey).is_err());
-
 
-        let test_pay_multiple_witnesses = test_commands.clone().get_matches_from(vec![
+        let test_create_nonce_account_with_authority = test_commands.clone().get_matches_from(vec![
             "test",
-            "pay",
+            "create-nonce-account",
             &pubkey_string,
-            "50",
-            "--require-signature-from",
+            "100",
+            "--authority",
             &witness0_string,
-            "--require-signature-from",
-            &witness1_string,
+        ]);
+        assert_eq!(
+            parse_command(pubkey, &test_create_nonce_account_with_authority).unwrap(),
+            QtcCommand::CreateNonceAccount(pubkey, witness0, 100)
+        );
+
+        let test_advance_nonce_account = test_commands
+            .clone()
+            .get_matches_from(vec!["test", "advance-nonce-account", &pubkey_string]);
+        assert_eq!(
+            parse_command(pubkey, &test_advance_nonce_account).unwrap(),
+            QtcCommand::AdvanceNonceAccount(pubkey)
+        );
+
+        let test_authorize_voter = test_commands
+            .clone()
+            .get_matches_from(vec!["test", "authorize-voter", &pubkey_string]);
+        assert_eq!(
+            parse_command(pubkey, &test_authorize_voter).unwrap(),
+            QtcCommand::AuthorizeVoter(pubkey)
+        );
+
+        let test_show_vote_account = test_commands
+            .clone()
+            .get_matches_from(vec!["test", "show-vote-account", &pubkey_string]);
+        assert_eq!(
+            parse_command(pubkey, &test_show_vote_account).unwrap(),
+            QtcCommand::ShowVoteAccount(pubkey)
+        );
+
+        let test_deploy =
+            test_commands
+                .clone()
+                .get_matches_from(vec!["test", "deploy", "/path/to/program.o"]);
+        assert_eq!(
+            parse_command(pubkey, &test_deploy).unwrap(),
+            QtcCommand::Deploy("/path/to/program.o".to_string())
+        );
+
+        let test_balance = test_commands.clone().get_matches_from(vec!["test", "balance"]);
+        assert_eq!(
+            parse_command(pubkey, &test_balance).unwrap(),
+            QtcCommand::Balance(None)
+        );
+
+        let test_balance_of = test_commands
+            .clone()
+            .get_matches_from(vec!["test", "balance", &pubkey_string]);
+        assert_eq!(
+            parse_command(pubkey, &test_balance_of).unwrap(),
+            QtcCommand::Balance(Some(pubkey))
+        );
+
+        let test_get_transaction_count = test_commands
+            .clone()
+            .get_matches_from(vec!["test", "get-transaction-count"]);
+        assert_eq!(
+            parse_command(pubkey, &test_get_transaction_count).unwrap(),
+            QtcCommand::GetTransactionCount
+        );
+
+        let test_estimate_fee = test_commands
+            .clone()
+            .get_matches_from(vec!["test", "estimate-fee"]);
+        assert_eq!(
+            parse_command(pubkey, &test_estimate_fee).unwrap(),
+            QtcCommand::EstimateFee(50)
+        );
+
+        let test_estimate_fee_at_percentile = test_commands
+            .clone()
+            .get_matches_from(vec!["test", "estimate-fee", "--percentile", "90"]);
+        assert_eq!(
+            parse_command(pubkey, &test_estimate_fee_at_percentile).unwrap(),
+            QtcCommand::EstimateFee(90)
+        );
+
+        let test_vote = test_commands.clone().get_matches_from(vec!["test", "vote"]);
+        assert_eq!(parse_command(pubkey, &test_vote).unwrap(), QtcCommand::Vote);
+
+        let signature = Signature::new(&vec![1; 64]);
+        let signature_string = format!("{:?}", signature);
+        let test_confirm =
+            test_commands
+                .clone()
+                .get_matches_from(vec!["test", "confirm", &signature_string]);
+        assert_eq!(
+            parse_command(pubkey, &test_confirm).unwrap(),
+            QtcCommand::Confirm(signature)
+        );
+        let test_bad_signature = test_commands
+            .clone()
+            .get_matches_from(vec!["test", "confirm", "deadbeef"]);
+        assert!(parse_command(pubkey, &test_bad_signature).is_err());
+
+        let test_pay =
+            test_commands
+                .clone()
+                .get_matches_from(vec!["test", "pay", &pubkey_string, "50"]);
+        assert_eq!(
+            parse_command(pubkey, &test_pay).unwrap(),
+            QtcCommand::Pay(50, pubkey, None, None, None, None, BlockhashQuery::Cluster, false, None, None)
+        );
+        let test_bad_pubkey = test_commands
+            .clone()
+            .get_matches_from(vec!["test", "pay", "deadbeef", "50"]);
+        assert!(parse_command(pubkey, &test_bad_pubkey).is_err());
+
+
+        let test_pay_multiple_witnesses = test_commands.clone().get_matches_from(vec![
+            "test",
+            "pay",
+            &pubkey_string,
+            "50",
+            "--require-signature-from",
+            &witness0_string,
+            "--require-signature-from",
+            &witness1_string,
         ]);
         assert_eq!(
             parse_command(pubkey, &test_pay_multiple_witnesses).unwrap(),
-            QtcCommand::Pay(50, pubkey, None, None, Some(vec![witness0, witness1]), None)
+            QtcCommand::Pay(50, pubkey, None, None, Some(vec![witness0, witness1]), None, BlockhashQuery::Cluster, false, None, None)
+        );
+        let test_pay_multisig_threshold = test_commands.clone().get_matches_from(vec![
+            "test",
+            "pay",
+            &pubkey_string,
+            "50",
+            "--require-signature-from",
+            &witness0_string,
+            "--require-signature-from",
+            &witness1_string,
+            "--threshold",
+            "1",
+        ]);
+        assert_eq!(
+            parse_command(pubkey, &test_pay_multisig_threshold).unwrap(),
+            QtcCommand::Pay(50, pubkey, None, None, Some(vec![witness0, witness1]), None, BlockhashQuery::Cluster, false, None, Some(1))
         );
         let test_pay_single_witness = test_commands.clone().get_matches_from(vec![
             "test",
@@ -895,7 +2294,7 @@ mod tests {
         ]);
         assert_eq!(
             parse_command(pubkey, &test_pay_single_witness).unwrap(),
-            QtcCommand::Pay(50, pubkey, None, None, Some(vec![witness0]), None)
+            QtcCommand::Pay(50, pubkey, None, None, Some(vec![witness0]), None, BlockhashQuery::Cluster, false, None, None)
         );
 
 
@@ -911,9 +2310,93 @@ mod tests {
         ]);
         assert_eq!(
             parse_command(pubkey, &test_pay_timestamp).unwrap(),
-            QtcCommand::Pay(50, pubkey, Some(dt), Some(witness0), None, None)
+            QtcCommand::Pay(50, pubkey, Some(dt), Some(witness0), None, None, BlockhashQuery::Cluster, false, None, None)
+        );
+
+
+        let blockhash = Hash::default();
+        let blockhash_string = format!("{}", blockhash);
+        let test_pay_sign_only = test_commands.clone().get_matches_from(vec![
+            "test",
+            "pay",
+            &pubkey_string,
+            "50",
+            "--blockhash",
+            &blockhash_string,
+            "--sign-only",
+        ]);
+        assert_eq!(
+            parse_command(pubkey, &test_pay_sign_only).unwrap(),
+            QtcCommand::Pay(50, pubkey, None, None, None, None, BlockhashQuery::Static(blockhash), true, None, None)
+        );
+
+        let test_pay_nonce_account = test_commands.clone().get_matches_from(vec![
+            "test",
+            "pay",
+            &pubkey_string,
+            "50",
+            "--nonce-account",
+            &witness0_string,
+        ]);
+        assert_eq!(
+            parse_command(pubkey, &test_pay_nonce_account).unwrap(),
+            QtcCommand::Pay(50, pubkey, None, None, None, None, BlockhashQuery::Nonce(witness0), false, None, None)
         );
 
+        let signer_string = format!("{}={}", pubkey, Signature::default());
+        let test_pay_with_signer = test_commands.clone().get_matches_from(vec![
+            "test",
+            "pay",
+            &pubkey_string,
+            "50",
+            "--blockhash",
+            &blockhash_string,
+            "--signer",
+            &signer_string,
+        ]);
+        assert_eq!(
+            parse_command(pubkey, &test_pay_with_signer).unwrap(),
+            QtcCommand::Pay(
+                50,
+                pubkey,
+                None,
+                None,
+                None,
+                None,
+                BlockhashQuery::Static(blockhash),
+                false,
+                Some((pubkey, Signature::default())),
+                None,
+            )
+        );
+
+        let reply_string = json!({
+            "blockhash": format!("{}", blockhash),
+            "signers": [signer_string],
+        }).to_string();
+        let test_pay_reply = test_commands.clone().get_matches_from(vec![
+            "test",
+            "pay",
+            &pubkey_string,
+            "50",
+            "--reply",
+            &reply_string,
+        ]);
+        assert_eq!(
+            parse_command(pubkey, &test_pay_reply).unwrap(),
+            QtcCommand::Pay(
+                50,
+                pubkey,
+                None,
+                None,
+                None,
+                None,
+                BlockhashQuery::Static(blockhash),
+                false,
+                Some((pubkey, Signature::default())),
+                None,
+            )
+        );
 
         let test_send_signature = test_commands.clone().get_matches_from(vec![
             "test",
@@ -947,7 +2430,11 @@ mod tests {
                 Some(dt),
                 Some(witness0),
                 Some(vec![witness0, witness1]),
-                None
+                None,
+                BlockhashQuery::Cluster,
+                false,
+                None,
+                None,
             )
         );
 
@@ -1001,6 +2488,7 @@ mod tests {
             false,
             None,
             Some(rpc_port),
+            None,
         );
         sleep(Duration::from_millis(900));
 
@@ -1011,7 +2499,7 @@ mod tests {
 
         let mut rpc_addr = leader_data.contact_info.ncp;
         rpc_addr.set_port(rpc_port);
-        config.rpc_addr = format!("http://{}", rpc_addr.to_string());
+        config.rpc_addr = get_rpc_request_str(rpc_addr, false);
 
         let tokens = 50;
         config.command = QtcCommand::AirDrop(tokens);
@@ -1020,7 +2508,7 @@ mod tests {
             format!("Your balance is: {:?}", tokens)
         );
 
-        config.command = QtcCommand::Balance;
+        config.command = QtcCommand::Balance(None);
         assert_eq!(
             process_command(&config).unwrap(),
             format!("Your balance is: {:?}", tokens)
@@ -1032,7 +2520,7 @@ mod tests {
             format!("{}", config.id.pubkey())
         );
 
-        config.command = QtcCommand::Pay(10, bob_pubkey, None, None, None, None);
+        config.command = QtcCommand::Pay(10, bob_pubkey, None, None, None, None, BlockhashQuery::Cluster, false, None, None);
         let sig_response = process_command(&config);
         assert!(sig_response.is_ok());
 
@@ -1043,7 +2531,7 @@ mod tests {
         config.command = QtcCommand::Confirm(signature);
         assert_eq!(process_command(&config).unwrap(), "Confirmed");
 
-        config.command = QtcCommand::Balance;
+        config.command = QtcCommand::Balance(None);
         assert_eq!(
             process_command(&config).unwrap(),
             format!("Your balance is: {:?}", tokens - 10)
@@ -1075,6 +2563,7 @@ mod tests {
             false,
             None,
             Some(rpc_port),
+            None,
         );
         sleep(Duration::from_millis(900));
 
@@ -1084,13 +2573,17 @@ mod tests {
 
         let mut addr = leader_data.contact_info.ncp;
         addr.set_port(rpc_port);
-        let rpc_addr = format!("http://{}", addr.to_string());
+        let rpc_addr = get_rpc_request_str(addr, false);
 
         let signature = request_airdrop(&faucet_addr, &bob_pubkey, 50);
         assert!(signature.is_ok());
         let params = json!(format!("{}", signature.unwrap()));
-        let confirmation = QtcRpcRequest::ConfirmTransaction
-            .make_rpc_request(&rpc_addr, 1, Some(params))
+        let confirmation = RpcClient.make_rpc_request(
+            &rpc_addr,
+            QtcRpcRequest::ConfirmTransaction,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_bool()
             .unwrap();
@@ -1142,6 +2635,7 @@ mod tests {
             false,
             None,
             Some(rpc_port),
+            None,
         );
         sleep(Duration::from_millis(900));
 
@@ -1154,7 +2648,7 @@ mod tests {
 
         let mut rpc_addr = leader_data.contact_info.ncp;
         rpc_addr.set_port(rpc_port);
-        config_payer.rpc_addr = format!("http://{}", rpc_addr.to_string());
+        config_payer.rpc_addr = get_rpc_request_str(rpc_addr, false);
         config_witness.rpc_addr = config_payer.rpc_addr.clone();
 
         assert_ne!(config_payer.id.pubkey(), config_witness.id.pubkey());
@@ -1170,6 +2664,10 @@ mod tests {
             Some(config_witness.id.pubkey()),
             None,
             None,
+            BlockhashQuery::Cluster,
+            false,
+            None,
+            None,
         );
         let sig_response = process_command(&config_payer);
         assert!(sig_response.is_ok());
@@ -1182,22 +2680,34 @@ mod tests {
         let process_id = Pubkey::new(&process_id_vec);
 
         let params = json!(format!("{}", config_payer.id.pubkey()));
-        let config_payer_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let config_payer_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
         assert_eq!(config_payer_balance, 39);
         let params = json!(format!("{}", process_id));
-        let contract_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let contract_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
         assert_eq!(contract_balance, 11);
         let params = json!(format!("{}", bob_pubkey));
-        let recipient_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let recipient_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
@@ -1208,22 +2718,34 @@ mod tests {
         assert!(sig_response.is_ok());
 
         let params = json!(format!("{}", config_payer.id.pubkey()));
-        let config_payer_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let config_payer_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
         assert_eq!(config_payer_balance, 39);
         let params = json!(format!("{}", process_id));
-        let contract_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let contract_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
         assert_eq!(contract_balance, 1);
         let params = json!(format!("{}", bob_pubkey));
-        let recipient_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let recipient_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
@@ -1234,7 +2756,7 @@ mod tests {
     }
     #[test]
     #[ignore]
-    fn test_qtc_witness_tx() {
+    fn test_qtc_timestamp_cancel_tx() {
         let leader_keypair = Keypair::new();
         let leader = Node::new_localhost_with_pubkey(leader_keypair.pubkey());
 
@@ -1243,13 +2765,10 @@ mod tests {
         let bob_pubkey = Keypair::new().pubkey();
         let leader_data = leader.info.clone();
         let leader_data1 = leader.info.clone();
-        let leader_data2 = leader.info.clone();
-        let ledger_path = tmp_ledger("qtc_witness_tx", &alice);
+        let ledger_path = tmp_ledger("qtc_timestamp_cancel_tx", &alice);
 
         let mut config_payer = QtcConfig::default();
-        let mut config_witness = QtcConfig::default();
-        let rpc_port = 11223; // Needs to be distinct known number to not conflict with other tests
-
+        let rpc_port = 13580;
         let server = Fullnode::new_with_transaction_processor(
             leader_keypair,
             transaction_processor,
@@ -1261,31 +2780,33 @@ mod tests {
             false,
             None,
             Some(rpc_port),
+            None,
         );
         sleep(Duration::from_millis(900));
 
         let (sender, receiver) = channel();
         run_local_faucet(alice.keypair(), leader_data.contact_info.ncp, sender);
         config_payer.faucet_addr = receiver.recv().unwrap();
-        config_witness.faucet_addr = config_payer.faucet_addr.clone();
         config_payer.leader = leader_data1;
-        config_witness.leader = leader_data2;
 
         let mut rpc_addr = leader_data.contact_info.ncp;
         rpc_addr.set_port(rpc_port);
-        config_payer.rpc_addr = format!("http://{}", rpc_addr.to_string());
-        config_witness.rpc_addr = config_payer.rpc_addr.clone();
-
-        assert_ne!(config_payer.id.pubkey(), config_witness.id.pubkey());
+        config_payer.rpc_addr = get_rpc_request_str(rpc_addr, false);
 
         let _signature = request_airdrop(&config_payer.faucet_addr, &config_payer.id.pubkey(), 50);
 
+        let date_string = "\"2038-09-19T17:30:59Z\"";
+        let dt: DateTime<Utc> = serde_json::from_str(&date_string).unwrap();
         config_payer.command = QtcCommand::Pay(
             10,
             bob_pubkey,
+            Some(dt),
+            Some(config_payer.id.pubkey()),
             None,
+            Some(config_payer.id.pubkey()),
+            BlockhashQuery::Cluster,
+            false,
             None,
-            Some(vec![config_witness.id.pubkey()]),
             None,
         );
         let sig_response = process_command(&config_payer);
@@ -1298,49 +2819,257 @@ mod tests {
             .expect("base58-encoded public key");
         let process_id = Pubkey::new(&process_id_vec);
 
-        let params = json!(format!("{}", config_payer.id.pubkey()));
-        let config_payer_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
-            .unwrap()
-            .as_i64()
-            .unwrap();
-        assert_eq!(config_payer_balance, 39);
         let params = json!(format!("{}", process_id));
-        let contract_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let contract_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
         assert_eq!(contract_balance, 11);
         let params = json!(format!("{}", bob_pubkey));
-        let recipient_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let recipient_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
         assert_eq!(recipient_balance, 0);
 
-        config_witness.command = QtcCommand::Witness(bob_pubkey, process_id);
-        let sig_response = process_command(&config_witness);
+        // The timestamp (2038) hasn't arrived yet, so cancel the contract and
+        // refund the payer instead of waiting on the oracle.
+        config_payer.command = QtcCommand::Cancel(process_id);
+        let sig_response = process_command(&config_payer);
         assert!(sig_response.is_ok());
 
         let params = json!(format!("{}", config_payer.id.pubkey()));
-        let config_payer_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let config_payer_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
-        assert_eq!(config_payer_balance, 39);
+        assert_eq!(config_payer_balance, 49);
         let params = json!(format!("{}", process_id));
-        let contract_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let contract_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
         assert_eq!(contract_balance, 1);
         let params = json!(format!("{}", bob_pubkey));
-        let recipient_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let recipient_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
+            .unwrap()
+            .as_i64()
+            .unwrap();
+        assert_eq!(recipient_balance, 0);
+
+        server.close().unwrap();
+        remove_dir_all(ledger_path).unwrap();
+    }
+    #[test]
+    fn test_qtc_witness_tx() {
+        let bob_pubkey = Keypair::new().pubkey();
+
+        let mock_rpc = Rc::new(MockRpcClient::new());
+        mock_rpc.set_response("getLastId", json!(format!("{}", Hash::default())));
+        mock_rpc.set_response("getBalance", json!(50));
+        mock_rpc.set_response("sendTransaction", json!(format!("{}", Signature::default())));
+        mock_rpc.set_response("getSignatureStatus", json!("Confirmed"));
+
+        let mut config_payer = QtcConfig::default();
+        config_payer.rpc_client = mock_rpc.clone();
+        let mut config_witness = QtcConfig::default();
+        config_witness.rpc_client = mock_rpc.clone();
+
+        config_payer.command = QtcCommand::Pay(
+            10,
+            bob_pubkey,
+            None,
+            None,
+            Some(vec![config_witness.id.pubkey()]),
+            None,
+            BlockhashQuery::Cluster,
+            false,
+            None,
+            None,
+        );
+        let sig_response = process_command(&config_payer).unwrap();
+
+        // The contract's two funding accounts are created fire-and-forget,
+        // and only the final fin_plan transaction is confirmed.
+        assert_eq!(
+            mock_rpc
+                .requests()
+                .iter()
+                .map(|(method, _)| method.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "getLastId",
+                "sendTransaction",
+                "sendTransaction",
+                "sendTransaction",
+                "getSignatureStatus",
+            ]
+        );
+
+        let object: Value = serde_json::from_str(&sig_response).unwrap();
+        let process_id_str = object.get("processId").unwrap().as_str().unwrap();
+        let process_id_vec = bs58::decode(process_id_str)
+            .into_vec()
+            .expect("base58-encoded public key");
+        let process_id = Pubkey::new(&process_id_vec);
+
+        config_witness.command = QtcCommand::Witness(bob_pubkey, process_id);
+        assert!(process_command(&config_witness).is_ok());
+    }
+    #[test]
+    fn test_qtc_witness_tx_send_failure() {
+        let bob_pubkey = Keypair::new().pubkey();
+
+        let mock_rpc = Rc::new(MockRpcClient::new());
+        mock_rpc.set_response("getLastId", json!(format!("{}", Hash::default())));
+        mock_rpc.set_error("sendTransaction", "node unavailable");
+
+        let mut config_payer = QtcConfig::default();
+        config_payer.rpc_client = mock_rpc.clone();
+        let witness_pubkey = Keypair::new().pubkey();
+
+        config_payer.command = QtcCommand::Pay(
+            10,
+            bob_pubkey,
+            None,
+            None,
+            Some(vec![witness_pubkey]),
+            None,
+            BlockhashQuery::Cluster,
+            false,
+            None,
+            None,
+        );
+        assert!(process_command(&config_payer).is_err());
+    }
+    #[test]
+    #[ignore]
+    fn test_qtc_multisig_witness_tx() {
+        let leader_keypair = Keypair::new();
+        let leader = Node::new_localhost_with_pubkey(leader_keypair.pubkey());
+
+        let alice = Mint::new(10_000_000);
+        let transaction_processor = TransactionProcessor::new(&alice);
+        let bob_pubkey = Keypair::new().pubkey();
+        let leader_data = leader.info.clone();
+        let leader_data1 = leader.info.clone();
+        let leader_data2 = leader.info.clone();
+        let leader_data3 = leader.info.clone();
+        let ledger_path = tmp_ledger("qtc_multisig_witness_tx", &alice);
+
+        let mut config_payer = QtcConfig::default();
+        let mut config_witness0 = QtcConfig::default();
+        let mut config_witness1 = QtcConfig::default();
+        let rpc_port = 11224; // Needs to be distinct known number to not conflict with other tests
+
+        let server = Fullnode::new_with_transaction_processor(
+            leader_keypair,
+            transaction_processor,
+            0,
+            &[],
+            leader,
+            None,
+            &ledger_path,
+            false,
+            None,
+            Some(rpc_port),
+            None,
+        );
+        sleep(Duration::from_millis(900));
+
+        let (sender, receiver) = channel();
+        run_local_faucet(alice.keypair(), leader_data.contact_info.ncp, sender);
+        config_payer.faucet_addr = receiver.recv().unwrap();
+        config_witness0.faucet_addr = config_payer.faucet_addr.clone();
+        config_witness1.faucet_addr = config_payer.faucet_addr.clone();
+        config_payer.leader = leader_data1;
+        config_witness0.leader = leader_data2;
+        config_witness1.leader = leader_data3;
+
+        let mut rpc_addr = leader_data.contact_info.ncp;
+        rpc_addr.set_port(rpc_port);
+        config_payer.rpc_addr = get_rpc_request_str(rpc_addr, false);
+        config_witness0.rpc_addr = config_payer.rpc_addr.clone();
+        config_witness1.rpc_addr = config_payer.rpc_addr.clone();
+
+        let _signature = request_airdrop(&config_payer.faucet_addr, &config_payer.id.pubkey(), 50);
+
+        config_payer.command = QtcCommand::Pay(
+            10,
+            bob_pubkey,
+            None,
+            None,
+            Some(vec![config_witness0.id.pubkey(), config_witness1.id.pubkey()]),
+            None,
+            BlockhashQuery::Cluster,
+            false,
+            None,
+            Some(2),
+        );
+        let sig_response = process_command(&config_payer);
+        assert!(sig_response.is_ok());
+
+        let object: Value = serde_json::from_str(&sig_response.unwrap()).unwrap();
+        let process_id_str = object.get("processId").unwrap().as_str().unwrap();
+        let process_id_vec = bs58::decode(process_id_str)
+            .into_vec()
+            .expect("base58-encoded public key");
+        let process_id = Pubkey::new(&process_id_vec);
+
+        // The first witness signature alone must not release the payment.
+        config_witness0.command = QtcCommand::Witness(bob_pubkey, process_id);
+        let sig_response = process_command(&config_witness0);
+        assert!(sig_response.is_ok());
+
+        let params = json!(format!("{}", bob_pubkey));
+        let recipient_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
+            .unwrap()
+            .as_i64()
+            .unwrap();
+        assert_eq!(recipient_balance, 0);
+
+        // The second witness signature meets the 2-of-2 threshold.
+        config_witness1.command = QtcCommand::Witness(bob_pubkey, process_id);
+        let sig_response = process_command(&config_witness1);
+        assert!(sig_response.is_ok());
+
+        let params = json!(format!("{}", bob_pubkey));
+        let recipient_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
@@ -1351,7 +3080,7 @@ mod tests {
     }
     #[test]
     #[ignore]
-    fn test_qtc_cancel_tx() {
+    fn test_qtc_multisig_cancel_tx() {
         let leader_keypair = Keypair::new();
         let leader = Node::new_localhost_with_pubkey(leader_keypair.pubkey());
 
@@ -1361,11 +3090,11 @@ mod tests {
         let leader_data = leader.info.clone();
         let leader_data1 = leader.info.clone();
         let leader_data2 = leader.info.clone();
-        let ledger_path = tmp_ledger("qtc_cancel_tx", &alice);
+        let ledger_path = tmp_ledger("qtc_multisig_cancel_tx", &alice);
 
         let mut config_payer = QtcConfig::default();
         let mut config_witness = QtcConfig::default();
-        let rpc_port = 13456; // Needs to be distinct known number to not conflict with other tests
+        let rpc_port = 11225; // Needs to be distinct known number to not conflict with other tests
 
         let server = Fullnode::new_with_transaction_processor(
             leader_keypair,
@@ -1378,6 +3107,7 @@ mod tests {
             false,
             None,
             Some(rpc_port),
+            None,
         );
         sleep(Duration::from_millis(900));
 
@@ -1390,21 +3120,25 @@ mod tests {
 
         let mut rpc_addr = leader_data.contact_info.ncp;
         rpc_addr.set_port(rpc_port);
-        config_payer.rpc_addr = format!("http://{}", rpc_addr.to_string());
+        config_payer.rpc_addr = get_rpc_request_str(rpc_addr, false);
         config_witness.rpc_addr = config_payer.rpc_addr.clone();
 
-        assert_ne!(config_payer.id.pubkey(), config_witness.id.pubkey());
-
         let _signature = request_airdrop(&config_payer.faucet_addr, &config_payer.id.pubkey(), 50);
 
-        // Make transaction (from config_payer to bob_pubkey) requiring witness signature from config_witness
+        // A 2-of-2 multisig that the threshold is never reached for; the
+        // payer should still be able to cancel and reclaim the funds.
+        let other_witness = Keypair::new().pubkey();
         config_payer.command = QtcCommand::Pay(
             10,
             bob_pubkey,
             None,
             None,
-            Some(vec![config_witness.id.pubkey()]),
+            Some(vec![config_witness.id.pubkey(), other_witness]),
             Some(config_payer.id.pubkey()),
+            BlockhashQuery::Cluster,
+            false,
+            None,
+            Some(2),
         );
         let sig_response = process_command(&config_payer);
         assert!(sig_response.is_ok());
@@ -1416,54 +3150,295 @@ mod tests {
             .expect("base58-encoded public key");
         let process_id = Pubkey::new(&process_id_vec);
 
+        config_witness.command = QtcCommand::Witness(bob_pubkey, process_id);
+        let sig_response = process_command(&config_witness);
+        assert!(sig_response.is_ok());
+
+        // Only one of the two required signatures has landed, so cancel
+        // instead of waiting on the other witness.
+        config_payer.command = QtcCommand::Cancel(process_id);
+        let sig_response = process_command(&config_payer);
+        assert!(sig_response.is_ok());
+
         let params = json!(format!("{}", config_payer.id.pubkey()));
-        let config_payer_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let config_payer_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
-        assert_eq!(config_payer_balance, 39);
-        let params = json!(format!("{}", process_id));
-        let contract_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
-            .unwrap()
-            .as_i64()
-            .unwrap();
-        assert_eq!(contract_balance, 11);
+        assert_eq!(config_payer_balance, 49);
         let params = json!(format!("{}", bob_pubkey));
-        let recipient_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let recipient_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
         assert_eq!(recipient_balance, 0);
 
-        // Sign transaction by config_witness
+        server.close().unwrap();
+        remove_dir_all(ledger_path).unwrap();
+    }
+    #[test]
+    fn test_qtc_cancel_tx() {
+        let bob_pubkey = Keypair::new().pubkey();
+
+        let mock_rpc = Rc::new(MockRpcClient::new());
+        mock_rpc.set_response("getLastId", json!(format!("{}", Hash::default())));
+        mock_rpc.set_response("getBalance", json!(50));
+        mock_rpc.set_response("sendTransaction", json!(format!("{}", Signature::default())));
+        mock_rpc.set_response("getSignatureStatus", json!("Confirmed"));
+
+        let mut config_payer = QtcConfig::default();
+        config_payer.rpc_client = mock_rpc.clone();
+        let witness_pubkey = Keypair::new().pubkey();
+
+        // Make a cancelable transaction (from config_payer to bob_pubkey)
+        // requiring a witness signature, so it can later be canceled.
+        config_payer.command = QtcCommand::Pay(
+            10,
+            bob_pubkey,
+            None,
+            None,
+            Some(vec![witness_pubkey]),
+            Some(config_payer.id.pubkey()),
+            BlockhashQuery::Cluster,
+            false,
+            None,
+            None,
+        );
+        let sig_response = process_command(&config_payer).unwrap();
+
+        let object: Value = serde_json::from_str(&sig_response).unwrap();
+        let process_id_str = object.get("processId").unwrap().as_str().unwrap();
+        let process_id_vec = bs58::decode(process_id_str)
+            .into_vec()
+            .expect("base58-encoded public key");
+        let process_id = Pubkey::new(&process_id_vec);
+
         config_payer.command = QtcCommand::Cancel(process_id);
-        let sig_response = process_command(&config_payer);
+        assert!(process_command(&config_payer).is_ok());
+        assert_eq!(
+            mock_rpc.requests().last().unwrap().0,
+            "getSignatureStatus"
+        );
+    }
+    #[test]
+    fn test_qtc_cancel_tx_confirmation_failure() {
+        let process_id = Keypair::new().pubkey();
+
+        let mock_rpc = Rc::new(MockRpcClient::new());
+        mock_rpc.set_response("getLastId", json!(format!("{}", Hash::default())));
+        mock_rpc.set_response("sendTransaction", json!(format!("{}", Signature::default())));
+        mock_rpc.set_response("getSignatureStatus", json!("ProgramRuntimeError"));
+
+        let mut config_payer = QtcConfig::default();
+        config_payer.rpc_client = mock_rpc.clone();
+
+        config_payer.command = QtcCommand::Cancel(process_id);
+        assert!(process_command(&config_payer).is_err());
+    }
+    #[test]
+    #[ignore]
+    fn test_qtc_sign_only_reply_tx() {
+        let leader_keypair = Keypair::new();
+        let leader = Node::new_localhost_with_pubkey(leader_keypair.pubkey());
+
+        let alice = Mint::new(10_000_000);
+        let transaction_processor = TransactionProcessor::new(&alice);
+        let bob_pubkey = Keypair::new().pubkey();
+        let leader_data = leader.info.clone();
+        let leader_data1 = leader.info.clone();
+        let leader_data2 = leader.info.clone();
+        let ledger_path = tmp_ledger("qtc_sign_only_reply_tx", &alice);
+
+        // config_payer holds the paying identity and signs offline; the
+        // funds never touch a QtcConfig that can reach the cluster.
+        // config_broadcaster, with a different identity, receives the reply
+        // string and is the one that actually finalizes and sends it.
+        let mut config_payer = QtcConfig::default();
+        let mut config_broadcaster = QtcConfig::default();
+        let rpc_port = 11226; // Needs to be distinct known number to not conflict with other tests
+
+        let server = Fullnode::new_with_transaction_processor(
+            leader_keypair,
+            transaction_processor,
+            0,
+            &[],
+            leader,
+            None,
+            &ledger_path,
+            false,
+            None,
+            Some(rpc_port),
+            None,
+        );
+        sleep(Duration::from_millis(900));
+
+        let (sender, receiver) = channel();
+        run_local_faucet(alice.keypair(), leader_data.contact_info.ncp, sender);
+        config_payer.faucet_addr = receiver.recv().unwrap();
+        config_broadcaster.faucet_addr = config_payer.faucet_addr.clone();
+        config_payer.leader = leader_data1;
+        config_broadcaster.leader = leader_data2;
+
+        let mut rpc_addr = leader_data.contact_info.ncp;
+        rpc_addr.set_port(rpc_port);
+        config_payer.rpc_addr = get_rpc_request_str(rpc_addr, false);
+        config_broadcaster.rpc_addr = config_payer.rpc_addr.clone();
+
+        assert_ne!(config_payer.id.pubkey(), config_broadcaster.id.pubkey());
+
+        let _signature = request_airdrop(&config_payer.faucet_addr, &config_payer.id.pubkey(), 50);
+
+        config_payer.command = QtcCommand::Pay(
+            10, bob_pubkey, None, None, None, None, BlockhashQuery::Cluster, true, None, None,
+        );
+        let reply = process_command(&config_payer).unwrap();
+
+        let (blockhash, pubkey_sigs) = parse_sign_only_reply_string(&reply).unwrap();
+        let (signer_pubkey, _) = pubkey_sigs[0];
+        let signature = presigner_from_pubkey_sigs(&pubkey_sigs, &signer_pubkey).unwrap();
+
+        config_broadcaster.command = QtcCommand::Pay(
+            10,
+            bob_pubkey,
+            None,
+            None,
+            None,
+            None,
+            BlockhashQuery::Static(blockhash),
+            false,
+            Some((signer_pubkey, signature)),
+            None,
+        );
+        let sig_response = process_command(&config_broadcaster);
         assert!(sig_response.is_ok());
 
-        let params = json!(format!("{}", config_payer.id.pubkey()));
-        let config_payer_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
-            .unwrap()
-            .as_i64()
-            .unwrap();
-        assert_eq!(config_payer_balance, 49);
-        let params = json!(format!("{}", process_id));
-        let contract_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let params = json!(format!("{}", bob_pubkey));
+        let recipient_balance = config_payer.rpc_client.make_rpc_request(
+            &config_payer.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
-        assert_eq!(contract_balance, 1);
+        assert_eq!(recipient_balance, 10);
+
+        server.close().unwrap();
+        remove_dir_all(ledger_path).unwrap();
+    }
+    #[test]
+    #[ignore]
+    fn test_qtc_nonce_account_tx() {
+        let leader_keypair = Keypair::new();
+        let leader = Node::new_localhost_with_pubkey(leader_keypair.pubkey());
+
+        let alice = Mint::new(10_000_000);
+        let transaction_processor = TransactionProcessor::new(&alice);
+        let bob_pubkey = Keypair::new().pubkey();
+        let nonce_account = Keypair::new().pubkey();
+        let leader_data = leader.info.clone();
+        let ledger_path = tmp_ledger("qtc_nonce_account_tx", &alice);
+
+        let mut config = QtcConfig::default();
+        let rpc_port = 11227; // Needs to be distinct known number to not conflict with other tests
+
+        let server = Fullnode::new_with_transaction_processor(
+            leader_keypair,
+            transaction_processor,
+            0,
+            &[],
+            leader,
+            None,
+            &ledger_path,
+            false,
+            None,
+            Some(rpc_port),
+            None,
+        );
+        sleep(Duration::from_millis(900));
+
+        let (sender, receiver) = channel();
+        run_local_faucet(alice.keypair(), leader_data.contact_info.ncp, sender);
+        config.faucet_addr = receiver.recv().unwrap();
+        config.leader = leader_data.clone();
+
+        let mut rpc_addr = leader_data.contact_info.ncp;
+        rpc_addr.set_port(rpc_port);
+        config.rpc_addr = get_rpc_request_str(rpc_addr, false);
+
+        let _signature = request_airdrop(&config.faucet_addr, &config.id.pubkey(), 50);
+
+        config.command = QtcCommand::CreateNonceAccount(nonce_account, config.id.pubkey(), 10);
+        process_command(&config).unwrap();
+        poll_for_balance(&config, &nonce_account, 10).unwrap();
+
+        // The stored blockhash is fixed at creation time, unlike the
+        // cluster's live last_id, which keeps advancing as the ledger
+        // grows. Sign a payment against the nonce account's hash now...
+        let nonce_hash = get_nonce_hash(&config, &nonce_account).unwrap();
+
+        config.command = QtcCommand::Pay(
+            10,
+            bob_pubkey,
+            None,
+            None,
+            None,
+            None,
+            BlockhashQuery::Nonce(nonce_account),
+            true,
+            None,
+            None,
+        );
+        let reply = process_command(&config).unwrap();
+        let (blockhash, pubkey_sigs) = parse_sign_only_reply_string(&reply).unwrap();
+        assert_eq!(blockhash, nonce_hash);
+        let (signer_pubkey, _) = pubkey_sigs[0];
+        let signature = presigner_from_pubkey_sigs(&pubkey_sigs, &signer_pubkey).unwrap();
+
+        // ...and let enough time pass, and the cluster's last_id move on,
+        // that a transaction signed against it would ordinarily have
+        // expired by the time it's finally submitted below.
+        sleep(Duration::from_millis(900));
+        let live_last_id = get_last_id(&config).unwrap();
+        assert_ne!(live_last_id, nonce_hash);
+
+        config.command = QtcCommand::Pay(
+            10,
+            bob_pubkey,
+            None,
+            None,
+            None,
+            None,
+            BlockhashQuery::Static(blockhash),
+            false,
+            Some((signer_pubkey, signature)),
+            None,
+        );
+        let sig_response = process_command(&config);
+        assert!(sig_response.is_ok());
+
         let params = json!(format!("{}", bob_pubkey));
-        let recipient_balance = QtcRpcRequest::GetBalance
-            .make_rpc_request(&config_payer.rpc_addr, 1, Some(params))
+        let recipient_balance = config.rpc_client.make_rpc_request(
+            &config.rpc_addr,
+            QtcRpcRequest::GetBalance,
+            1,
+            Some(params),
+        )
             .unwrap()
             .as_i64()
             .unwrap();
-        assert_eq!(recipient_balance, 0);
+        assert_eq!(recipient_balance, 10);
 
         server.close().unwrap();
         remove_dir_all(ledger_path).unwrap();
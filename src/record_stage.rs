@@ -0,0 +1,69 @@
+//! The `record_stage` module provides a dedicated thread for ticking the
+//! shared PoH hash chain at a configured rate, independent of however many
+//! `TransactionProcessoringStage` worker threads are recording transaction
+//! batches into that same chain. Separating the two means the tick rate
+//! stays steady even while the number of processing threads scales with
+//! the machine.
+
+use pod_recorder::PodRecorder;
+use result::{Error, Result};
+use service::Service;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, sleep, Builder, JoinHandle};
+use std::time::Duration;
+use transaction_processoring_stage::Config;
+
+pub struct RecordStage {
+    thread_hdl: JoinHandle<()>,
+}
+
+impl RecordStage {
+    /// Spawn a thread that ticks `pod` at the rate described by `config`
+    /// until `exit` is set. Idle ticks fold their hash count into whichever
+    /// entry a `TransactionProcessoringStage` next records on the same
+    /// `pod`, so entry height stays monotonic across ticks with no work.
+    pub fn new(pod: PodRecorder, config: Config, exit: Arc<AtomicBool>) -> Self {
+        let thread_hdl = Builder::new()
+            .name("hypercube-record-stage".to_string())
+            .spawn(move || {
+                if let Err(e) = Self::tick_producer(&pod, &config, &exit) {
+                    match e {
+                        Error::SendError => (),
+                        _ => error!("hypercube-record-stage unexpected error {:?}", e),
+                    }
+                }
+                debug!("record stage exiting");
+                exit.store(true, Ordering::Relaxed);
+            }).unwrap();
+        RecordStage { thread_hdl }
+    }
+
+    fn tick_producer(pod: &PodRecorder, config: &Config, exit: &AtomicBool) -> Result<()> {
+        loop {
+            match *config {
+                Config::Tick(num) => {
+                    for _ in 0..num {
+                        pod.hash();
+                    }
+                }
+                Config::Sleep(duration) => {
+                    sleep(duration);
+                }
+            }
+            pod.tick();
+            if exit.load(Ordering::Relaxed) {
+                debug!("record stage exited");
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Service for RecordStage {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        self.thread_hdl.join()
+    }
+}
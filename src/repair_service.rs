@@ -0,0 +1,148 @@
+//! While a node is catching up (or a leader never got around to filling
+//! every slot), `SharedWindow` can be left with gaps below its leading,
+//! contiguous edge. `RepairService` is the half of the TVU that notices
+//! those gaps and does something about them: on a timer it asks
+//! `SharedWindow` which indices below the leading edge are still missing,
+//! and for each one that hasn't been re-requested too recently it asks a
+//! peer drawn from `BlockThread`'s broadcast table to resend the blob.
+//! Peers that receive a `WindowIndexRequest` and still have that blob in
+//! their own window serve it straight back over the same socket.
+
+use bincode::{deserialize, serialize};
+use blockthread::BlockThread;
+use result::Result;
+use service::Service;
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, Builder, JoinHandle};
+use std::time::{Duration, Instant};
+use window::SharedWindow;
+
+/// How often `RepairService` checks the window for gaps and sends requests.
+const REPAIR_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Minimum time to wait before re-requesting the same missing index, so a
+/// slow peer doesn't get hammered with duplicate requests every tick.
+const REPAIR_BACKOFF: Duration = Duration::from_millis(400);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum RepairRequest {
+    WindowIndexRequest(u64),
+}
+
+pub struct RepairService {
+    thread_hdls: Vec<JoinHandle<()>>,
+    exit: Arc<AtomicBool>,
+}
+
+impl RepairService {
+    pub fn new(
+        window: SharedWindow,
+        blockthread: Arc<RwLock<BlockThread>>,
+        repair_socket: Arc<UdpSocket>,
+    ) -> Self {
+        let exit = Arc::new(AtomicBool::new(false));
+        let t_request = Self::run_requester(window.clone(), blockthread, repair_socket.clone(), exit.clone());
+        let t_respond = Self::run_responder(window, repair_socket, exit.clone());
+        RepairService {
+            thread_hdls: vec![t_request, t_respond],
+            exit,
+        }
+    }
+
+    /// Periodically asks the window for gaps below its leading edge and
+    /// requests each one, throttled so the same index isn't re-requested
+    /// more often than `REPAIR_BACKOFF`.
+    fn run_requester(
+        window: SharedWindow,
+        blockthread: Arc<RwLock<BlockThread>>,
+        repair_socket: Arc<UdpSocket>,
+        exit: Arc<AtomicBool>,
+    ) -> JoinHandle<()> {
+        Builder::new()
+            .name("hypercube-repair-service".to_string())
+            .spawn(move || {
+                let mut last_requested: HashMap<u64, Instant> = HashMap::new();
+                loop {
+                    if exit.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    let missing = window.read().unwrap().repair_requests();
+                    let now = Instant::now();
+                    let due: Vec<u64> = missing
+                        .into_iter()
+                        .filter(|index| {
+                            last_requested
+                                .get(index)
+                                .map(|requested_at| now.duration_since(*requested_at) >= REPAIR_BACKOFF)
+                                .unwrap_or(true)
+                        }).collect();
+
+                    if !due.is_empty() {
+                        let broadcast_table = blockthread.read().unwrap().compute_broadcast_table();
+                        for index in due {
+                            if let Some(peer) = broadcast_table.get(index as usize % broadcast_table.len().max(1)) {
+                                let request = RepairRequest::WindowIndexRequest(index);
+                                if let Ok(data) = serialize(&request) {
+                                    let _ = repair_socket.send_to(&data, peer.contact_info.repair);
+                                }
+                                last_requested.insert(index, now);
+                            }
+                        }
+                    }
+
+                    thread::sleep(REPAIR_INTERVAL);
+                }
+            }).unwrap()
+    }
+
+    /// Serves `WindowIndexRequest`s for blobs this node still holds.
+    fn run_responder(window: SharedWindow, repair_socket: Arc<UdpSocket>, exit: Arc<AtomicBool>) -> JoinHandle<()> {
+        repair_socket
+            .set_read_timeout(Some(REPAIR_INTERVAL))
+            .expect("set repair socket read timeout");
+        Builder::new()
+            .name("hypercube-repair-responder".to_string())
+            .spawn(move || loop {
+                if exit.load(Ordering::Relaxed) {
+                    break;
+                }
+                let _ = Self::process_request(&window, &repair_socket);
+            }).unwrap()
+    }
+
+    fn process_request(window: &SharedWindow, repair_socket: &Arc<UdpSocket>) -> Result<()> {
+        let mut buf = [0; 1024];
+        let (size, from) = repair_socket.recv_from(&mut buf)?;
+        if let Ok(RepairRequest::WindowIndexRequest(index)) = deserialize(&buf[..size]) {
+            if let Some(blob) = window.read().unwrap().get_blob(index) {
+                let data = blob.read().unwrap();
+                let _ = repair_socket.send_to(&data.data[..data.meta.size], from);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn exit(&self) {
+        self.exit.store(true, Ordering::Relaxed);
+    }
+
+    pub fn close(self) -> thread::Result<()> {
+        self.exit();
+        self.join()
+    }
+}
+
+impl Service for RepairService {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        for thread_hdl in self.thread_hdls {
+            thread_hdl.join()?;
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,16 @@
  
 
-use transaction_processor::TransactionProcessor;
+use transaction_processor::{TransactionProcessor, TransactionProcessorError};
 use counter::Counter;
 use blockthread::BlockThread;
-use entry::EntryReceiver;
-use ledger::{Block, LedgerWriter};
+use entry::{Entry, EntryReceiver, EntrySender};
+use hash::Hash;
+use ledger::Block;
 use log::Level;
 use result::{Error, Result};
 use service::Service;
 use signature::Keypair;
+use sigverify;
 use std::net::UdpSocket;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::channel;
@@ -18,7 +20,22 @@ use std::thread::{self, Builder, JoinHandle};
 use std::time::Duration;
 use std::time::Instant;
 use streamer::{responder, BlobSender};
-use vote_stage::send_validator_vote;
+use vote_stage::{send_validator_vote, Tower};
+
+/// Drop any entries whose transactions don't verify, via the same
+/// `sigverify` signature-checking path the leader uses before admitting
+/// transactions to the bank. Returns the entries that passed.
+fn verify_entries(entries: Vec<Entry>) -> Vec<Entry> {
+    let mut verified = Vec::with_capacity(entries.len());
+    for entry in entries {
+        if sigverify::verify_transactions(&entry.transactions) {
+            verified.push(entry);
+        } else {
+            inc_new_counter_info!("replicate-verify-failures", 1);
+        }
+    }
+    verified
+}
 
  
 struct Finalizer {
@@ -37,31 +54,101 @@ impl Drop for Finalizer {
     }
 }
 
+#[derive(Default)]
+struct ReplicateStateInner {
+    tick_height: u64,
+    last_entry_id: Hash,
+}
+
+/// Shared handle onto the last entry `ReplicateStage` applied, so that if
+/// this node is handed the leader role, `TxSigner::join` can report the
+/// tick height and entry id the new leader should resume PoH generation
+/// from, in addition to the entry height `RetransmitStage` already tracks.
+#[derive(Clone, Default)]
+pub struct ReplicateState {
+    state: Arc<RwLock<ReplicateStateInner>>,
+}
+
+impl ReplicateState {
+    pub fn new() -> Self {
+        ReplicateState::default()
+    }
+
+    pub fn tick_height(&self) -> u64 {
+        self.state.read().unwrap().tick_height
+    }
+
+    pub fn last_entry_id(&self) -> Hash {
+        self.state.read().unwrap().last_entry_id
+    }
+
+    fn update(&self, tick_height: u64, last_entry_id: Hash) {
+        let mut state = self.state.write().unwrap();
+        state.tick_height = tick_height;
+        state.last_entry_id = last_entry_id;
+    }
+}
+
 pub struct ReplicateStage {
     thread_hdls: Vec<JoinHandle<()>>,
 }
 
 impl ReplicateStage {
-   
+
     fn replicate_requests(
         transaction_processor: &Arc<TransactionProcessor>,
         blockthread: &Arc<RwLock<BlockThread>>,
         window_receiver: &EntryReceiver,
-        ledger_writer: Option<&mut LedgerWriter>,
+        ledger_entry_sender: Option<&EntrySender>,
+        storage_entry_sender: Option<&EntrySender>,
+        replicate_state: &ReplicateState,
         keypair: &Arc<Keypair>,
         vote_blob_sender: Option<&BlobSender>,
+        tower: &mut Tower,
+        sigverify_disabled: bool,
     ) -> Result<()> {
         let timer = Duration::new(1, 0);
- 
+
         let mut entries = window_receiver.recv_timeout(timer)?;
         while let Ok(mut more) = window_receiver.try_recv() {
             entries.append(&mut more);
         }
 
+        // Entries received over the network come from an untrusted leader,
+        // so verify their transaction signatures before they ever reach the
+        // bank. Locally produced entries (sigverify_disabled) skip this.
+        let entries = if sigverify_disabled {
+            entries
+        } else {
+            verify_entries(entries)
+        };
+
+        // Make sure the entries still form an unbroken PoH hash chain from
+        // the last entry this stage applied before trusting their contents;
+        // a leader (or a man in the middle) could otherwise splice in
+        // transactions against a hash chain that never actually happened.
+        if !entries.is_empty() && !entries.verify(&replicate_state.last_entry_id()) {
+            inc_new_counter_info!("replicate-chain-failures", 1);
+            Err(TransactionProcessorError::LedgerVerificationFailed)?;
+        }
+
         let res = transaction_processor.process_entries(&entries);
 
+        if let Some(last_entry) = entries.last() {
+            let tick_height = replicate_state.tick_height()
+                + entries.iter().map(|entry| entry.num_hashes).sum::<u64>();
+            replicate_state.update(tick_height, last_entry.id);
+        }
+
         if let Some(sender) = vote_blob_sender {
-            send_validator_vote(transaction_processor, keypair, blockthread, sender)?;
+            send_validator_vote(
+                transaction_processor,
+                keypair,
+                blockthread,
+                sender,
+                tower,
+                replicate_state.tick_height(),
+            )?;
         }
 
         {
@@ -74,9 +161,17 @@ impl ReplicateStage {
             entries.iter().map(|x| x.transactions.len()).sum()
         );
 
-        // TODO: move this to another stage?
-        if let Some(ledger_writer) = ledger_writer {
-            ledger_writer.write_entries(entries)?;
+        // Feed the same processed entries to the storage stage so it can
+        // sample them for a proof-of-replication, before they move on to
+        // the ledger-writing stage below.
+        if let Some(entry_sender) = storage_entry_sender {
+            entry_sender.send(entries.clone())?;
+        }
+
+        // Ledger persistence runs on its own stage so disk I/O never stalls
+        // transaction replay; just hand the processed entries off.
+        if let Some(entry_sender) = ledger_entry_sender {
+            entry_sender.send(entries)?;
         }
 
         res?;
@@ -88,14 +183,16 @@ impl ReplicateStage {
         transaction_processor: Arc<TransactionProcessor>,
         blockthread: Arc<RwLock<BlockThread>>,
         window_receiver: EntryReceiver,
-        ledger_path: Option<&str>,
+        ledger_entry_sender: Option<EntrySender>,
+        storage_entry_sender: Option<EntrySender>,
+        replicate_state: ReplicateState,
         exit: Arc<AtomicBool>,
+        sigverify_disabled: bool,
     ) -> Self {
         let (vote_blob_sender, vote_blob_receiver) = channel();
         let send = UdpSocket::bind("0.0.0.0:0").expect("bind");
         let t_responder = responder("replicate_stage", Arc::new(send), vote_blob_receiver);
 
-        let mut ledger_writer = ledger_path.map(|p| LedgerWriter::open(p, false).unwrap());
         let keypair = Arc::new(keypair);
 
         let t_replicate = Builder::new()
@@ -104,6 +201,7 @@ impl ReplicateStage {
                 let _exit = Finalizer::new(exit);;
                 let now = Instant::now();
                 let mut next_vote_secs = 1;
+                let mut tower = Tower::new();
                 loop {
                     // Only vote once a second.
                     let vote_sender = if now.elapsed().as_secs() > next_vote_secs {
@@ -117,9 +215,13 @@ impl ReplicateStage {
                         &transaction_processor,
                         &blockthread,
                         &window_receiver,
-                        ledger_writer.as_mut(),
+                        ledger_entry_sender.as_ref(),
+                        storage_entry_sender.as_ref(),
+                        &replicate_state,
                         &keypair,
                         vote_sender,
+                        &mut tower,
+                        sigverify_disabled,
                     ) {
                         match e {
                             Error::RecvTimeoutError(RecvTimeoutError::Disconnected) => break,
@@ -0,0 +1,235 @@
+//! `rpc` answers one-shot client queries over HTTP JSON-RPC, backed directly
+//! by an `Arc<TransactionProcessor>`. Because the processor is shared rather
+//! than snapshotted, `JsonRpcService` keeps serving correct answers straight
+//! through a leader/validator rotation with no restart, unlike the old
+//! `Rpu`/`RequestProcessor` pair it replaces, which had to be torn down and
+//! rebuilt against the new bank on every rotation.
+
+use accounts::ProgramAccountsFilter;
+use bs58;
+use bincode::{deserialize, serialize};
+use faucet::DroneRequest;
+use jsonrpc_core::{Error, ErrorCode, IoHandler, Result};
+use jsonrpc_http_server::ServerBuilder;
+use jsonrpc_macros::build_rpc_trait;
+use signature::Signature;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, sleep, Builder, JoinHandle};
+use std::time::Duration;
+use service::Service;
+use transaction_processor::TransactionProcessor;
+use xpz_program_interface::account::Account;
+use xpz_program_interface::pubkey::Pubkey;
+
+pub const RPC_PORT: u16 = 8899;
+
+fn pubkey_from_bs58(input: &str) -> Result<Pubkey> {
+    bs58::decode(input)
+        .into_vec()
+        .map(|bytes| Pubkey::new(&bytes))
+        .map_err(|_| Error::new(ErrorCode::InvalidParams))
+}
+
+fn signature_from_bs58(input: &str) -> Result<Signature> {
+    bs58::decode(input)
+        .into_vec()
+        .map(|bytes| Signature::new(&bytes))
+        .map_err(|_| Error::new(ErrorCode::InvalidParams))
+}
+
+build_rpc_trait! {
+    pub trait RpcSol {
+        #[rpc(name = "getBalance")]
+        fn get_balance(&self, String) -> Result<i64>;
+
+        #[rpc(name = "getAccountInfo")]
+        fn get_account_info(&self, String) -> Result<Option<Account>>;
+
+        #[rpc(name = "getProgramAccounts")]
+        fn get_program_accounts(
+            &self,
+            String,
+            Option<Vec<ProgramAccountsFilter>>
+        ) -> Result<Vec<(String, Account)>>;
+
+        #[rpc(name = "getTransactionCount")]
+        fn get_transaction_count(&self) -> Result<u64>;
+
+        #[rpc(name = "getLastId")]
+        fn get_last_id(&self) -> Result<String>;
+
+        #[rpc(name = "confirmTransaction")]
+        fn confirm_transaction(&self, String) -> Result<bool>;
+
+        #[rpc(name = "getFinality")]
+        fn get_finality(&self) -> Result<usize>;
+
+        #[rpc(name = "sendTransaction")]
+        fn send_transaction(&self, Vec<u8>) -> Result<String>;
+
+        #[rpc(name = "requestAirdrop")]
+        fn request_airdrop(&self, String, u64) -> Result<String>;
+
+        #[rpc(name = "getRecommendedFee")]
+        fn get_recommended_fee(&self, Option<u8>) -> Result<i64>;
+    }
+}
+
+struct RpcSolImpl {
+    transaction_processor: Arc<TransactionProcessor>,
+    tx_creator_addr: SocketAddr,
+    drone_addr: SocketAddr,
+}
+
+impl RpcSol for RpcSolImpl {
+    fn get_balance(&self, id: String) -> Result<i64> {
+        let pubkey = pubkey_from_bs58(&id)?;
+        Ok(self.transaction_processor.get_balance(&pubkey))
+    }
+
+    fn get_account_info(&self, id: String) -> Result<Option<Account>> {
+        let pubkey = pubkey_from_bs58(&id)?;
+        Ok(self.transaction_processor.get_account(&pubkey))
+    }
+
+    /// Enumerate every account owned by `program_id`, optionally narrowed by
+    /// `filters`, so a client can discover a deployed program's state in
+    /// one round trip instead of guessing keys. Errors out (rather than
+    /// truncating) if the match set exceeds `MAX_PROGRAM_ACCOUNTS`.
+    fn get_program_accounts(
+        &self,
+        program_id: String,
+        filters: Option<Vec<ProgramAccountsFilter>>,
+    ) -> Result<Vec<(String, Account)>> {
+        let program_id = pubkey_from_bs58(&program_id)?;
+        let filters = filters.unwrap_or_else(Vec::new);
+        let accounts = self
+            .transaction_processor
+            .get_program_accounts(&program_id, &filters)
+            .map_err(|_| Error::new(ErrorCode::InvalidParams))?;
+        Ok(accounts
+            .into_iter()
+            .map(|(pubkey, account)| (bs58::encode(pubkey.as_ref()).into_string(), account))
+            .collect())
+    }
+
+    fn get_transaction_count(&self) -> Result<u64> {
+        Ok(self.transaction_processor.transaction_count() as u64)
+    }
+
+    fn get_last_id(&self) -> Result<String> {
+        Ok(bs58::encode(self.transaction_processor.last_id().as_ref()).into_string())
+    }
+
+    fn confirm_transaction(&self, id: String) -> Result<bool> {
+        let signature = signature_from_bs58(&id)?;
+        Ok(self.transaction_processor.has_signature(&signature))
+    }
+
+    fn get_finality(&self) -> Result<usize> {
+        Ok(self.transaction_processor.finality())
+    }
+
+    /// Relay the raw signed transaction bytes to this node's own TPU, same
+    /// as `faucet::Drone::send_airdrop` does for its own transactions;
+    /// `FetchStage::new_with_forwarder` is what actually gets them to the
+    /// current leader when this node isn't leading.
+    fn send_transaction(&self, tx_bytes: Vec<u8>) -> Result<String> {
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(|_| Error::internal_error())?;
+        socket
+            .send_to(&tx_bytes, self.tx_creator_addr)
+            .map_err(|_| Error::internal_error())?;
+        let signature_bytes = &tx_bytes[..64.min(tx_bytes.len())];
+        Ok(bs58::encode(signature_bytes).into_string())
+    }
+
+    /// The fee at `percentile` (default: the median) among recently paid
+    /// transaction fees, so a client can size its own fee against current
+    /// network demand instead of hardcoding one.
+    fn get_recommended_fee(&self, percentile: Option<u8>) -> Result<i64> {
+        Ok(self
+            .transaction_processor
+            .get_recommended_fee(percentile.unwrap_or(50)))
+    }
+
+    /// Forward an airdrop request to the drone over the same
+    /// length-prefix-free TCP protocol `qtc::request_airdrop` speaks, and
+    /// hand back the drone's signature for the client to confirm.
+    fn request_airdrop(&self, id: String, tokens: u64) -> Result<String> {
+        let pubkey = pubkey_from_bs58(&id)?;
+        let req = DroneRequest::GetAirdrop {
+            airdrop_request_amount: tokens,
+            client_pubkey: pubkey,
+        };
+        let bytes = serialize(&req).map_err(|_| Error::internal_error())?;
+
+        let mut stream = TcpStream::connect(self.drone_addr).map_err(|_| Error::internal_error())?;
+        stream.write_all(&bytes).map_err(|_| Error::internal_error())?;
+        let mut buffer = [0; 64];
+        stream
+            .read_exact(&mut buffer)
+            .map_err(|_| Error::internal_error())?;
+        let signature: Signature = deserialize(&buffer).map_err(|_| Error::internal_error())?;
+        Ok(bs58::encode(signature.as_ref()).into_string())
+    }
+}
+
+pub struct JsonRpcService {
+    thread_hdl: JoinHandle<()>,
+    exit: Arc<AtomicBool>,
+}
+
+impl JsonRpcService {
+    pub fn new(
+        transaction_processor: &Arc<TransactionProcessor>,
+        tx_creator_addr: SocketAddr,
+        drone_addr: SocketAddr,
+        rpc_addr: SocketAddr,
+        exit: Arc<AtomicBool>,
+    ) -> Self {
+        let request_processor = RpcSolImpl {
+            transaction_processor: transaction_processor.clone(),
+            tx_creator_addr,
+            drone_addr,
+        };
+        let mut io = IoHandler::new();
+        io.extend_with(request_processor.to_delegate());
+
+        let thread_exit = exit.clone();
+        let thread_hdl = Builder::new()
+            .name("hypercube-jsonrpc-service".to_string())
+            .spawn(move || {
+                let server = ServerBuilder::new(io)
+                    .start_http(&rpc_addr)
+                    .expect("start JSON-RPC http server");
+
+                while !thread_exit.load(Ordering::Relaxed) {
+                    sleep(Duration::from_millis(100));
+                }
+                server.close();
+            }).unwrap();
+
+        info!("rpc service listening on {}", rpc_addr);
+        JsonRpcService { thread_hdl, exit }
+    }
+
+    pub fn exit(&self) {
+        self.exit.store(true, Ordering::Relaxed);
+    }
+
+    pub fn close(self) -> thread::Result<()> {
+        self.exit();
+        self.join()
+    }
+}
+
+impl Service for JsonRpcService {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        self.thread_hdl.join()
+    }
+}
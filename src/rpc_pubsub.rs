@@ -0,0 +1,257 @@
+//! `rpc_pubsub` is the WebSocket companion to `rpc::JsonRpcService`. Where
+//! `JsonRpcService` only answers one-shot queries over HTTP, `PubSubService`
+//! lets a client open a persistent connection and subscribe to (a) a
+//! transaction signature's confirmation or (b) an account's latest value.
+//! Each subscription registers straight into `TransactionProcessor`'s
+//! existing `add_signature_subscription`/`add_account_subscription` hooks,
+//! so a subscriber is pushed a notification the instant `TransactionProcessor`
+//! commits the entry that resolves it, instead of polling `JsonRpcService`
+//! for the same answer.
+
+use jsonrpc_core::{Error, ErrorCode, Result};
+use jsonrpc_core::futures::Future;
+use jsonrpc_macros::pubsub;
+use jsonrpc_pubsub::{PubSubHandler, Session, SubscriptionId};
+use jsonrpc_ws_server::{RequestContext, Server, ServerBuilder};
+use service::Service;
+use signature::Signature;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, sleep, Builder, JoinHandle};
+use std::time::Duration;
+use transaction_processor::{Sink, Status, TransactionProcessor};
+use xpz_program_interface::account::Account;
+use xpz_program_interface::pubkey::Pubkey;
+
+/// How often `SignatureReaperService` sweeps `TransactionProcessor` for
+/// signature subscriptions that outlived `SIGNATURE_SUBSCRIPTION_TTL_MS`.
+const SIGNATURE_REAP_INTERVAL_MS: u64 = 5_000;
+
+/// Bridges one live subscription back to whichever client asked for it.
+/// `notify` hands the update to the pubsub session, which serializes it
+/// and writes it out over that client's WebSocket connection.
+struct SignatureSubscription {
+    sink: pubsub::Sink<Status>,
+}
+
+impl Sink<Status> for SignatureSubscription {
+    fn notify(&self, value: Status) {
+        let _ = self.sink.notify(Ok(value)).wait();
+    }
+}
+
+struct AccountSubscription {
+    sink: pubsub::Sink<Account>,
+}
+
+impl Sink<Account> for AccountSubscription {
+    fn notify(&self, value: Account) {
+        let _ = self.sink.notify(Ok(value)).wait();
+    }
+}
+
+/// Tracks which `SubscriptionId` is waiting on which signature/pubkey, so
+/// an unsubscribe (or a dropped connection) can remove the matching
+/// registration from `TransactionProcessor` again.
+pub struct RpcSubscriptions {
+    transaction_processor: Arc<TransactionProcessor>,
+    signature_subscriptions: RwLock<HashMap<SubscriptionId, Signature>>,
+    account_subscriptions: RwLock<HashMap<SubscriptionId, Pubkey>>,
+    next_id: AtomicUsize,
+}
+
+impl RpcSubscriptions {
+    pub fn new(transaction_processor: &Arc<TransactionProcessor>) -> Self {
+        RpcSubscriptions {
+            transaction_processor: transaction_processor.clone(),
+            signature_subscriptions: RwLock::new(HashMap::new()),
+            account_subscriptions: RwLock::new(HashMap::new()),
+            next_id: AtomicUsize::new(0),
+        }
+    }
+
+    fn next_subscription_id(&self) -> SubscriptionId {
+        SubscriptionId::Number(self.next_id.fetch_add(1, Ordering::Relaxed) as u64)
+    }
+
+    fn add_signature_subscription(&self, signature: Signature, sink: pubsub::Sink<Status>) -> SubscriptionId {
+        let id = self.next_subscription_id();
+        self.transaction_processor
+            .add_signature_subscription(signature, Box::new(SignatureSubscription { sink }));
+        self.signature_subscriptions
+            .write()
+            .unwrap()
+            .insert(id.clone(), signature);
+        id
+    }
+
+    fn remove_signature_subscription(&self, id: &SubscriptionId) -> bool {
+        match self.signature_subscriptions.write().unwrap().remove(id) {
+            Some(signature) => {
+                self.transaction_processor.remove_signature_subscription(&signature);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn add_account_subscription(&self, pubkey: Pubkey, sink: pubsub::Sink<Account>) -> SubscriptionId {
+        let id = self.next_subscription_id();
+        self.transaction_processor
+            .add_account_subscription(pubkey, Box::new(AccountSubscription { sink }));
+        self.account_subscriptions
+            .write()
+            .unwrap()
+            .insert(id.clone(), pubkey);
+        id
+    }
+
+    fn remove_account_subscription(&self, id: &SubscriptionId) -> bool {
+        match self.account_subscriptions.write().unwrap().remove(id) {
+            Some(pubkey) => {
+                self.transaction_processor.remove_account_subscription(&pubkey);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+build_rpc_trait! {
+    pub trait RpcSolPubSub {
+        type Metadata;
+
+        #[pubsub(subscription = "signatureNotification", subscribe, name = "signatureSubscribe")]
+        fn signature_subscribe(&self, Self::Metadata, pubsub::Subscriber<Status>, String);
+        #[pubsub(subscription = "signatureNotification", unsubscribe, name = "signatureUnsubscribe")]
+        fn signature_unsubscribe(&self, SubscriptionId) -> Result<bool>;
+
+        #[pubsub(subscription = "accountNotification", subscribe, name = "accountSubscribe")]
+        fn account_subscribe(&self, Self::Metadata, pubsub::Subscriber<Account>, String);
+        #[pubsub(subscription = "accountNotification", unsubscribe, name = "accountUnsubscribe")]
+        fn account_unsubscribe(&self, SubscriptionId) -> Result<bool>;
+    }
+}
+
+struct RpcSolPubSubImpl {
+    subscriptions: Arc<RpcSubscriptions>,
+}
+
+impl RpcSolPubSubImpl {
+    fn new(subscriptions: Arc<RpcSubscriptions>) -> Self {
+        RpcSolPubSubImpl { subscriptions }
+    }
+}
+
+impl RpcSolPubSub for RpcSolPubSubImpl {
+    type Metadata = Arc<Session>;
+
+    fn signature_subscribe(&self, _meta: Self::Metadata, subscriber: pubsub::Subscriber<Status>, param: String) {
+        match param.parse::<Signature>() {
+            Ok(signature) => {
+                let sink = subscriber.assign_id_async(self.subscriptions.next_subscription_id());
+                if let Ok(sink) = sink.wait() {
+                    self.subscriptions.add_signature_subscription(signature, sink);
+                }
+            }
+            Err(_) => subscriber.reject(Error::new(ErrorCode::InvalidParams)).unwrap(),
+        }
+    }
+
+    fn signature_unsubscribe(&self, id: SubscriptionId) -> Result<bool> {
+        Ok(self.subscriptions.remove_signature_subscription(&id))
+    }
+
+    fn account_subscribe(&self, _meta: Self::Metadata, subscriber: pubsub::Subscriber<Account>, param: String) {
+        match param.parse::<Pubkey>() {
+            Ok(pubkey) => {
+                let sink = subscriber.assign_id_async(self.subscriptions.next_subscription_id());
+                if let Ok(sink) = sink.wait() {
+                    self.subscriptions.add_account_subscription(pubkey, sink);
+                }
+            }
+            Err(_) => subscriber.reject(Error::new(ErrorCode::InvalidParams)).unwrap(),
+        }
+    }
+
+    fn account_unsubscribe(&self, id: SubscriptionId) -> Result<bool> {
+        Ok(self.subscriptions.remove_account_subscription(&id))
+    }
+}
+
+/// Periodically sweeps `TransactionProcessor::reap_expired_signature_subscriptions`
+/// so a subscription on a signature that's never submitted doesn't sit in
+/// the map forever waiting for a status that will never be written.
+struct SignatureReaperService {
+    thread_hdl: JoinHandle<()>,
+    exit: Arc<AtomicBool>,
+}
+
+impl SignatureReaperService {
+    fn new(transaction_processor: Arc<TransactionProcessor>) -> Self {
+        let exit = Arc::new(AtomicBool::new(false));
+        let thread_exit = exit.clone();
+        let thread_hdl = Builder::new()
+            .name("hypercube-signature-reaper".to_string())
+            .spawn(move || {
+                while !thread_exit.load(Ordering::Relaxed) {
+                    sleep(Duration::from_millis(SIGNATURE_REAP_INTERVAL_MS));
+                    let reaped = transaction_processor.reap_expired_signature_subscriptions();
+                    if reaped > 0 {
+                        info!("reaped {} expired signature subscription(s)", reaped);
+                    }
+                }
+            }).unwrap();
+
+        SignatureReaperService { thread_hdl, exit }
+    }
+
+    fn close(self) {
+        self.exit.store(true, Ordering::Relaxed);
+        let _ = self.join();
+    }
+}
+
+impl Service for SignatureReaperService {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        self.thread_hdl.join()
+    }
+}
+
+pub struct PubSubService {
+    server: Option<Server>,
+    signature_reaper: Option<SignatureReaperService>,
+}
+
+impl PubSubService {
+    pub fn new(subscriptions: &Arc<RpcSubscriptions>, pubsub_addr: SocketAddr) -> Self {
+        let rpc_impl = RpcSolPubSubImpl::new(subscriptions.clone());
+        let mut io = PubSubHandler::default();
+        io.extend_with(rpc_impl.to_delegate());
+        let server = ServerBuilder::with_meta_extractor(io, |context: &RequestContext| {
+            Arc::new(Session::new(context.sender()))
+        }).start(&pubsub_addr)
+            .expect("start pubsub server");
+
+        let signature_reaper = SignatureReaperService::new(subscriptions.transaction_processor.clone());
+
+        info!("pubsub service listening on {}", pubsub_addr);
+        PubSubService {
+            server: Some(server),
+            signature_reaper: Some(signature_reaper),
+        }
+    }
+
+    pub fn close(mut self) {
+        if let Some(server) = self.server.take() {
+            server.close();
+        }
+        if let Some(signature_reaper) = self.signature_reaper.take() {
+            signature_reaper.close();
+        }
+    }
+}
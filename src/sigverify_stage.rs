@@ -0,0 +1,123 @@
+//! The `sigverify_stage` batches packets off a `FetchStage` channel, hands
+//! each batch to a pluggable `SigVerifier`, and tags every packet with the
+//! verdict before forwarding it on to transaction processing.
+
+use packet::Packets;
+use result::{Error, Result};
+use service::Service;
+use sigverify;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, Builder, JoinHandle};
+use std::time::{Duration, Instant};
+use timing;
+
+pub type VerifiedPackets = Vec<(Arc<RwLock<Packets>>, Vec<u8>)>;
+
+/// Something that can look at a batch of packets and decide, per packet,
+/// whether its signature verifies. Implementations are free to run on the
+/// CPU, the GPU, or skip verification entirely, which lets `SigVerifyStage`
+/// stay oblivious to how verification is actually done.
+pub trait SigVerifier: Send + Sync {
+    fn verify_batch(&self, batch: Vec<Arc<RwLock<Packets>>>) -> Vec<Vec<u8>>;
+}
+
+/// The real ed25519 signature-verification path.
+pub struct TransactionSigVerifier;
+
+impl SigVerifier for TransactionSigVerifier {
+    fn verify_batch(&self, batch: Vec<Arc<RwLock<Packets>>>) -> Vec<Vec<u8>> {
+        batch
+            .iter()
+            .map(|packets| sigverify::ed25519_verify(&packets.read().unwrap()))
+            .collect()
+    }
+}
+
+/// Marks every packet verified, skipping actual signature checks. Useful for
+/// benchmarks and tests that don't want to pay the verification cost.
+pub struct DisabledSigVerifier;
+
+impl SigVerifier for DisabledSigVerifier {
+    fn verify_batch(&self, batch: Vec<Arc<RwLock<Packets>>>) -> Vec<Vec<u8>> {
+        batch
+            .iter()
+            .map(|packets| vec![1u8; packets.read().unwrap().packets.len()])
+            .collect()
+    }
+}
+
+impl SigVerifier for Box<SigVerifier> {
+    fn verify_batch(&self, batch: Vec<Arc<RwLock<Packets>>>) -> Vec<Vec<u8>> {
+        (**self).verify_batch(batch)
+    }
+}
+
+pub struct SigVerifyStage<V: SigVerifier> {
+    thread_hdl: JoinHandle<()>,
+    _verifier: ::std::marker::PhantomData<V>,
+}
+
+impl<V: SigVerifier + 'static> SigVerifyStage<V> {
+    pub fn new(
+        packet_receiver: Receiver<Arc<RwLock<Packets>>>,
+        sigverifier: V,
+    ) -> (Self, Receiver<VerifiedPackets>) {
+        let (verified_sender, verified_receiver) = channel();
+        let thread_hdl = Self::verifier_thread(packet_receiver, verified_sender, sigverifier);
+        (
+            SigVerifyStage {
+                thread_hdl,
+                _verifier: ::std::marker::PhantomData,
+            },
+            verified_receiver,
+        )
+    }
+
+    fn verifier_thread(
+        packet_receiver: Receiver<Arc<RwLock<Packets>>>,
+        verified_sender: Sender<VerifiedPackets>,
+        sigverifier: V,
+    ) -> JoinHandle<()> {
+        Builder::new()
+            .name("hypercube-sigverify-stage".to_string())
+            .spawn(move || loop {
+                if let Err(e) = Self::verify_batch(&packet_receiver, &verified_sender, &sigverifier) {
+                    match e {
+                        Error::RecvTimeoutError(RecvTimeoutError::Timeout) => (),
+                        Error::RecvTimeoutError(RecvTimeoutError::Disconnected) => break,
+                        Error::SendError => break,
+                        _ => error!("hypercube-sigverify-stage unexpected error {:?}", e),
+                    }
+                }
+            }).unwrap()
+    }
+
+    fn verify_batch(
+        packet_receiver: &Receiver<Arc<RwLock<Packets>>>,
+        verified_sender: &Sender<VerifiedPackets>,
+        sigverifier: &V,
+    ) -> Result<()> {
+        let mut batch = vec![packet_receiver.recv_timeout(Duration::from_millis(100))?];
+        while let Ok(more) = packet_receiver.try_recv() {
+            batch.push(more);
+        }
+        let start = Instant::now();
+        let verdicts = sigverifier.verify_batch(batch.clone());
+        debug!(
+            "sigverify batch of {} took {}ms",
+            batch.len(),
+            timing::duration_as_ms(&start.elapsed())
+        );
+        verified_sender.send(batch.into_iter().zip(verdicts).collect())?;
+        Ok(())
+    }
+}
+
+impl<V: SigVerifier> Service for SigVerifyStage<V> {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        self.thread_hdl.join()
+    }
+}
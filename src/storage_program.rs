@@ -0,0 +1,75 @@
+//! `storage_program` records each replicator's most recently submitted
+//! proof-of-replication on chain: the chained sample hash from
+//! `storage_stage` and the entry height it was computed up to. Keeping this
+//! on chain, rather than trusting a replicator's self-report, lets the
+//! network tell a replicator that is actually storing its assigned ledger
+//! segment apart from one that has gone silent or is only pretending to.
+
+use bincode::{deserialize, serialize};
+use hash::Hash;
+use transaction::Transaction;
+use xpz_program_interface::account::Account;
+use xpz_program_interface::pubkey::Pubkey;
+
+/// On-chain record of a replicator's latest submitted proof.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct StorageAccount {
+    pub proof_hash: Hash,
+    pub entry_height: u64,
+}
+
+/// Instructions understood by the storage program.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum StorageInstruction {
+    SubmitProof { proof_hash: Hash, entry_height: u64 },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum StorageError {
+    InvalidInstruction,
+    InvalidAccountData,
+}
+
+pub struct StorageProgram;
+
+impl StorageProgram {
+    pub fn id() -> Pubkey {
+        Pubkey::new(&[
+            11, 11, 11, 11, 11, 11, 11, 11, 11, 11, 11, 11, 11, 11, 11, 11, 11, 11, 11, 11, 11,
+            11, 11, 11, 11, 11, 11, 11, 11, 11, 11, 11,
+        ])
+    }
+
+    pub fn check_id(program_id: &Pubkey) -> bool {
+        *program_id == Self::id()
+    }
+
+    fn decode_instruction(tx: &Transaction) -> Result<StorageInstruction, StorageError> {
+        let program_index = tx
+            .program_ids
+            .iter()
+            .position(Self::check_id)
+            .ok_or(StorageError::InvalidInstruction)?;
+        let ix = tx
+            .instructions
+            .iter()
+            .find(|ix| ix.program_ids_index as usize == program_index)
+            .ok_or(StorageError::InvalidInstruction)?;
+        deserialize(&ix.userdata).map_err(|_| StorageError::InvalidInstruction)
+    }
+
+    /// Overwrite the last named account with `tx`'s proof: only the most
+    /// recent proof per replicator is ever kept on chain.
+    pub fn process_transaction(tx: &Transaction, accounts: &mut [Account]) -> Result<(), StorageError> {
+        let StorageInstruction::SubmitProof {
+            proof_hash,
+            entry_height,
+        } = Self::decode_instruction(tx)?;
+        let storage_account = accounts.last_mut().ok_or(StorageError::InvalidAccountData)?;
+        storage_account.userdata = serialize(&StorageAccount {
+            proof_hash,
+            entry_height,
+        }).map_err(|_| StorageError::InvalidAccountData)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+//! The `storage_stage` samples entries as they're replayed and chains them
+//! into a periodic proof-of-replication hash, evidence that this node is
+//! actually storing the ledger segments it replays rather than discarding
+//! them. Each sample's offset is derived from the previous sample's hash, so
+//! computing a proof can't be parallelized or guessed ahead of time — a node
+//! has to have hashed sample N-1 before it can even pick sample N. Once a
+//! proof is computed it's periodically signed and submitted to the storage
+//! program as a transaction, the same way `vote_stage` periodically submits
+//! a signed vote.
+
+use bincode::serialize;
+use blockthread::BlockThread;
+use entry::{Entry, EntryReceiver};
+use hash::{hashv, Hash};
+use result::{Error, Result};
+use service::Service;
+use signature::{Keypair, KeypairUtil};
+use std::net::UdpSocket;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, RwLock};
+use std::thread::{self, Builder, JoinHandle};
+use std::time::Duration;
+use storage_program::{StorageInstruction, StorageProgram};
+use timing;
+use transaction::Transaction;
+use transaction_processor::TransactionProcessor;
+
+/// How many chained samples go into a single round's proof hash.
+const NUM_STORAGE_SAMPLES: usize = 4;
+
+/// Minimum time between storage proof submissions, mirroring
+/// `vote_stage::VOTE_TIMEOUT_MS`'s role of keeping a periodic broadcast from
+/// firing on every single batch.
+const STORAGE_PROOF_SUBMIT_INTERVAL_MS: u64 = 60_000;
+
+#[derive(Default)]
+struct StorageStateInner {
+    proof_hash: Hash,
+    entry_height: u64,
+}
+
+/// Shared handle onto the node's most recent storage proof, so the RPC
+/// layer can report it without needing a reference to `StorageStage`.
+#[derive(Clone, Default)]
+pub struct StorageState {
+    state: Arc<RwLock<StorageStateInner>>,
+}
+
+impl StorageState {
+    pub fn new() -> Self {
+        StorageState::default()
+    }
+
+    pub fn proof_hash(&self) -> Hash {
+        self.state.read().unwrap().proof_hash
+    }
+
+    /// Entry height the current `proof_hash` was sampled up to.
+    pub fn entry_height(&self) -> u64 {
+        self.state.read().unwrap().entry_height
+    }
+
+    fn set_proof(&self, proof_hash: Hash, entry_height: u64) {
+        let mut state = self.state.write().unwrap();
+        state.proof_hash = proof_hash;
+        state.entry_height = entry_height;
+    }
+}
+
+pub struct StorageStage {
+    thread_hdl: JoinHandle<()>,
+}
+
+impl StorageStage {
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
+    pub fn new(
+        storage_state: StorageState,
+        entry_receiver: EntryReceiver,
+        keypair: Arc<Keypair>,
+        transaction_processor: Arc<TransactionProcessor>,
+        blockthread: Arc<RwLock<BlockThread>>,
+        storage_socket: Arc<UdpSocket>,
+    ) -> Self {
+        let thread_hdl = Builder::new()
+            .name("hypercube-storage-stage".to_string())
+            .spawn(move || {
+                let mut entry_height = 0;
+                let mut last_submit_ms = 0;
+                loop {
+                    if let Err(e) = Self::process_entries(
+                        &storage_state,
+                        &entry_receiver,
+                        &keypair,
+                        &transaction_processor,
+                        &blockthread,
+                        &storage_socket,
+                        &mut entry_height,
+                        &mut last_submit_ms,
+                    ) {
+                        match e {
+                            Error::RecvTimeoutError(RecvTimeoutError::Disconnected) => break,
+                            Error::RecvTimeoutError(RecvTimeoutError::Timeout) => (),
+                            _ => error!("hypercube-storage-stage unexpected error {:?}", e),
+                        }
+                    }
+                }
+            }).unwrap();
+
+        StorageStage { thread_hdl }
+    }
+
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
+    fn process_entries(
+        storage_state: &StorageState,
+        entry_receiver: &EntryReceiver,
+        keypair: &Arc<Keypair>,
+        transaction_processor: &Arc<TransactionProcessor>,
+        blockthread: &Arc<RwLock<BlockThread>>,
+        storage_socket: &Arc<UdpSocket>,
+        entry_height: &mut u64,
+        last_submit_ms: &mut u64,
+    ) -> Result<()> {
+        let timer = Duration::new(1, 0);
+        let entries = entry_receiver.recv_timeout(timer)?;
+        if entries.is_empty() {
+            return Ok(());
+        }
+        *entry_height += entries.len() as u64;
+
+        let seed = storage_state.proof_hash();
+        let proof_hash = Self::chain_proof_hash(seed, &entries);
+        storage_state.set_proof(proof_hash, *entry_height);
+
+        let now = timing::timestamp();
+        if now - *last_submit_ms > STORAGE_PROOF_SUBMIT_INTERVAL_MS {
+            Self::submit_proof(
+                keypair,
+                transaction_processor,
+                blockthread,
+                storage_socket,
+                proof_hash,
+                *entry_height,
+            );
+            *last_submit_ms = now;
+        }
+        Ok(())
+    }
+
+    /// Chain `NUM_STORAGE_SAMPLES` sampled entry ids onto `seed`, each
+    /// sample's offset into `entries` chosen from the previous link's hash,
+    /// so the whole chain has to be walked in order to reproduce it.
+    fn chain_proof_hash(seed: Hash, entries: &[Entry]) -> Hash {
+        if entries.is_empty() {
+            return seed;
+        }
+        let mut hash = seed;
+        for _ in 0..NUM_STORAGE_SAMPLES {
+            let offset = hash.as_ref()[0] as usize % entries.len();
+            hash = hashv(&[hash.as_ref(), entries[offset].id.as_ref()]);
+        }
+        hash
+    }
+
+    /// Sign and send this round's proof to the current leader's TPU, the
+    /// same way `vote_stage` delivers a signed vote: as a blob addressed to
+    /// whoever `blockthread` currently names as leader, rather than routed
+    /// through local transaction processing.
+    fn submit_proof(
+        keypair: &Arc<Keypair>,
+        transaction_processor: &Arc<TransactionProcessor>,
+        blockthread: &Arc<RwLock<BlockThread>>,
+        storage_socket: &Arc<UdpSocket>,
+        proof_hash: Hash,
+        entry_height: u64,
+    ) {
+        let last_id = transaction_processor.last_id();
+        let userdata = serialize(&StorageInstruction::SubmitProof {
+            proof_hash,
+            entry_height,
+        }).expect("serialize StorageInstruction");
+        let tx = Transaction::new(keypair, &[], StorageProgram::id(), userdata, last_id, 0);
+        let bytes = serialize(&tx).expect("serialize storage proof transaction");
+
+        if let Some(leader) = blockthread.read().unwrap().leader_data() {
+            let _ = storage_socket.send_to(&bytes, leader.contact_info.tx_creator);
+        }
+    }
+}
+
+impl Service for StorageStage {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        self.thread_hdl.join()
+    }
+}
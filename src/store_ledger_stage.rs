@@ -0,0 +1,53 @@
+//! The `store_ledger_stage` persists processed entries to the ledger on its
+//! own thread, so ledger I/O latency never stalls `ReplicateStage`'s replay
+//! of transactions against the bank.
+
+use entry::EntryReceiver;
+use ledger::LedgerWriter;
+use result::{Error, Result};
+use service::Service;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread::{self, Builder, JoinHandle};
+use std::time::Duration;
+
+pub struct StoreLedgerStage {
+    thread_hdl: JoinHandle<()>,
+}
+
+impl StoreLedgerStage {
+    pub fn new(ledger_path: &str, entry_receiver: EntryReceiver) -> Self {
+        let mut ledger_writer = LedgerWriter::open(ledger_path, false).unwrap();
+
+        let thread_hdl = Builder::new()
+            .name("hypercube-store-ledger-stage".to_string())
+            .spawn(move || loop {
+                if let Err(e) = Self::write_entries(&mut ledger_writer, &entry_receiver) {
+                    match e {
+                        Error::RecvTimeoutError(RecvTimeoutError::Disconnected) => break,
+                        Error::RecvTimeoutError(RecvTimeoutError::Timeout) => (),
+                        _ => error!("hypercube-store-ledger-stage unexpected error {:?}", e),
+                    }
+                }
+            }).unwrap();
+
+        StoreLedgerStage { thread_hdl }
+    }
+
+    fn write_entries(ledger_writer: &mut LedgerWriter, entry_receiver: &EntryReceiver) -> Result<()> {
+        let timer = Duration::new(1, 0);
+        let mut entries = entry_receiver.recv_timeout(timer)?;
+        while let Ok(mut more) = entry_receiver.try_recv() {
+            entries.append(&mut more);
+        }
+        ledger_writer.write_entries(entries)?;
+        Ok(())
+    }
+}
+
+impl Service for StoreLedgerStage {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        self.thread_hdl.join()
+    }
+}
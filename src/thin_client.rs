@@ -0,0 +1,163 @@
+//! `thin_client` is a BanksClient-style in-process async client for tests
+//! and tooling that want to drive a `TransactionProcessor` without
+//! round-tripping through UDP/HTTP or hand-rolling a request/response pair
+//! for every call. `BanksServer::new` spins up a small worker pool sharing
+//! the processor's `Arc`, and hands back a `BanksClient` whose methods each
+//! enqueue one request and return a future that resolves once a worker
+//! picks it up — so `client.get_balance(pubkey).wait()` (or composing with
+//! other futures) replaces a caller's own polling loop.
+
+use jsonrpc_core::futures::sync::oneshot;
+use jsonrpc_core::futures::Future;
+use service::Service;
+use signature::Signature;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, Builder, JoinHandle};
+use std::time::{Duration, Instant};
+use transaction::Transaction;
+use transaction_processor::{Result, TransactionProcessor, TransactionProcessorError};
+use xpz_program_interface::account::Account;
+use xpz_program_interface::pubkey::Pubkey;
+
+/// How often a worker re-checks a transaction's signature while waiting for
+/// it to confirm in `send_and_confirm_transaction`.
+const CONFIRM_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+enum BanksRequest {
+    GetAccount(Pubkey, oneshot::Sender<Option<Account>>),
+    GetBalance(Pubkey, oneshot::Sender<i64>),
+    GetSignatureStatus(Signature, oneshot::Sender<Result<()>>),
+    SendAndConfirmTransaction(Box<Transaction>, Duration, oneshot::Sender<Result<()>>),
+}
+
+/// An async handle onto a `BanksServer`'s worker pool. Cheap to clone and
+/// share between tests, same as a `TransactionProcessor`'s own `Arc`.
+#[derive(Clone)]
+pub struct BanksClient {
+    sender: mpsc::Sender<BanksRequest>,
+}
+
+impl BanksClient {
+    pub fn get_account(&self, pubkey: Pubkey) -> impl Future<Item = Option<Account>, Error = oneshot::Canceled> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        let _ = self.sender.send(BanksRequest::GetAccount(pubkey, reply_sender));
+        reply_receiver
+    }
+
+    pub fn get_balance(&self, pubkey: Pubkey) -> impl Future<Item = i64, Error = oneshot::Canceled> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        let _ = self.sender.send(BanksRequest::GetBalance(pubkey, reply_sender));
+        reply_receiver
+    }
+
+    pub fn get_signature_status(
+        &self,
+        signature: Signature,
+    ) -> impl Future<Item = Result<()>, Error = oneshot::Canceled> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        let _ = self.sender.send(BanksRequest::GetSignatureStatus(signature, reply_sender));
+        reply_receiver
+    }
+
+    /// Submit `tx` and poll until its signature confirms or `deadline`
+    /// elapses, the same behavior `qtc`'s CLI gets from blocking on
+    /// `serialize_send_and_confirm_tx`, but as an awaitable future a test
+    /// can run alongside other work instead of stalling on.
+    pub fn send_and_confirm_transaction(
+        &self,
+        tx: Transaction,
+        deadline: Duration,
+    ) -> impl Future<Item = Result<()>, Error = oneshot::Canceled> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        let _ = self.sender.send(BanksRequest::SendAndConfirmTransaction(
+            Box::new(tx),
+            deadline,
+            reply_sender,
+        ));
+        reply_receiver
+    }
+}
+
+/// The worker pool backing a `BanksClient`. Workers share one
+/// `Arc<TransactionProcessor>` and pull requests off a single queue, so
+/// request latency (e.g. a slow confirmation poll) no longer serializes
+/// behind other callers the way a single synchronous request loop would.
+/// There's no explicit shutdown signal: a worker's `recv()` loop ends on
+/// its own once every `BanksClient` (and its clones) have been dropped and
+/// the request channel closes.
+pub struct BanksServer {
+    thread_hdls: Vec<JoinHandle<()>>,
+}
+
+impl BanksServer {
+    pub fn new(transaction_processor: Arc<TransactionProcessor>, num_workers: usize) -> (BanksClient, Self) {
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let thread_hdls = (0..num_workers.max(1))
+            .map(|i| {
+                let transaction_processor = transaction_processor.clone();
+                let receiver = receiver.clone();
+                Builder::new()
+                    .name(format!("hypercube-banks-server-{}", i))
+                    .spawn(move || loop {
+                        let request = receiver.lock().unwrap().recv();
+                        match request {
+                            Ok(request) => Self::handle_request(&transaction_processor, request),
+                            Err(_) => break,
+                        }
+                    }).unwrap()
+            })
+            .collect();
+
+        (BanksClient { sender }, BanksServer { thread_hdls })
+    }
+
+    fn handle_request(transaction_processor: &Arc<TransactionProcessor>, request: BanksRequest) {
+        match request {
+            BanksRequest::GetAccount(pubkey, reply) => {
+                let _ = reply.send(transaction_processor.get_account(&pubkey));
+            }
+            BanksRequest::GetBalance(pubkey, reply) => {
+                let _ = reply.send(transaction_processor.get_balance(&pubkey));
+            }
+            BanksRequest::GetSignatureStatus(signature, reply) => {
+                let _ = reply.send(transaction_processor.get_signature_status(&signature));
+            }
+            BanksRequest::SendAndConfirmTransaction(tx, deadline, reply) => {
+                let result = Self::send_and_confirm(transaction_processor, &tx, deadline);
+                let _ = reply.send(result);
+            }
+        }
+    }
+
+    fn send_and_confirm(
+        transaction_processor: &Arc<TransactionProcessor>,
+        tx: &Transaction,
+        deadline: Duration,
+    ) -> Result<()> {
+        transaction_processor.process_transaction(tx)?;
+
+        let started = Instant::now();
+        loop {
+            if transaction_processor.has_signature(&tx.signature) {
+                return transaction_processor.get_signature_status(&tx.signature);
+            }
+            if started.elapsed() >= deadline {
+                return Err(TransactionProcessorError::SignatureNotFound);
+            }
+            thread::sleep(CONFIRM_POLL_INTERVAL);
+        }
+    }
+}
+
+impl Service for BanksServer {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        for thread_hdl in self.thread_hdls {
+            thread_hdl.join()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+//! `TpuForwarder` runs only while this node is validating: it binds the TPU
+//! ingress sockets so client transactions that land here don't just sit
+//! queued in the OS socket buffer until a rotation happens to make this node
+//! leader, and instead relays them immediately to whoever `BlockThread`
+//! currently names as leader. `FetchStage::new_with_forwarder` already makes
+//! this same per-batch decision for a leader that might be momentarily
+//! behind on rotation; `TpuForwarder` is the validator-side mirror of that
+//! same path, forwarding unconditionally since a validator never processes
+//! transactions locally.
+
+use blockthread::BlockThread;
+use packet::Packets;
+use service::Service;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, Builder, JoinHandle};
+use std::time::Duration;
+use streamer;
+
+pub struct TpuForwarder {
+    thread_hdls: Vec<JoinHandle<()>>,
+    exit: Arc<AtomicBool>,
+}
+
+impl TpuForwarder {
+    pub fn new(
+        sockets: Vec<Arc<UdpSocket>>,
+        forward_sockets: Vec<Arc<UdpSocket>>,
+        blockthread: Arc<RwLock<BlockThread>>,
+    ) -> Self {
+        let exit = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = channel();
+        let mut thread_hdls = Self::spawn_receivers(sockets, exit.clone(), sender);
+        thread_hdls.extend(Self::spawn_forwarders(
+            forward_sockets,
+            receiver,
+            blockthread,
+            exit.clone(),
+        ));
+
+        TpuForwarder { thread_hdls, exit }
+    }
+
+    fn spawn_receivers(
+        sockets: Vec<Arc<UdpSocket>>,
+        exit: Arc<AtomicBool>,
+        sender: Sender<Arc<RwLock<Packets>>>,
+    ) -> Vec<JoinHandle<()>> {
+        sockets
+            .into_iter()
+            .map(|socket| streamer::receiver(socket, exit.clone(), sender.clone(), "tpu_forwarder"))
+            .collect()
+    }
+
+    /// Drains the shared receiver and relays each packet's bytes to the
+    /// current leader's TPU address over `forward_sockets`. Packets that
+    /// arrive while no leader is known are dropped rather than buffered.
+    fn spawn_forwarders(
+        forward_sockets: Vec<Arc<UdpSocket>>,
+        receiver: ::std::sync::mpsc::Receiver<Arc<RwLock<Packets>>>,
+        blockthread: Arc<RwLock<BlockThread>>,
+        exit: Arc<AtomicBool>,
+    ) -> Vec<JoinHandle<()>> {
+        let receiver = Arc::new(Mutex::new(receiver));
+        forward_sockets
+            .into_iter()
+            .map(|socket| {
+                let receiver = receiver.clone();
+                let blockthread = blockthread.clone();
+                let exit = exit.clone();
+                Builder::new()
+                    .name("hypercube-tpu-forwarder".to_string())
+                    .spawn(move || loop {
+                        let msgs = match receiver.lock().unwrap().recv_timeout(Duration::from_millis(100)) {
+                            Ok(msgs) => msgs,
+                            Err(RecvTimeoutError::Timeout) => {
+                                if exit.load(Ordering::Relaxed) {
+                                    break;
+                                }
+                                continue;
+                            }
+                            Err(RecvTimeoutError::Disconnected) => break,
+                        };
+                        if let Some(leader) = blockthread.read().unwrap().leader_data() {
+                            let packets = msgs.read().unwrap();
+                            for packet in &packets.packets {
+                                let _ = socket.send_to(
+                                    &packet.data[..packet.meta.size],
+                                    leader.contact_info.tx_creator,
+                                );
+                            }
+                        }
+                        if exit.load(Ordering::Relaxed) {
+                            break;
+                        }
+                    }).unwrap()
+            }).collect()
+    }
+
+    pub fn exit(&self) {
+        self.exit.store(true, Ordering::Relaxed);
+    }
+
+    pub fn close(self) -> thread::Result<()> {
+        self.exit();
+        self.join()
+    }
+}
+
+impl Service for TpuForwarder {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        for thread_hdl in self.thread_hdls {
+            thread_hdl.join()?;
+        }
+        Ok(())
+    }
+}
@@ -8,18 +8,36 @@ pub const SIGNED_DATA_OFFSET: usize = size_of::<Signature>();
 pub const SIG_OFFSET: usize = 0;
 pub const PUB_KEY_OFFSET: usize = size_of::<Signature>() + size_of::<u64>();
 
+/// An instruction within a `Transaction`, run against one of the transaction's
+/// `program_ids` over a subset of its `keys`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Instruction {
+    /// Index into the enclosing transaction's `program_ids`.
+    pub program_ids_index: u8,
+    /// Opaque, program-specific instruction payload.
+    pub userdata: Vec<u8>,
+    /// Indices into the enclosing transaction's `keys`.
+    pub accounts: Vec<u8>,
+}
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Transaction {
     pub signature: Signature,
     pub keys: Vec<Pubkey>,
-    pub program_id: Pubkey,
+    pub program_ids: Vec<Pubkey>,
     pub last_id: Hash,
     pub fee: i64,
-    pub userdata: Vec<u8>,
+    pub instructions: Vec<Instruction>,
+    /// 0 for a single-instruction transaction, the layout every ledger
+    /// entry written so far already uses and that's always accepted; 1
+    /// once more than one instruction is packed into the transaction,
+    /// which a `TransactionProcessor` only accepts when explicitly opted
+    /// in via `allow_versioned`.
+    pub version: u8,
 }
 
 impl Transaction {
+    /// Create and sign a new single-instruction Transaction.
     pub fn new(
         from_keypair: &Keypair,
         transaction_keys: &[Pubkey],
@@ -27,28 +45,90 @@ impl Transaction {
         userdata: Vec<u8>,
         last_id: Hash,
         fee: i64,
+    ) -> Self {
+        let account_indices = (0..=transaction_keys.len() as u8).collect();
+        Self::new_with_instructions(
+            from_keypair,
+            transaction_keys,
+            last_id,
+            fee,
+            vec![program_id],
+            vec![(0, userdata, account_indices)],
+        )
+    }
+
+    /// Create and sign a new Transaction that runs several instructions against
+    /// possibly-different programs atomically. Each instruction is given as a
+    /// tuple of `(program_ids_index, instruction_data, account_indices)`.
+    pub fn new_with_instructions(
+        from_keypair: &Keypair,
+        transaction_keys: &[Pubkey],
+        last_id: Hash,
+        fee: i64,
+        program_ids: Vec<Pubkey>,
+        instructions: Vec<(u8, Vec<u8>, Vec<u8>)>,
     ) -> Self {
         let from = from_keypair.pubkey();
         let mut keys = vec![from];
         keys.extend_from_slice(transaction_keys);
+        let instructions = instructions
+            .into_iter()
+            .map(|(program_ids_index, userdata, accounts)| Instruction {
+                program_ids_index,
+                userdata,
+                accounts,
+            })
+            .collect();
+        let version = if instructions.len() > 1 { 1 } else { 0 };
         let mut tx = Transaction {
             signature: Signature::default(),
             keys,
-            program_id,
+            program_ids,
             last_id,
             fee,
-            userdata,
+            instructions,
+            version,
         };
         tx.sign(from_keypair);
         tx
     }
 
+    /// Construct a single-instruction Transaction from a signature obtained
+    /// out of band (e.g. from an offline signer), rather than signing it
+    /// locally with a `Keypair`. The caller is responsible for ensuring the
+    /// signature actually covers this transaction's sign data.
+    pub fn new_presigned(
+        from: Pubkey,
+        transaction_keys: &[Pubkey],
+        program_id: Pubkey,
+        userdata: Vec<u8>,
+        last_id: Hash,
+        fee: i64,
+        signature: Signature,
+    ) -> Self {
+        let account_indices = (0..=transaction_keys.len() as u8).collect();
+        let mut keys = vec![from];
+        keys.extend_from_slice(transaction_keys);
+        Transaction {
+            signature,
+            keys,
+            program_ids: vec![program_id],
+            last_id,
+            fee,
+            instructions: vec![Instruction {
+                program_ids_index: 0,
+                userdata,
+                accounts: account_indices,
+            }],
+            version: 0,
+        }
+    }
 
     pub fn get_sign_data(&self) -> Vec<u8> {
         let mut data = serialize(&(&self.keys)).expect("serialize keys");
 
-        let program_id = serialize(&(&self.program_id)).expect("serialize program_id");
-        data.extend_from_slice(&program_id);
+        let program_ids = serialize(&(&self.program_ids)).expect("serialize program_ids");
+        data.extend_from_slice(&program_ids);
 
         let last_id_data = serialize(&(&self.last_id)).expect("serialize last_id");
         data.extend_from_slice(&last_id_data);
@@ -56,8 +136,11 @@ impl Transaction {
         let fee_data = serialize(&(&self.fee)).expect("serialize last_id");
         data.extend_from_slice(&fee_data);
 
-        let userdata = serialize(&(&self.userdata)).expect("serialize userdata");
-        data.extend_from_slice(&userdata);
+        let instructions = serialize(&(&self.instructions)).expect("serialize instructions");
+        data.extend_from_slice(&instructions);
+
+        let version_data = serialize(&(&self.version)).expect("serialize version");
+        data.extend_from_slice(&version_data);
         data
     }
 
@@ -90,10 +173,28 @@ impl Transaction {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bincode::serialize;
+    use bincode::{deserialize, serialize};
+    use fin_plan_transaction::FinPlanTransaction;
     use signature::GenKeys;
+
     #[test]
-    fn test_sdk_serialize() {
+    fn test_layout_offsets_match_fin_plan_transaction() {
+        let keypair = Keypair::new();
+        let tx = Transaction::fin_plan_new(&keypair, keypair.pubkey(), 42, Hash::default());
+        let buf = serialize(&tx).unwrap();
+
+        let sig_bytes = &buf[SIG_OFFSET..SIG_OFFSET + size_of::<Signature>()];
+        assert_eq!(sig_bytes, tx.signature.as_ref());
+
+        let signed_data = &buf[SIGNED_DATA_OFFSET..];
+        assert_eq!(signed_data, &tx.get_sign_data()[..]);
+
+        let pub_key_bytes = &buf[PUB_KEY_OFFSET..PUB_KEY_OFFSET + 32];
+        assert_eq!(pub_key_bytes, tx.from().as_ref());
+    }
+
+    #[test]
+    fn test_sdk_serialize_roundtrip() {
         let keypair = &GenKeys::new([0u8; 32]).gen_n_keypairs(1)[0];
         let to = Pubkey::new(&[
             1, 1, 1, 4, 5, 6, 7, 8, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 8, 7, 6, 5, 4,
@@ -113,22 +214,33 @@ mod tests {
             Hash::default(),
             99,
         );
-        assert_eq!(
-            serialize(&tx).unwrap(),
-            vec![
-                88, 1, 212, 176, 31, 197, 35, 156, 135, 24, 30, 57, 204, 253, 224, 28, 89, 189, 53,
-                64, 27, 148, 42, 199, 43, 236, 85, 182, 150, 64, 96, 53, 255, 235, 90, 197, 228, 6,
-                105, 22, 140, 209, 206, 221, 85, 117, 125, 126, 11, 1, 176, 130, 57, 236, 7, 155,
-                127, 58, 130, 92, 230, 219, 254, 0, 3, 0, 0, 0, 0, 0, 0, 0, 32, 253, 186, 201, 177,
-                11, 117, 135, 187, 167, 181, 188, 22, 59, 206, 105, 231, 150, 215, 30, 78, 212, 76,
-                16, 252, 180, 72, 134, 137, 247, 161, 68, 32, 253, 186, 201, 177, 11, 117, 135,
-                187, 167, 181, 188, 22, 59, 206, 105, 231, 150, 215, 30, 78, 212, 76, 16, 252, 180,
-                72, 134, 137, 247, 161, 68, 1, 1, 1, 4, 5, 6, 7, 8, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9,
-                9, 9, 9, 9, 9, 9, 8, 7, 6, 5, 4, 1, 1, 1, 2, 2, 2, 4, 5, 6, 7, 8, 9, 1, 1, 1, 1, 1,
-                1, 1, 1, 1, 1, 1, 1, 1, 1, 9, 8, 7, 6, 5, 4, 2, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 99, 0, 0, 0, 0,
-                0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3
-            ],
+        let buf = serialize(&tx).unwrap();
+        let tx1: Transaction = deserialize(&buf).unwrap();
+        assert_eq!(tx1, tx);
+        assert_eq!(tx.instructions.len(), 1);
+        assert_eq!(tx.program_ids, vec![program_id]);
+    }
+
+    #[test]
+    fn test_new_with_instructions() {
+        let keypair = Keypair::new();
+        let contract = Keypair::new().pubkey();
+        let system_id = Pubkey::default();
+        let fin_plan_id = Pubkey::new(&[3u8; 32]);
+
+        let tx = Transaction::new_with_instructions(
+            &keypair,
+            &[contract],
+            Hash::default(),
+            0,
+            vec![system_id, fin_plan_id],
+            vec![(0, vec![1, 2, 3], vec![0, 1]), (1, vec![4, 5], vec![1])],
         );
+
+        assert_eq!(tx.keys, vec![keypair.pubkey(), contract]);
+        assert_eq!(tx.program_ids, vec![system_id, fin_plan_id]);
+        assert_eq!(tx.instructions[0].accounts, vec![0, 1]);
+        assert_eq!(tx.instructions[1].accounts, vec![1]);
+        assert!(tx.verify_signature());
     }
 }
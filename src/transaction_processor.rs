@@ -1,3 +1,4 @@
+use accounts::{Accounts, ErrorCounters, ProgramAccountsFilter};
 use bincode::deserialize;
 use bincode::serialize;
 use fin_plan_program::FinPlanState;
@@ -10,29 +11,61 @@ use itertools::Itertools;
 use ledger::Block;
 use log::Level;
 use mint::Mint;
+use nonce_program::NonceState;
 use trx_out::Payment;
+use rayon::prelude::*;
 use signature::{Keypair, Signature};
 use xpz_program_interface::account::{Account, KeyedAccount};
 use xpz_program_interface::pubkey::Pubkey;
 use std;
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::collections::{HashMap, VecDeque};
 use std::result;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::RwLock;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use storage_program::StorageProgram;
 use builtin_pgm::SystemProgram;
 use builtin_tansaction::SystemTransaction;
 use tictactoe_dashboard_program::TicTacToeDashboardProgram;
 use tictactoe_program::TicTacToeProgram;
 use timing::{duration_as_us, timestamp};
-use transaction::Transaction;
+use transaction::{Instruction, Transaction};
+use vote_program::{VoteProgram, VoteState};
 use window::WINDOW_SIZE;
 
+/// Width of the recent-id window tracked in `last_ids`/`last_ids_sigs`. A
+/// transaction's `last_id` must still be in this window for
+/// `reserve_signature_with_last_id` to accept it; once `register_entry_id`
+/// evicts an id past this depth, any transaction still referencing it is
+/// rejected as `LastIdNotFound` rather than accepted indefinitely, and that
+/// id's whole signature-dedup set is dropped with it so memory stays
+/// bounded by the window rather than growing for the life of the ledger.
 pub const MAX_ENTRY_IDS: usize = 1024 * 16;
 
 pub const VERIFY_BLOCK_SIZE: usize = 16;
 
+/// How long an unresolved `signature_subscriptions` entry is kept around
+/// before `reap_expired_signature_subscriptions` gives up on it. Most
+/// subscriptions are cleared long before this by `update_signature_status`
+/// or by their `last_id` aging out of the `MAX_ENTRY_IDS` window, but a
+/// subscription on a signature that's simply never submitted would
+/// otherwise sit in the map forever.
+pub const SIGNATURE_SUBSCRIPTION_TTL_MS: u64 = 60_000;
+
+/// Caps how many accounts `get_program_accounts` returns in a single call;
+/// past this the scan is rejected with `TooManyProgramAccounts` rather than
+/// risk an unbounded response payload.
+pub const MAX_PROGRAM_ACCOUNTS: usize = 1000;
+
+/// How many of the most recently paid transaction fees `get_recommended_fee`
+/// draws its percentile from. Bounded so the corpus tracks current demand
+/// rather than growing for the life of the ledger.
+const FEE_CORPUS_CAPACITY: usize = 1024;
+
+/// Fee returned by `get_recommended_fee` while the corpus is still empty
+/// (right after startup, before any transaction has paid a fee).
+pub const MIN_FEE: i64 = 0;
+
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TransactionProcessorError {
@@ -60,20 +93,53 @@ pub enum TransactionProcessorError {
     ExternalAccountTokenSpend,
 
     ProgramRuntimeError,
+
+    AccountInUse,
+
+    UnsupportedVersion,
+
+    TooManyProgramAccounts,
 }
 
 pub type Result<T> = result::Result<T, TransactionProcessorError>;
 type SignatureStatusMap = HashMap<Signature, Result<()>>;
 
-#[derive(Default)]
-struct ErrorCounters {
-    account_not_found_validator: usize,
-    account_not_found_leader: usize,
-    account_not_found_vote: usize,
+/// The value delivered to a signature subscription: the same `Result<()>`
+/// `get_signature_status` would return once the signature is known.
+pub type Status = Result<()>;
+
+/// A one-shot callback a caller registers to be notified of a signature's
+/// status or an account's latest value, instead of having to poll for it.
+pub trait Sink<T>: Send + Sync {
+    fn notify(&self, value: T);
+}
+
+/// The `last_ids`/`last_ids_sigs`/`transaction_count` state captured by a
+/// `checkpoint()`, so `rollback()` can put it all back exactly as it was
+/// rather than just discarding the accompanying account delta.
+#[derive(Clone)]
+struct CheckpointSnapshot {
+    last_ids: VecDeque<Hash>,
+    last_ids_sigs: HashMap<Hash, (SignatureStatusMap, u64)>,
+    transaction_count: usize,
 }
 
 pub struct TransactionProcessor {
-    accounts: RwLock<HashMap<Pubkey, Account>>,
+    /// Account storage: the live account map, the checkpoint/rollback delta
+    /// stack, per-account locks, and account-change subscriptions.
+    accounts: Accounts,
+
+    /// Parallel stack to `accounts`' own delta stack: the `last_ids`/
+    /// `last_ids_sigs`/`transaction_count` state as of each open
+    /// checkpoint, so that state can be restored on `rollback`.
+    checkpoints: RwLock<Vec<CheckpointSnapshot>>,
+
+    /// Subscribers waiting on a signature's terminal status, notified and
+    /// dropped the moment that status is written in `update_signature_status`.
+    /// Each entry is timestamped at registration so
+    /// `reap_expired_signature_subscriptions` can find ones that outlived
+    /// `SIGNATURE_SUBSCRIPTION_TTL_MS` without ever resolving.
+    signature_subscriptions: RwLock<HashMap<Signature, Vec<(u64, Box<Sink<Status>>)>>>,
 
     last_ids: RwLock<VecDeque<Hash>>,
 
@@ -81,8 +147,21 @@ pub struct TransactionProcessor {
 
     transaction_count: AtomicUsize,
 
+    /// Ring buffer of the fees actually paid by the last
+    /// `FEE_CORPUS_CAPACITY` successfully processed transactions, snapshotted
+    /// and sorted on demand by `get_recommended_fee` rather than kept sorted
+    /// on every insert, so recording a fee off the processing hot path stays
+    /// a single push/pop.
+    fee_corpus: RwLock<VecDeque<i64>>,
+
     pub is_leader: bool,
 
+    /// Whether version-1 (multi-instruction) transactions are accepted.
+    /// Defaults to `false` so a ledger recorded before this feature
+    /// shipped keeps replaying exactly as it did before; version-0
+    /// transactions are always accepted regardless of this flag.
+    pub allow_versioned: bool,
+
     finality_time: AtomicUsize,
 
     loaded_contracts: RwLock<HashMap<Pubkey, DynamicProgram>>,
@@ -91,11 +170,15 @@ pub struct TransactionProcessor {
 impl Default for TransactionProcessor {
     fn default() -> Self {
         TransactionProcessor {
-            accounts: RwLock::new(HashMap::new()),
+            accounts: Accounts::default(),
+            checkpoints: RwLock::new(Vec::new()),
+            signature_subscriptions: RwLock::new(HashMap::new()),
             last_ids: RwLock::new(VecDeque::new()),
             last_ids_sigs: RwLock::new(HashMap::new()),
             transaction_count: AtomicUsize::new(0),
+            fee_corpus: RwLock::new(VecDeque::new()),
             is_leader: true,
+            allow_versioned: false,
             finality_time: AtomicUsize::new(std::usize::MAX),
             loaded_contracts: RwLock::new(HashMap::new()),
         }
@@ -112,11 +195,9 @@ impl TransactionProcessor {
 
     pub fn new_from_deposit(deposit: &Payment) -> Self {
         let transaction_processor = Self::default();
-        {
-            let mut accounts = transaction_processor.accounts.write().unwrap();
-            let account = accounts.entry(deposit.to).or_insert_with(Account::default);
-            Self::apply_payment(deposit, account);
-        }
+        let mut account = transaction_processor.accounts.get_account(&deposit.to).unwrap_or_default();
+        Self::apply_payment(deposit, &mut account);
+        transaction_processor.accounts.set_account(deposit.to, Some(account));
         transaction_processor
     }
 
@@ -135,6 +216,63 @@ impl TransactionProcessor {
         account.tokens += payment.tokens;
     }
 
+    /// Push a new checkpoint on both the account layer and the
+    /// `last_ids`/`last_ids_sigs`/`transaction_count` state: writes made
+    /// from here on land in the new layer until it's either discarded with
+    /// `rollback` or folded into the layer below with `squash`.
+    pub fn checkpoint(&self) {
+        let snapshot = CheckpointSnapshot {
+            last_ids: self.last_ids.read().unwrap().clone(),
+            last_ids_sigs: self.last_ids_sigs.read().unwrap().clone(),
+            transaction_count: self.transaction_count.load(Ordering::Relaxed),
+        };
+        self.accounts.checkpoint();
+        self.checkpoints.write().unwrap().push(snapshot);
+    }
+
+    /// Discard every change made since the matching `checkpoint`, putting
+    /// `last_ids`/`last_ids_sigs`/`transaction_count` back the way they
+    /// were at that point too. Panics if there's no open checkpoint.
+    pub fn rollback(&self) {
+        self.accounts.rollback();
+        let snapshot = self
+            .checkpoints
+            .write()
+            .unwrap()
+            .pop()
+            .expect("rollback() with no open checkpoint");
+        *self.last_ids.write().unwrap() = snapshot.last_ids;
+        *self.last_ids_sigs.write().unwrap() = snapshot.last_ids_sigs;
+        self.transaction_count
+            .store(snapshot.transaction_count, Ordering::Relaxed);
+    }
+
+    /// Merge the top checkpoint layer down into the one below it, keeping
+    /// the changes instead of discarding them. Panics if there's no open
+    /// checkpoint.
+    pub fn squash(&self) {
+        self.accounts.squash();
+        self.checkpoints
+            .write()
+            .unwrap()
+            .pop()
+            .expect("squash() with no open checkpoint");
+    }
+
+
+    /// Fold checkpoint layers older than the most recent `depth` into the
+    /// base state, on both the account delta stack and this mirrored
+    /// snapshot stack, so a validator doesn't have to keep every
+    /// speculative layer back to the root once a fork that deep is
+    /// confirmed final.
+    pub fn purge(&self, depth: usize) {
+        self.accounts.purge(depth);
+        let mut checkpoints = self.checkpoints.write().unwrap();
+        let len = checkpoints.len();
+        if len > depth {
+            checkpoints.drain(0..len - depth);
+        }
+    }
 
     pub fn last_id(&self) -> Hash {
         let last_ids = self.last_ids.read().expect("'last_ids' read lock");
@@ -153,10 +291,26 @@ impl TransactionProcessor {
         Ok(())
     }
 
+    /// Wipe every tracked signature status, and fire a terminal
+    /// `SignatureNotFound` at any subscription still waiting on one of
+    /// them — their signature is about to become unrecognized, so they'd
+    /// otherwise wait forever for a status that will never be written.
     pub fn clear_signatures(&self) {
         for (_, sigs) in self.last_ids_sigs.write().unwrap().iter_mut() {
             sigs.0.clear();
         }
+
+        let stale_subscriptions: Vec<_> = self
+            .signature_subscriptions
+            .write()
+            .unwrap()
+            .drain()
+            .collect();
+        for (_, sinks) in stale_subscriptions {
+            for (_, sink) in sinks {
+                sink.notify(Err(TransactionProcessorError::SignatureNotFound));
+            }
+        }
     }
 
     fn reserve_signature_with_last_id(&self, signature: &Signature, last_id: &Hash) -> Result<()> {
@@ -172,12 +326,19 @@ impl TransactionProcessor {
     }
 
     fn update_signature_status(
+        &self,
         signatures: &mut SignatureStatusMap,
         signature: &Signature,
         result: &Result<()>,
     ) {
         let entry = signatures.entry(*signature).or_insert(Ok(()));
         *entry = result.clone();
+
+        if let Some(sinks) = self.signature_subscriptions.write().unwrap().remove(signature) {
+            for (_, sink) in sinks {
+                sink.notify(result.clone());
+            }
+        }
     }
 
     fn update_signature_status_with_last_id(
@@ -187,7 +348,7 @@ impl TransactionProcessor {
         last_id: &Hash,
     ) {
         if let Some(entry) = self.last_ids_sigs.write().unwrap().get_mut(last_id) {
-            Self::update_signature_status(&mut entry.0, signature, result);
+            self.update_signature_status(&mut entry.0, signature, result);
         }
     }
 
@@ -197,6 +358,86 @@ impl TransactionProcessor {
         }
     }
 
+    /// Register `sink` to be notified once with `signature`'s terminal
+    /// status, whenever `update_signature_status` next writes one. The
+    /// subscription is dropped automatically after it fires.
+    pub fn add_signature_subscription(&self, signature: Signature, sink: Box<Sink<Status>>) {
+        self.signature_subscriptions
+            .write()
+            .unwrap()
+            .entry(signature)
+            .or_insert_with(Vec::new)
+            .push((timestamp(), sink));
+    }
+
+    pub fn remove_signature_subscription(&self, signature: &Signature) {
+        self.signature_subscriptions.write().unwrap().remove(signature);
+    }
+
+    /// Fire a terminal `SignatureNotFound` at, and drop, any signature
+    /// subscription registered more than `SIGNATURE_SUBSCRIPTION_TTL_MS`
+    /// ago. Meant to be called periodically from a background reaper
+    /// (see `rpc_pubsub::SignatureReaperService`) rather than from the
+    /// transaction-processing hot path. Returns the number reaped.
+    pub fn reap_expired_signature_subscriptions(&self) -> usize {
+        let now = timestamp();
+        let mut reaped = Vec::new();
+        self.signature_subscriptions
+            .write()
+            .unwrap()
+            .retain(|_, sinks| {
+                let (expired, live): (Vec<_>, Vec<_>) = sinks
+                    .drain(..)
+                    .partition(|(registered_at, _)| now.saturating_sub(*registered_at) >= SIGNATURE_SUBSCRIPTION_TTL_MS);
+                reaped.extend(expired);
+                *sinks = live;
+                !sinks.is_empty()
+            });
+
+        let count = reaped.len();
+        for (_, sink) in reaped {
+            sink.notify(Err(TransactionProcessorError::SignatureNotFound));
+        }
+        count
+    }
+
+    /// Register `sink` to be notified with `pubkey`'s latest `Account`
+    /// every time `store_accounts` writes to it, until explicitly removed.
+    pub fn add_account_subscription(&self, pubkey: Pubkey, sink: Box<Sink<Account>>) {
+        self.accounts.add_subscription(pubkey, sink);
+    }
+
+    pub fn remove_account_subscription(&self, pubkey: &Pubkey) {
+        self.accounts.remove_subscription(pubkey);
+    }
+
+    /// Record the fees actually paid by a batch of just-committed
+    /// transactions into `fee_corpus`, evicting the oldest entries past
+    /// `FEE_CORPUS_CAPACITY`. Takes the write lock once for the whole batch
+    /// so this stays cheap on the `process_transactions` hot path.
+    fn record_fees(&self, fees: &[i64]) {
+        let mut fee_corpus = self.fee_corpus.write().unwrap();
+        fee_corpus.extend(fees);
+        while fee_corpus.len() > FEE_CORPUS_CAPACITY {
+            fee_corpus.pop_front();
+        }
+    }
+
+    /// The fee at `percentile` (clamped to `[0, 100]`) among the most
+    /// recently paid transaction fees, or `MIN_FEE` if none have been
+    /// recorded yet. Snapshots and sorts the corpus on this call rather
+    /// than keeping it sorted on every `record_fees`, so a query never
+    /// blocks transaction processing.
+    pub fn get_recommended_fee(&self, percentile: u8) -> i64 {
+        let mut fees: Vec<i64> = self.fee_corpus.read().unwrap().iter().cloned().collect();
+        if fees.is_empty() {
+            return MIN_FEE;
+        }
+        fees.sort();
+        let percentile = percentile.min(100) as usize;
+        let index = percentile * (fees.len() - 1) / 100;
+        fees[index]
+    }
 
     pub fn count_valid_ids(&self, ids: &[Hash]) -> Vec<(usize, u64)> {
         let last_ids = self.last_ids_sigs.read().unwrap();
@@ -210,6 +451,11 @@ impl TransactionProcessor {
     }
 
 
+    /// Push `last_id` onto the recent-id ring, evicting and forgetting the
+    /// oldest id (and its signature-dedup set) once the ring is full at
+    /// `MAX_ENTRY_IDS`. This is what gives transactions natural expiry tied
+    /// to PoH progress: a transaction built against an id old enough to have
+    /// fallen off the back of this ring can no longer be replayed.
     pub fn register_entry_id(&self, last_id: &Hash) {
         let mut last_ids = self
             .last_ids
@@ -221,7 +467,20 @@ impl TransactionProcessor {
             .expect("last_ids_sigs write lock");
         if last_ids.len() >= MAX_ENTRY_IDS {
             let id = last_ids.pop_front().unwrap();
-            last_ids_sigs.remove(&id);
+            if let Some((evicted_sigs, _)) = last_ids_sigs.remove(&id) {
+                // Any subscription still waiting on one of these signatures
+                // just had its last_id fall out of the window — it can
+                // never be confirmed now, so fire a terminal notification
+                // instead of leaving the subscriber to wait forever.
+                let mut subscriptions = self.signature_subscriptions.write().unwrap();
+                for signature in evicted_sigs.keys() {
+                    if let Some(sinks) = subscriptions.remove(signature) {
+                        for (_, sink) in sinks {
+                            sink.notify(Err(TransactionProcessorError::SignatureNotFound));
+                        }
+                    }
+                }
+            }
         }
         last_ids_sigs.insert(*last_id, (HashMap::new(), timestamp()));
         last_ids.push_back(*last_id);
@@ -237,66 +496,56 @@ impl TransactionProcessor {
         }
     }
 
-    fn load_account(
+    /// Reserve `tx`'s signature and gate it on its version, on top of the
+    /// account load `Accounts::load_accounts` already did. Kept on
+    /// `TransactionProcessor` rather than `Accounts` since both the version
+    /// flag and the signature/last-id bookkeeping this reserves against
+    /// live here, not in the account layer.
+    fn finish_load_account(
         &self,
         tx: &Transaction,
-        accounts: &HashMap<Pubkey, Account>,
+        account_result: Result<Vec<Account>>,
         error_counters: &mut ErrorCounters,
     ) -> Result<Vec<Account>> {
+        if tx.version > 0 && !self.allow_versioned {
+            return Err(TransactionProcessorError::UnsupportedVersion);
+        }
 
-        if accounts.get(&tx.keys[0]).is_none() {
-            if !self.is_leader {
-                error_counters.account_not_found_validator += 1;
-            } else {
-                error_counters.account_not_found_leader += 1;
-            }
-            if FinPlanState::check_id(&tx.program_id) {
-                use fin_plan_instruction::Instruction;
-                if let Some(Instruction::NewVote(_vote)) = tx.instruction() {
-                    error_counters.account_not_found_vote += 1;
+        let called_accounts = account_result?;
+
+        if let Err(e) = self.reserve_signature_with_last_id(&tx.signature, &tx.last_id) {
+            match e {
+                TransactionProcessorError::DuplicateSignature => {
+                    error_counters.duplicate_signature += 1;
+                }
+                TransactionProcessorError::LastIdNotFound => {
+                    error_counters.last_id_not_found += 1;
+                }
+                _ => {
+                    error_counters.reserve_signature += 1;
                 }
             }
-            Err(TransactionProcessorError::AccountNotFound)
-        } else if accounts.get(&tx.keys[0]).unwrap().tokens < tx.fee {
-            Err(TransactionProcessorError::InsufficientFundsForFee)
-        } else {
-            let mut called_accounts: Vec<Account> = tx
-                .keys
-                .iter()
-                .map(|key| accounts.get(key).cloned().unwrap_or_default())
-                .collect();
-            self.reserve_signature_with_last_id(&tx.signature, &tx.last_id)?;
-            called_accounts[0].tokens -= tx.fee;
-            Ok(called_accounts)
+            return Err(e);
         }
-    }
 
-    fn load_accounts(
-        &self,
-        txs: &[Transaction],
-        accounts: &HashMap<Pubkey, Account>,
-        error_counters: &mut ErrorCounters,
-    ) -> Vec<Result<Vec<Account>>> {
-        txs.iter()
-            .map(|tx| self.load_account(tx, accounts, error_counters))
-            .collect()
+        Ok(called_accounts)
     }
 
     pub fn verify_transaction(
-        tx: &Transaction,
+        program_id: &Pubkey,
         pre_program_id: &Pubkey,
         pre_tokens: i64,
         account: &Account,
     ) -> Result<()> {
 
         if !((*pre_program_id == account.program_id)
-            || (SystemProgram::check_id(&tx.program_id)
+            || (SystemProgram::check_id(program_id)
                 && SystemProgram::check_id(&pre_program_id)))
         {
             return Err(TransactionProcessorError::ModifiedContractId);
         }
 
-        if tx.program_id != account.program_id && pre_tokens > account.tokens {
+        if *program_id != account.program_id && pre_tokens > account.tokens {
             return Err(TransactionProcessorError::ExternalAccountTokenSpend);
         }
         if account.tokens < 0 {
@@ -305,118 +554,204 @@ impl TransactionProcessor {
         Ok(())
     }
 
-    fn loaded_contract(&self, tx: &Transaction, accounts: &mut [Account]) -> bool {
+    fn loaded_contract(
+        &self,
+        program_id: &Pubkey,
+        keys: &[Pubkey],
+        userdata: &[u8],
+        accounts: &mut [Account],
+    ) -> bool {
         let loaded_contracts = self.loaded_contracts.write().unwrap();
-        match loaded_contracts.get(&tx.program_id) {
+        match loaded_contracts.get(program_id) {
             Some(dc) => {
-                let mut infos: Vec<_> = (&tx.keys)
-                    .into_iter()
+                let mut infos: Vec<_> = keys
+                    .iter()
                     .zip(accounts)
                     .map(|(key, account)| KeyedAccount { key, account })
                     .collect();
 
-                dc.call(&mut infos, &tx.userdata);
+                dc.call(&mut infos, userdata);
                 true
             }
             None => false,
         }
     }
 
+    /// The built-in programs `execute_instruction` dispatches to, in the
+    /// order their `program_id` is checked. Each entry pairs a program's id
+    /// check with a handler normalized to a plain `Result<(), ()>`, so
+    /// adding a new built-in program is just adding a row here rather than
+    /// another `else if` branch.
+    fn builtin_handlers() -> &'static [(fn(&Pubkey) -> bool, fn(&Transaction, &mut [Account]) -> result::Result<(), ()>)] {
+        &[
+            (FinPlanState::check_id, |tx, accounts| {
+                FinPlanState::process_transaction(tx, accounts).map_err(|_| ())
+            }),
+            (StorageProgram::check_id, |tx, accounts| {
+                StorageProgram::process_transaction(tx, accounts).map_err(|_| ())
+            }),
+            (TicTacToeProgram::check_id, |tx, accounts| {
+                TicTacToeProgram::process_transaction(tx, accounts).map_err(|_| ())
+            }),
+            (TicTacToeDashboardProgram::check_id, |tx, accounts| {
+                TicTacToeDashboardProgram::process_transaction(tx, accounts).map_err(|_| ())
+            }),
+            (NonceState::check_id, |tx, accounts| {
+                NonceState::process_transaction(tx, accounts).map_err(|_| ())
+            }),
+            (VoteState::check_id, |tx, accounts| {
+                VoteState::process_transaction(tx, accounts).map_err(|_| ())
+            }),
+        ]
+    }
+
+    /// Route a single instruction to the handler selected by its
+    /// `program_id`: the system program, one of the `builtin_handlers`, a
+    /// dynamically loaded contract, or — if none of those recognize it —
+    /// a typed `UnknownContractId` rejection rather than a panic.
+    fn execute_instruction(
+        &self,
+        tx: &Transaction,
+        instruction: &Instruction,
+        accounts: &mut [Account],
+    ) -> Result<()> {
+        let program_id = tx.program_ids[instruction.program_ids_index as usize];
 
-    fn execute_transaction(&self, tx: &Transaction, accounts: &mut [Account]) -> Result<()> {
-        let pre_total: i64 = accounts.iter().map(|a| a.tokens).sum();
-        let pre_data: Vec<_> = accounts
-            .iter_mut()
-            .map(|a| (a.program_id, a.tokens))
-            .collect();
-
-  
-        if SystemProgram::check_id(&tx.program_id) {
+        if SystemProgram::check_id(&program_id) {
             SystemProgram::process_transaction(&tx, accounts, &self.loaded_contracts)
-        } else if FinPlanState::check_id(&tx.program_id) {
-
-            if FinPlanState::process_transaction(&tx, accounts).is_err() {
-                return Err(TransactionProcessorError::ProgramRuntimeError);
-            }
-        } else if StorageProgram::check_id(&tx.program_id) {
-            if StorageProgram::process_transaction(&tx, accounts).is_err() {
-                return Err(TransactionProcessorError::ProgramRuntimeError);
-            }
-        } else if TicTacToeProgram::check_id(&tx.program_id) {
-            if TicTacToeProgram::process_transaction(&tx, accounts).is_err() {
-                return Err(TransactionProcessorError::ProgramRuntimeError);
-            }
-        } else if TicTacToeDashboardProgram::check_id(&tx.program_id) {
-            if TicTacToeDashboardProgram::process_transaction(&tx, accounts).is_err() {
+        } else if let Some((_, handler)) = Self::builtin_handlers()
+            .iter()
+            .find(|(check_id, _)| check_id(&program_id))
+        {
+            if handler(tx, accounts).is_err() {
                 return Err(TransactionProcessorError::ProgramRuntimeError);
             }
-        } else if self.loaded_contract(&tx, accounts) {
         } else {
-            return Err(TransactionProcessorError::UnknownContractId);
+            let keys: Vec<Pubkey> = instruction
+                .accounts
+                .iter()
+                .map(|&i| tx.keys[i as usize])
+                .collect();
+            if !self.loaded_contract(&program_id, &keys, &instruction.userdata, accounts) {
+                return Err(TransactionProcessorError::UnknownContractId);
+            }
         }
 
-        for ((pre_program_id, pre_tokens), post_account) in pre_data.iter().zip(accounts.iter()) {
-            Self::verify_transaction(&tx, pre_program_id, *pre_tokens, post_account)?;
-        }
+        Ok(())
+    }
 
-        let post_total: i64 = accounts.iter().map(|a| a.tokens).sum();
-        if pre_total != post_total {
-            Err(TransactionProcessorError::UnbalancedTransaction)
-        } else {
-            Ok(())
+    /// Run every instruction in `tx` in order against a scratch copy of
+    /// `accounts`, each handed only the `&mut [Account]` slice its
+    /// instruction names. `accounts` is only overwritten once every
+    /// instruction succeeds and the whole transaction balances, so a
+    /// failing instruction leaves the caller's loaded copy untouched and
+    /// nothing is ever committed for it.
+    fn execute_transaction(&self, tx: &Transaction, accounts: &mut [Account]) -> Result<()> {
+        if tx.version > 0 && !self.allow_versioned {
+            return Err(TransactionProcessorError::UnsupportedVersion);
         }
-    }
 
-    pub fn store_accounts(
-        txs: &[Transaction],
-        res: &[Result<()>],
-        loaded: &[Result<Vec<Account>>],
-        accounts: &mut HashMap<Pubkey, Account>,
-    ) {
-        for (i, racc) in loaded.iter().enumerate() {
-            if res[i].is_err() || racc.is_err() {
-                continue;
-            }
+        let pre_total: i64 = accounts.iter().map(|a| a.tokens).sum();
 
-            let tx = &txs[i];
-            let acc = racc.as_ref().unwrap();
-            for (key, account) in tx.keys.iter().zip(acc.iter()) {
-                if account.tokens == 0 {
-                    accounts.remove(&key);
-                } else {
-                    *accounts.entry(*key).or_insert_with(Account::default) = account.clone();
-                    assert_eq!(accounts.get(key).unwrap().tokens, account.tokens);
-                }
+        let mut scratch: Vec<Account> = accounts.to_vec();
+
+        for instruction in &tx.instructions {
+            let program_id = tx.program_ids[instruction.program_ids_index as usize];
+
+            let pre_data: Vec<_> = instruction
+                .accounts
+                .iter()
+                .map(|&i| {
+                    let account = &scratch[i as usize];
+                    (account.program_id, account.tokens)
+                }).collect();
+
+            let mut ix_accounts: Vec<Account> = instruction
+                .accounts
+                .iter()
+                .map(|&i| scratch[i as usize].clone())
+                .collect();
+
+            self.execute_instruction(tx, instruction, &mut ix_accounts)?;
+
+            for (idx, &key_index) in instruction.accounts.iter().enumerate() {
+                let (pre_program_id, pre_tokens) = pre_data[idx];
+                Self::verify_transaction(
+                    &program_id,
+                    &pre_program_id,
+                    pre_tokens,
+                    &ix_accounts[idx],
+                )?;
+                scratch[key_index as usize] = ix_accounts[idx].clone();
             }
         }
+
+        let post_total: i64 = scratch.iter().map(|a| a.tokens).sum();
+        if pre_total != post_total {
+            return Err(TransactionProcessorError::UnbalancedTransaction);
+        }
+
+        accounts.clone_from_slice(&scratch);
+        Ok(())
     }
 
     #[must_use]
     pub fn process_transactions(&self, txs: &[Transaction]) -> Vec<Result<()>> {
         debug!("processing transactions: {}", txs.len());
 
-        let mut accounts = self.accounts.write().unwrap();
         let txs_len = txs.len();
         let mut error_counters = ErrorCounters::default();
+
         let now = Instant::now();
-        let mut loaded_accounts = self.load_accounts(&txs, &accounts, &mut error_counters);
-        let load_elapsed = now.elapsed();
+        let lock_results = self.accounts.lock_accounts(txs);
+        let lock_elapsed = now.elapsed();
+
+        // Only the txs that won their lock get loaded; a losing tx just
+        // carries its `AccountInUse` result straight through to `res`.
         let now = Instant::now();
+        let mut account_results = self.accounts.load_accounts(txs, self.is_leader, &mut error_counters);
+        let mut loaded: Vec<Result<Vec<Account>>> = txs
+            .iter()
+            .zip(lock_results.iter())
+            .zip(account_results.drain(..))
+            .map(|((tx, lock_result), account_result)| match lock_result {
+                Err(e) => Err(e.clone()),
+                Ok(()) => self.finish_load_account(tx, account_result, &mut error_counters),
+            }).collect();
+        let load_elapsed = now.elapsed();
 
-        let res: Vec<_> = loaded_accounts
-            .iter_mut()
-            .zip(txs.iter())
-            .map(|(acc, tx)| match acc {
+        // Every tx that made it this far touches a disjoint set of
+        // accounts (that's what `lock_accounts` guaranteed), so they can
+        // all execute concurrently against their own loaded copy.
+        let now = Instant::now();
+        let res: Vec<Result<()>> = txs
+            .par_iter()
+            .zip(loaded.par_iter_mut())
+            .map(|(tx, acc)| match acc {
                 Err(e) => Err(e.clone()),
                 Ok(ref mut accounts) => self.execute_transaction(tx, accounts),
             }).collect();
         let execution_elapsed = now.elapsed();
+
         let now = Instant::now();
-        Self::store_accounts(&txs, &res, &loaded_accounts, &mut accounts);
-        self.update_transaction_statuses(&txs, &res);
+        self.accounts.store_accounts(txs, &res, &loaded);
+        self.update_transaction_statuses(txs, &res);
+        self.accounts.unlock_accounts(txs, &lock_results);
+
+        let paid_fees: Vec<i64> = txs
+            .iter()
+            .zip(res.iter())
+            .filter(|(_, r)| r.is_ok())
+            .map(|(tx, _)| tx.fee)
+            .collect();
+        if !paid_fees.is_empty() {
+            self.record_fees(&paid_fees);
+        }
         let write_elapsed = now.elapsed();
+
         debug!(
-            "load: {}us execution: {}us write: {}us txs_len={}",
+            "lock: {}us load: {}us execution: {}us write: {}us txs_len={}",
+            duration_as_us(&lock_elapsed),
             duration_as_us(&load_elapsed),
             duration_as_us(&execution_elapsed),
             duration_as_us(&write_elapsed),
@@ -453,10 +788,26 @@ impl TransactionProcessor {
                     error_counters.account_not_found_vote
                 );
             }
+            inc_new_counter_info!(
+                "transaction_processor-appy_debits-insufficient_funds",
+                error_counters.insufficient_funds
+            );
+            inc_new_counter_info!(
+                "transaction_processor-appy_debits-duplicate_signature",
+                error_counters.duplicate_signature
+            );
+            inc_new_counter_info!(
+                "transaction_processor-appy_debits-last_id_not_found",
+                error_counters.last_id_not_found
+            );
+            inc_new_counter_info!(
+                "transaction_processor-appy_debits-reserve_signature",
+                error_counters.reserve_signature
+            );
         }
         let cur_tx_count = self.transaction_count.load(Ordering::Relaxed);
         if ((cur_tx_count + tx_count) & !(262_144 - 1)) > cur_tx_count & !(262_144 - 1) {
-            info!("accounts.len: {}", accounts.len());
+            info!("accounts.len: {}", self.accounts.len());
         }
         self.transaction_count
             .fetch_add(tx_count, Ordering::Relaxed);
@@ -543,18 +894,19 @@ impl TransactionProcessor {
             .expect("invalid ledger: need at least 2 entries");
         {
             let tx = &entry1.transactions[0];
-            assert!(SystemProgram::check_id(&tx.program_id), "Invalid ledger");
-            let instruction: SystemProgram = deserialize(&tx.userdata).unwrap();
+            let program_id = tx.program_ids[tx.instructions[0].program_ids_index as usize];
+            assert!(SystemProgram::check_id(&program_id), "Invalid ledger");
+            let instruction: SystemProgram = deserialize(&tx.instructions[0].userdata).unwrap();
             let deposit = if let SystemProgram::Move { tokens } = instruction {
                 Some(tokens)
             } else {
                 None
             }.expect("invalid ledger, needs to start with a contract");
             {
-                let mut accounts = self.accounts.write().unwrap();
-                let account = accounts.entry(tx.keys[0]).or_insert_with(Account::default);
+                let mut account = self.get_account(&tx.keys[0]).unwrap_or_default();
                 account.tokens += deposit;
                 trace!("applied genesis payment {:?} => {:?}", deposit, account);
+                self.accounts.set_account(tx.keys[0], Some(account));
             }
         }
         self.register_entry_id(&entry0.id);
@@ -604,11 +956,31 @@ impl TransactionProcessor {
     }
 
     pub fn get_account(&self, pubkey: &Pubkey) -> Option<Account> {
-        let accounts = self
-            .accounts
-            .read()
-            .expect("'accounts' read lock in get_balance");
-        accounts.get(pubkey).cloned()
+        self.accounts.get_account(pubkey)
+    }
+
+    /// The raw program-owned bytes stored alongside `pubkey`'s balance, for
+    /// callers that want an account's state without the whole `Account`.
+    pub fn get_account_userdata(&self, pubkey: &Pubkey) -> Option<Vec<u8>> {
+        self.get_account(pubkey).map(|account| account.userdata)
+    }
+
+    /// Every `(Pubkey, Account)` owned by `program_id` and matching every
+    /// filter in `filters`, so a thin client can enumerate a deployed
+    /// program's state in one round trip instead of guessing keys. Bounded
+    /// by `MAX_PROGRAM_ACCOUNTS` to keep the response from growing into an
+    /// unbounded UDP/HTTP payload; callers that hit the cap should narrow
+    /// their filters rather than retry.
+    pub fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        filters: &[ProgramAccountsFilter],
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        let accounts = self.accounts.accounts_matching(program_id, filters);
+        if accounts.len() > MAX_PROGRAM_ACCOUNTS {
+            return Err(TransactionProcessorError::TooManyProgramAccounts);
+        }
+        Ok(accounts)
     }
 
     pub fn transaction_count(&self) -> usize {
@@ -631,17 +1003,60 @@ impl TransactionProcessor {
 
 
     pub fn hash_internal_state(&self) -> Hash {
-        let mut ordered_accounts = BTreeMap::new();
-        for (pubkey, account) in self.accounts.read().unwrap().iter() {
-            ordered_accounts.insert(*pubkey, account.clone());
+        self.accounts.hash_internal_state()
+    }
+
+    /// Stake-weighted confirmation latency: the elapsed time, in ms, since
+    /// at least 2/3 of the tokens staked across all vote accounts voted on
+    /// the current `last_id`, or `None` if no such supermajority has
+    /// voted on it yet.
+    fn compute_finality(&self) -> Option<usize> {
+        let vote_accounts = self.accounts.accounts_owned_by(&VoteState::id());
+        let total_stake: i64 = vote_accounts.iter().map(|account| account.tokens).sum();
+        if total_stake == 0 {
+            return None;
         }
-        hash(&serialize(&ordered_accounts).unwrap())
+
+        let last_id = self.last_id();
+        let mut votes: Vec<(u64, i64)> = vote_accounts
+            .iter()
+            .filter_map(|account| {
+                let vote_program: VoteProgram = deserialize(&account.userdata).ok()?;
+                let &(_, hash) = vote_program.votes.back()?;
+                if hash == last_id {
+                    Some((vote_program.last_vote_timestamp_ms, account.tokens))
+                } else {
+                    None
+                }
+            }).collect();
+        votes.sort_by_key(|&(timestamp_ms, _)| timestamp_ms);
+
+        let mut staked_so_far = 0i64;
+        for (timestamp_ms, stake) in votes {
+            staked_so_far += stake;
+            if staked_so_far * 3 >= total_stake * 2 {
+                return Some(timestamp().saturating_sub(timestamp_ms) as usize);
+            }
+        }
+        None
     }
 
+    /// `set_finality`'s override, if one is in effect; otherwise the real
+    /// stake-weighted confirmation latency computed from vote accounts,
+    /// falling back to `usize::MAX` (unconfirmed) if no supermajority has
+    /// voted on the current `last_id`.
     pub fn finality(&self) -> usize {
-        self.finality_time.load(Ordering::Relaxed)
+        let override_ms = self.finality_time.load(Ordering::Relaxed);
+        if override_ms != std::usize::MAX {
+            return override_ms;
+        }
+        self.compute_finality().unwrap_or(std::usize::MAX)
     }
 
+    /// Override `finality()` with a fixed value, bypassing the computed
+    /// stake-weighted latency — used in tests and by `vote_stage`, which
+    /// tracks confirmation against gossiped validator agreement rather
+    /// than on-chain vote accounts.
     pub fn set_finality(&self, finality: usize) {
         self.finality_time.store(finality, Ordering::Relaxed);
     }
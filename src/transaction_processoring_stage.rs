@@ -11,22 +11,33 @@ use log::Level;
 use packet::Packets;
 use pod_recorder::PodRecorder;
 use rayon::prelude::*;
+use record_stage::RecordStage;
 use result::{Error, Result};
 use service::Service;
+use signature::Signature;
 use sigverify_stage::VerifiedPackets;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender};
 use std::sync::{Arc, Mutex};
 use std::thread::sleep;
 use std::thread::{self, Builder, JoinHandle};
 use std::time::Duration;
 use std::time::Instant;
+use sys_info;
 use timing;
 use transaction::Transaction;
-
-// number of threads is 1 until mt transaction_processor is ready
-pub const NUM_THREADS: usize = 1;
+use tx_creator::RotationSignal;
+
+/// Number of worker threads to run `process_packets` on. Safe to run above 1
+/// now that `Accounts::lock_accounts`/`unlock_accounts` serialize conflicting
+/// transactions across threads and reject the rest as `AccountInUse` rather
+/// than letting them race; each thread below gets its own cloned handle onto
+/// the shared `transaction_processor`, so they only ever contend over account
+/// locks, never over the handles themselves.
+pub fn num_threads() -> usize {
+    sys_info::cpu_num().unwrap_or(1) as usize
+}
 
 /// Stores the stage's thread handle and outx_creatort receiver.
 pub struct TransactionProcessoringStage {
@@ -48,49 +59,70 @@ impl Default for Config {
         Config::Sleep(Duration::from_millis(500))
     }
 }
+
+/// One transaction's outcome as committed at a given PoH entry height,
+/// published for RPC/indexing subscribers.
+pub struct TransactionStatus {
+    pub signature: Signature,
+    pub result: ::transaction_processor::Result<()>,
+    pub entry_height: u64,
+}
+
+/// Best-effort fan-out of committed transaction results to RPC/indexing
+/// subscribers. Backed by a bounded channel so a slow or absent subscriber
+/// can never stall the processing pipeline: a full channel just drops the
+/// update instead of blocking `process_transactions`.
+#[derive(Clone)]
+pub struct TransactionStatusSender {
+    sender: SyncSender<TransactionStatus>,
+}
+
+impl TransactionStatusSender {
+    pub fn new(capacity: usize) -> (Self, Receiver<TransactionStatus>) {
+        let (sender, receiver) = sync_channel(capacity);
+        (TransactionStatusSender { sender }, receiver)
+    }
+
+    fn send(&self, status: TransactionStatus) {
+        let _ = self.sender.try_send(status);
+    }
+}
 impl TransactionProcessoringStage {
     /// Create the stage using `transaction_processor`. Exit when `verified_receiver` is dropped.
+    /// `entry_height` seeds the counter used to tag `transaction_status_sender`
+    /// updates with the PoH height their entry will land at.
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     pub fn new(
         transaction_processor: &Arc<TransactionProcessor>,
         verified_receiver: Receiver<VerifiedPackets>,
         config: Config,
-    ) -> (Self, Receiver<Vec<Entry>>) {
+        entry_height: u64,
+        transaction_status_sender: Option<TransactionStatusSender>,
+        max_tick_height: Option<u64>,
+        rotation_sender: Sender<RotationSignal>,
+    ) -> (Self, RecordStage, Receiver<Vec<Entry>>) {
         let (entry_sender, entry_receiver) = channel();
         let shared_verified_receiver = Arc::new(Mutex::new(verified_receiver));
         let pod = PodRecorder::new(transaction_processor.clone(), entry_sender);
-        let tick_pod = pod.clone();
-        // Tick producer is a headless producer, so when it exits it should notify the transaction_processoring stage.
+        // The record stage is a headless producer, so when it exits it should notify the transaction_processoring stage.
         // Since channel are not used to talk between these threads an AtomicBool is used as a
         // signal.
         let pod_exit = Arc::new(AtomicBool::new(false));
         let transaction_processoring_exit = pod_exit.clone();
-        // Single thread to generate entries from many transaction_processors.
-        // This thread talks to pod_service and broadcasts the entries once they have been recorded.
-        // Once an entry has been recorded, its last_id is registered with the transaction_processor.
-        let tick_producer = Builder::new()
-            .name("hypercube-transaction_processoring-stage-tick_producer".to_string())
-            .spawn(move || {
-                if let Err(e) = Self::tick_producer(&tick_pod, &config, &pod_exit) {
-                    match e {
-                        Error::SendError => (),
-                        _ => error!(
-                            "hypercube-transaction_processoring-stage-tick_producer unexpected error {:?}",
-                            e
-                        ),
-                    }
-                }
-                debug!("tick producer exiting");
-                pod_exit.store(true, Ordering::Relaxed);
-            }).unwrap();
+        let record_stage = RecordStage::new(pod.clone(), config, pod_exit);
+        let entry_height = Arc::new(AtomicUsize::new(entry_height as usize));
 
         // Many transaction_processors that process transactions in parallel.
-        let mut thread_hdls: Vec<JoinHandle<()>> = (0..NUM_THREADS)
+        let thread_hdls: Vec<JoinHandle<()>> = (0..num_threads())
             .into_iter()
             .map(|_| {
                 let thread_transaction_processor = transaction_processor.clone();
                 let thread_verified_receiver = shared_verified_receiver.clone();
                 let thread_pod = pod.clone();
                 let thread_transaction_processoring_exit = transaction_processoring_exit.clone();
+                let thread_entry_height = entry_height.clone();
+                let thread_transaction_status_sender = transaction_status_sender.clone();
+                let thread_rotation_sender = rotation_sender.clone();
                 Builder::new()
                     .name("hypercube-transaction_processoring-stage-tx".to_string())
                     .spawn(move || {
@@ -99,6 +131,11 @@ impl TransactionProcessoringStage {
                                 &thread_transaction_processor,
                                 &thread_verified_receiver,
                                 &thread_pod,
+                                &thread_entry_height,
+                                &thread_transaction_status_sender,
+                                max_tick_height,
+                                &thread_rotation_sender,
+                                &thread_transaction_processoring_exit,
                             ) {
                                 debug!("got error {:?}", e);
                                 match e {
@@ -119,8 +156,7 @@ impl TransactionProcessoringStage {
                         thread_transaction_processoring_exit.store(true, Ordering::Relaxed);
                     }).unwrap()
             }).collect();
-        thread_hdls.push(tick_producer);
-        (TransactionProcessoringStage { thread_hdls }, entry_receiver)
+        (TransactionProcessoringStage { thread_hdls }, record_stage, entry_receiver)
     }
 
     /// Convert the transactions from a blob of binary data to a vector of transactions and
@@ -135,30 +171,16 @@ impl TransactionProcessoringStage {
             }).collect()
     }
 
-    fn tick_producer(pod: &PodRecorder, config: &Config, pod_exit: &AtomicBool) -> Result<()> {
-        loop {
-            match *config {
-                Config::Tick(num) => {
-                    for _ in 0..num {
-                        pod.hash();
-                    }
-                }
-                Config::Sleep(duration) => {
-                    sleep(duration);
-                }
-            }
-            pod.tick()?;
-            if pod_exit.load(Ordering::Relaxed) {
-                debug!("tick service exited");
-                return Ok(());
-            }
-        }
-    }
-
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     fn process_transactions(
         transaction_processor: &Arc<TransactionProcessor>,
         transactions: &[Transaction],
         pod: &PodRecorder,
+        entry_height: &Arc<AtomicUsize>,
+        transaction_status_sender: &Option<TransactionStatusSender>,
+        max_tick_height: Option<u64>,
+        rotation_sender: &Sender<RotationSignal>,
+        exit: &Arc<AtomicBool>,
     ) -> Result<()> {
         debug!("transactions: {}", transactions.len());
         let mut chunk_start = 0;
@@ -182,6 +204,22 @@ impl TransactionProcessoringStage {
                 let hash = Transaction::hash(&processed_transactions);
                 debug!("processed ok: {} {}", processed_transactions.len(), hash);
                 pod.record(hash, processed_transactions)?;
+                let height = entry_height.fetch_add(1, Ordering::Relaxed) as u64 + 1;
+                if let Some(sender) = transaction_status_sender {
+                    for (i, tx) in transactions[chunk_start..chunk_end].iter().enumerate() {
+                        sender.send(TransactionStatus {
+                            signature: tx.signature,
+                            result: results[i].clone(),
+                            entry_height: height,
+                        });
+                    }
+                }
+                if let Some(max_height) = max_tick_height {
+                    if height >= max_height {
+                        let _ = rotation_sender.send(RotationSignal::MaxHeightReached { entry_height: height });
+                        exit.store(true, Ordering::Relaxed);
+                    }
+                }
             }
             chunk_start = chunk_end;
         }
@@ -191,10 +229,16 @@ impl TransactionProcessoringStage {
 
     /// Process the incoming packets and send outx_creatort `Signal` messages to `signal_sender`.
     /// Discard packets via `packet_recycler`.
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     pub fn process_packets(
         transaction_processor: &Arc<TransactionProcessor>,
         verified_receiver: &Arc<Mutex<Receiver<VerifiedPackets>>>,
         pod: &PodRecorder,
+        entry_height: &Arc<AtomicUsize>,
+        transaction_status_sender: &Option<TransactionStatusSender>,
+        max_tick_height: Option<u64>,
+        rotation_sender: &Sender<RotationSignal>,
+        exit: &Arc<AtomicBool>,
     ) -> Result<()> {
         let recv_start = Instant::now();
         let mms = verified_receiver
@@ -231,7 +275,16 @@ impl TransactionProcessoringStage {
                     },
                 }).collect();
             debug!("verified transactions {}", transactions.len());
-            Self::process_transactions(transaction_processor, &transactions, pod)?;
+            Self::process_transactions(
+                transaction_processor,
+                &transactions,
+                pod,
+                entry_height,
+                transaction_status_sender,
+                max_tick_height,
+                rotation_sender,
+                exit,
+            )?;
         }
 
         inc_new_counter_info!(
@@ -284,20 +337,36 @@ mod tests {
     fn test_transaction_processoring_stage_shutdown1() {
         let transaction_processor = TransactionProcessor::new(&Mint::new(2));
         let (verified_sender, verified_receiver) = channel();
-        let (transaction_processoring_stage, _entry_receiver) =
-            TransactionProcessoringStage::new(&Arc::new(transaction_processor), verified_receiver, Default::default());
+        let (transaction_processoring_stage, record_stage, _entry_receiver) = TransactionProcessoringStage::new(
+            &Arc::new(transaction_processor),
+            verified_receiver,
+            Default::default(),
+            0,
+            None,
+            None,
+            channel().0,
+        );
         drop(verified_sender);
         assert_eq!(transaction_processoring_stage.join().unwrap(), ());
+        assert_eq!(record_stage.join().unwrap(), ());
     }
 
     #[test]
     fn test_transaction_processoring_stage_shutdown2() {
         let transaction_processor = TransactionProcessor::new(&Mint::new(2));
         let (_verified_sender, verified_receiver) = channel();
-        let (transaction_processoring_stage, entry_receiver) =
-            TransactionProcessoringStage::new(&Arc::new(transaction_processor), verified_receiver, Default::default());
+        let (transaction_processoring_stage, record_stage, entry_receiver) = TransactionProcessoringStage::new(
+            &Arc::new(transaction_processor),
+            verified_receiver,
+            Default::default(),
+            0,
+            None,
+            None,
+            channel().0,
+        );
         drop(entry_receiver);
         assert_eq!(transaction_processoring_stage.join().unwrap(), ());
+        assert_eq!(record_stage.join().unwrap(), ());
     }
 
     #[test]
@@ -305,10 +374,14 @@ mod tests {
         let transaction_processor = Arc::new(TransactionProcessor::new(&Mint::new(2)));
         let start_hash = transaction_processor.last_id();
         let (verified_sender, verified_receiver) = channel();
-        let (transaction_processoring_stage, entry_receiver) = TransactionProcessoringStage::new(
+        let (transaction_processoring_stage, record_stage, entry_receiver) = TransactionProcessoringStage::new(
             &transaction_processor,
             verified_receiver,
             Config::Sleep(Duration::from_millis(1)),
+            0,
+            None,
+            None,
+            channel().0,
         );
         sleep(Duration::from_millis(500));
         drop(verified_sender);
@@ -318,6 +391,7 @@ mod tests {
         assert!(entries.verify(&start_hash));
         assert_eq!(entries[entries.len() - 1].id, transaction_processor.last_id());
         assert_eq!(transaction_processoring_stage.join().unwrap(), ());
+        assert_eq!(record_stage.join().unwrap(), ());
     }
 
     #[test]
@@ -326,8 +400,15 @@ mod tests {
         let transaction_processor = Arc::new(TransactionProcessor::new(&mint));
         let start_hash = transaction_processor.last_id();
         let (verified_sender, verified_receiver) = channel();
-        let (transaction_processoring_stage, entry_receiver) =
-            TransactionProcessoringStage::new(&transaction_processor, verified_receiver, Default::default());
+        let (transaction_processoring_stage, record_stage, entry_receiver) = TransactionProcessoringStage::new(
+            &transaction_processor,
+            verified_receiver,
+            Default::default(),
+            0,
+            None,
+            None,
+            channel().0,
+        );
 
         // good tx
         let keypair = mint.keypair();
@@ -363,6 +444,7 @@ mod tests {
         });
         drop(entry_receiver);
         assert_eq!(transaction_processoring_stage.join().unwrap(), ());
+        assert_eq!(record_stage.join().unwrap(), ());
     }
     #[test]
     fn test_transaction_processoring_stage_entryfication() {
@@ -372,8 +454,15 @@ mod tests {
         let mint = Mint::new(2);
         let transaction_processor = Arc::new(TransactionProcessor::new(&mint));
         let (verified_sender, verified_receiver) = channel();
-        let (transaction_processoring_stage, entry_receiver) =
-            TransactionProcessoringStage::new(&transaction_processor, verified_receiver, Default::default());
+        let (transaction_processoring_stage, record_stage, entry_receiver) = TransactionProcessoringStage::new(
+            &transaction_processor,
+            verified_receiver,
+            Default::default(),
+            0,
+            None,
+            None,
+            channel().0,
+        );
 
         // Process a batch that includes a transaction that receives two tokens.
         let alice = Keypair::new();
@@ -392,6 +481,7 @@ mod tests {
             .unwrap();
         drop(verified_sender);
         assert_eq!(transaction_processoring_stage.join().unwrap(), ());
+        assert_eq!(record_stage.join().unwrap(), ());
 
         // Collect the ledger and feed it to a new transaction_processor.
         let entries: Vec<_> = entry_receiver.iter().flat_map(|x| x).collect();
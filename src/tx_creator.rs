@@ -1,50 +1,100 @@
 use transaction_processor::TransactionProcessor;
-use transaction_processoring_stage::{TransactionProcessoringStage, Config};
+use transaction_processoring_stage::{TransactionProcessoringStage, Config, TransactionStatusSender};
 use blockthread::BlockThread;
-use entry::Entry;
+use broadcast_stage::{BroadcastStage, BroadcastStageType};
 use fetch_stage::FetchStage;
+use record_stage::RecordStage;
 use service::Service;
-use signature::Keypair;
-use sigverify_stage::SigVerifyStage;
+use signature::{Keypair, KeypairUtil};
+use sigverify_stage::{SigVerifier, SigVerifyStage};
 use std::net::UdpSocket;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
 use std::sync::{Arc, RwLock};
 use std::thread;
+use vote_listener_stage::VoteListener;
+use window::SharedWindow;
 use write_stage::{WriteStage, WriteStageReturnType};
 
+/// Pushed by a leader or validator stage the instant it detects the
+/// scheduled-leader boundary, so `Fullnode` can react to a rotation without
+/// waiting on `Service::join` to unwind the old stage first.
+pub enum RotationSignal {
+    LeaderRotation { entry_height: u64 },
+    /// `TransactionProcessoringStage` hit the tick ceiling for this leader's
+    /// slot before `write_stage` noticed the boundary on its own. Carries
+    /// the exact entry height recording stopped at, so `Fullnode` can rotate
+    /// out without waiting on (or re-deriving from) anything else.
+    MaxHeightReached { entry_height: u64 },
+}
+
 pub enum TxCreatorReturnType {
-    LeaderRotation,
+    LeaderRotation(u64),
 }
 
 pub struct TxCreator {
     fetch_stage: FetchStage,
-    sigverify_stage: SigVerifyStage,
+    sigverify_stage: SigVerifyStage<Box<SigVerifier>>,
     transaction_processoring_stage: TransactionProcessoringStage,
+    record_stage: RecordStage,
+    vote_listener: VoteListener,
     write_stage: WriteStage,
+    broadcast_stage: BroadcastStage,
     exit: Arc<AtomicBool>,
 }
 
 impl TxCreator {
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     pub fn new(
         keypair: Arc<Keypair>,
         transaction_processor: &Arc<TransactionProcessor>,
         blockthread: &Arc<RwLock<BlockThread>>,
+        shared_window: SharedWindow,
         tick_duration: Config,
-        transactions_sockets: Vec<UdpSocket>,
+        transactions_sockets: Vec<Arc<UdpSocket>>,
+        tpu_forwards_sockets: Vec<Arc<UdpSocket>>,
+        broadcast_sockets: Vec<Arc<UdpSocket>>,
+        shred_version: u16,
+        broadcast_stage_type: BroadcastStageType,
         ledger_path: &str,
-        sigverify_disabled: bool,
+        sigverifier: Box<SigVerifier>,
         entry_height: u64,
-    ) -> (Self, Receiver<Vec<Entry>>, Arc<AtomicBool>) {
+        max_tick_height: Option<u64>,
+        transaction_status_sender: Option<TransactionStatusSender>,
+        rotation_sender: Sender<RotationSignal>,
+    ) -> (Self, Arc<AtomicBool>) {
         let exit = Arc::new(AtomicBool::new(false));
 
-        let (fetch_stage, packet_receiver) = FetchStage::new(transactions_sockets, exit.clone());
+        let (fetch_stage, packet_receiver) = if tpu_forwards_sockets.is_empty() {
+            FetchStage::new(transactions_sockets, exit.clone())
+        } else {
+            FetchStage::new_with_forwarder(
+                transactions_sockets,
+                tpu_forwards_sockets,
+                keypair.pubkey(),
+                blockthread.clone(),
+                exit.clone(),
+            )
+        };
 
-        let (sigverify_stage, verified_receiver) =
-            SigVerifyStage::new(packet_receiver, sigverify_disabled);
+        let (sigverify_stage, verified_receiver) = SigVerifyStage::new(packet_receiver, sigverifier);
+
+        let (transaction_processoring_stage, record_stage, entry_receiver) = TransactionProcessoringStage::new(
+            &transaction_processor,
+            verified_receiver,
+            tick_duration,
+            entry_height,
+            transaction_status_sender,
+            max_tick_height,
+            rotation_sender.clone(),
+        );
 
-        let (transaction_processoring_stage, entry_receiver) =
-            TransactionProcessoringStage::new(&transaction_processor, verified_receiver, tick_duration);
+        // Bound off the main fetch path so a burst of transactions can't
+        // starve consensus: votes are verified and applied to `blockthread`
+        // as they arrive, and also handed to the write stage below so
+        // leader-rotation decisions can use fresh consensus data instead of
+        // inferring it solely from locally written entries.
+        let (vote_listener, vote_receiver) = VoteListener::new(blockthread.clone(), exit.clone());
 
         let (write_stage, entry_forwarder) = WriteStage::new(
             keypair,
@@ -52,17 +102,32 @@ impl TxCreator {
             blockthread.clone(),
             ledger_path,
             entry_receiver,
+            vote_receiver,
+            entry_height,
+            rotation_sender,
+        );
+
+        let broadcast_stage = broadcast_stage_type.new_broadcast_stage(
+            broadcast_sockets,
+            blockthread.clone(),
+            shared_window,
             entry_height,
+            shred_version,
+            entry_forwarder,
+            exit.clone(),
         );
 
         let tx_creator = TxCreator {
             fetch_stage,
             sigverify_stage,
             transaction_processoring_stage,
+            record_stage,
+            vote_listener,
             write_stage,
+            broadcast_stage,
             exit: exit.clone(),
         };
-        (tx_creator, entry_forwarder, exit)
+        (tx_creator, exit)
     }
 
     pub fn exit(&self) -> () {
@@ -82,8 +147,14 @@ impl Service for TxCreator {
         self.fetch_stage.join()?;
         self.sigverify_stage.join()?;
         self.transaction_processoring_stage.join()?;
-        match self.write_stage.join()? {
-            WriteStageReturnType::LeaderRotation => Ok(Some(TxCreatorReturnType::LeaderRotation)),
+        self.record_stage.join()?;
+        self.vote_listener.join()?;
+        let write_stage_return = self.write_stage.join()?;
+        self.broadcast_stage.join()?;
+        match write_stage_return {
+            WriteStageReturnType::LeaderRotation(entry_height) => {
+                Ok(Some(TxCreatorReturnType::LeaderRotation(entry_height)))
+            }
             _ => Ok(None),
         }
     }
@@ -1,25 +1,36 @@
 use transaction_processor::TransactionProcessor;
 use blob_fetch_stage::BlobFetchStage;
 use blockthread::BlockThread;
-use replicate_stage::ReplicateStage;
+use hash::Hash;
+use repair_service::RepairService;
+use replicate_stage::{ReplicateStage, ReplicateState};
 use retransmit_stage::{RetransmitStage, RetransmitStageReturnType};
 use service::Service;
 use signature::Keypair;
 use std::net::UdpSocket;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
 use std::sync::{Arc, RwLock};
 use std::thread;
+use storage_stage::{StorageStage, StorageState};
+use store_ledger_stage::StoreLedgerStage;
+use tx_creator::RotationSignal;
 use window::SharedWindow;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TxSignerReturnType {
-    LeaderRotation(u64),
+    LeaderRotation(u64, u64, Hash),
 }
 
 pub struct TxSigner {
     replicate_stage: ReplicateStage,
+    replicate_state: ReplicateState,
+    store_ledger_stage: Option<StoreLedgerStage>,
+    storage_stage: StorageStage,
+    storage_state: StorageState,
     fetch_stage: BlobFetchStage,
     retransmit_stage: RetransmitStage,
+    repair_service: RepairService,
     exit: Arc<AtomicBool>,
 }
 
@@ -31,47 +42,87 @@ impl TxSigner {
         entry_height: u64,
         blockthread: Arc<RwLock<BlockThread>>,
         window: SharedWindow,
-        replicate_sockets: Vec<UdpSocket>,
-        repair_socket: UdpSocket,
-        retransmit_socket: UdpSocket,
+        replicate_sockets: Vec<Arc<UdpSocket>>,
+        repair_socket: Arc<UdpSocket>,
+        retransmit_socket: Arc<UdpSocket>,
+        storage_socket: Arc<UdpSocket>,
         ledger_path: Option<&str>,
+        sigverify_disabled: bool,
+        rotation_sender: Sender<RotationSignal>,
     ) -> Self {
         let exit = Arc::new(AtomicBool::new(false));
 
-        let repair_socket = Arc::new(repair_socket);
-        let mut blob_sockets: Vec<Arc<UdpSocket>> =
-            replicate_sockets.into_iter().map(Arc::new).collect();
+        let mut blob_sockets = replicate_sockets;
         blob_sockets.push(repair_socket.clone());
         let (fetch_stage, blob_fetch_receiver) =
             BlobFetchStage::new_multi_socket(blob_sockets, exit.clone());
+        let repair_service = RepairService::new(window.clone(), blockthread.clone(), repair_socket.clone());
         let (retransmit_stage, blob_window_receiver) = RetransmitStage::new(
             &blockthread,
             window,
             entry_height,
-            Arc::new(retransmit_socket),
+            retransmit_socket,
             repair_socket,
             blob_fetch_receiver,
+            rotation_sender,
         );
 
+        let (ledger_entry_sender, store_ledger_stage) = match ledger_path {
+            Some(ledger_path) => {
+                let (entry_sender, entry_receiver) = channel();
+                let store_ledger_stage = StoreLedgerStage::new(ledger_path, entry_receiver);
+                (Some(entry_sender), Some(store_ledger_stage))
+            }
+            None => (None, None),
+        };
+
+        let (storage_entry_sender, storage_entry_receiver) = channel();
+        let storage_state = StorageState::new();
+        let storage_stage = StorageStage::new(
+            storage_state.clone(),
+            storage_entry_receiver,
+            keypair.clone(),
+            transaction_processor.clone(),
+            blockthread.clone(),
+            storage_socket,
+        );
+
+        let replicate_state = ReplicateState::new();
+
         let replicate_stage = ReplicateStage::new(
             keypair,
             transaction_processor.clone(),
             blockthread,
             blob_window_receiver,
-            ledger_path,
+            ledger_entry_sender,
+            Some(storage_entry_sender),
+            replicate_state.clone(),
             exit.clone(),
+            sigverify_disabled,
         );
 
         TxSigner {
             replicate_stage,
+            replicate_state,
+            store_ledger_stage,
+            storage_stage,
+            storage_state,
             fetch_stage,
             retransmit_stage,
+            repair_service,
             exit,
         }
     }
 
+    /// Shared handle onto this node's current proof-of-replication hash, so
+    /// the RPC layer can report it without holding a reference to `TxSigner`.
+    pub fn storage_state(&self) -> StorageState {
+        self.storage_state.clone()
+    }
+
     pub fn exit(&self) -> () {
         self.exit.store(true, Ordering::Relaxed);
+        self.repair_service.exit();
     }
 
     pub fn close(self) -> thread::Result<Option<TxSignerReturnType>> {
@@ -85,10 +136,19 @@ impl Service for TxSigner {
 
     fn join(self) -> thread::Result<Option<TxSignerReturnType>> {
         self.replicate_stage.join()?;
+        if let Some(store_ledger_stage) = self.store_ledger_stage {
+            store_ledger_stage.join()?;
+        }
+        self.storage_stage.join()?;
         self.fetch_stage.join()?;
+        self.repair_service.close()?;
         match self.retransmit_stage.join()? {
             Some(RetransmitStageReturnType::LeaderRotation(entry_height)) => {
-                Ok(Some(TxSignerReturnType::LeaderRotation(entry_height)))
+                Ok(Some(TxSignerReturnType::LeaderRotation(
+                    self.replicate_state.tick_height(),
+                    entry_height,
+                    self.replicate_state.last_entry_id(),
+                )))
             }
             _ => Ok(None),
         }
@@ -178,16 +238,20 @@ pub mod tests {
         let cref1 = Arc::new(RwLock::new(blockthread1));
         let dr_1 = new_ncp(cref1.clone(), target1.sockets.gossip, exit.clone());
 
+        let (rotation_sender, _rotation_receiver) = channel();
         let tx_signer = TxSigner::new(
             Arc::new(target1_keypair),
             &transaction_processor,
             0,
             cref1,
             dr_1.1,
-            target1.sockets.replicate,
-            target1.sockets.repair,
-            target1.sockets.retransmit,
+            target1.sockets.replicate.into_iter().map(Arc::new).collect(),
+            Arc::new(target1.sockets.repair),
+            Arc::new(target1.sockets.retransmit),
+            Arc::new(target1.sockets.storage),
             None,
+            false,
+            rotation_sender,
         );
 
         let mut alice_ref_balance = starting_balance;
@@ -0,0 +1,108 @@
+//! The `vote_listener_stage` binds its own UDP socket so incoming votes
+//! never have to compete with the main transaction `FetchStage` queue — a
+//! burst of transactions can't starve consensus traffic this way. Every vote
+//! it decodes is applied straight to the shared `BlockThread` view and also
+//! republished over a channel, so a downstream stage like `write_stage` can
+//! base leader-rotation decisions on fresh consensus data instead of
+//! inferring it solely from locally written entries.
+
+use bincode::deserialize;
+use blockthread::BlockThread;
+use hash::Hash;
+use result::{Error, Result};
+use service::Service;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, Builder, JoinHandle};
+use std::time::Duration;
+use streamer::{self, BlobSender};
+use transaction::Transaction;
+use vote_program::VoteState;
+use xpz_program_interface::pubkey::Pubkey;
+
+/// A vote that's been decoded and applied to `BlockThread` (its transaction
+/// signature is not checked here): who cast it and the `last_id` they voted
+/// on.
+pub type ObservedVote = (Pubkey, Hash);
+
+pub struct VoteListener {
+    thread_hdls: Vec<JoinHandle<()>>,
+}
+
+impl VoteListener {
+    pub fn new(
+        blockthread: Arc<RwLock<BlockThread>>,
+        exit: Arc<AtomicBool>,
+    ) -> (Self, Receiver<ObservedVote>) {
+        let socket = Arc::new(UdpSocket::bind("0.0.0.0:0").expect("bind vote listener socket"));
+        let (blob_sender, blob_receiver): (BlobSender, _) = channel();
+        let t_receiver = streamer::blob_receiver(socket, exit.clone(), blob_sender);
+
+        let (vote_sender, vote_receiver) = channel();
+        let t_listener = Self::listen(blob_receiver, blockthread, vote_sender, exit);
+
+        (
+            VoteListener {
+                thread_hdls: vec![t_receiver, t_listener],
+            },
+            vote_receiver,
+        )
+    }
+
+    fn listen(
+        blob_receiver: Receiver<::packet::SharedBlobs>,
+        blockthread: Arc<RwLock<BlockThread>>,
+        vote_sender: Sender<ObservedVote>,
+        exit: Arc<AtomicBool>,
+    ) -> JoinHandle<()> {
+        Builder::new()
+            .name("hypercube-vote-listener".to_string())
+            .spawn(move || loop {
+                if let Err(e) = Self::process_blobs(&blob_receiver, &blockthread, &vote_sender) {
+                    match e {
+                        Error::RecvTimeoutError(RecvTimeoutError::Timeout) => (),
+                        Error::RecvTimeoutError(RecvTimeoutError::Disconnected) => break,
+                        Error::SendError => break,
+                        _ => error!("hypercube-vote-listener unexpected error {:?}", e),
+                    }
+                }
+                if exit.load(Ordering::Relaxed) {
+                    break;
+                }
+            }).unwrap()
+    }
+
+    fn process_blobs(
+        blob_receiver: &Receiver<::packet::SharedBlobs>,
+        blockthread: &Arc<RwLock<BlockThread>>,
+        vote_sender: &Sender<ObservedVote>,
+    ) -> Result<()> {
+        let blobs = blob_receiver.recv_timeout(Duration::from_millis(100))?;
+        for blob in &blobs {
+            let blob = blob.read().unwrap();
+            let tx: Transaction = match deserialize(&blob.data[..blob.meta.size]) {
+                Ok(tx) => tx,
+                Err(_) => continue,
+            };
+            if let Some(vote) = VoteState::decode_vote(&tx) {
+                let voter = tx.keys[0];
+                blockthread.write().unwrap().insert_vote(&voter, &vote, tx.last_id);
+                vote_sender.send((voter, tx.last_id))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Service for VoteListener {
+    type JoinReturnType = ();
+
+    fn join(self) -> thread::Result<()> {
+        for thread_hdl in self.thread_hdls {
+            thread_hdl.join()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use bincode::{deserialize, serialize};
+use hash::Hash;
+use timing;
+use transaction::Transaction;
+use xpz_program_interface::account::Account;
+use xpz_program_interface::pubkey::Pubkey;
+use std::collections::VecDeque;
+
+/// A recorded vote: the fullnode's software version and its contact-info
+/// version. The `last_id` voted on is the enclosing transaction's `last_id`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Vote {
+    pub version: u64,
+    pub contact_info_version: u64,
+}
+
+/// Votes older than this are dropped from a `VoteProgram`'s history; only
+/// the outcome for finality (the latest entry) is ever actually read back,
+/// but a short bounded history is kept around for diagnostics.
+pub const MAX_VOTE_HISTORY: usize = 32;
+
+/// On-chain state for a vote account: the node it is delegated to, who is
+/// currently authorized to submit votes on its behalf, the commission it
+/// charges, the credits it has accumulated, and a bounded history of the
+/// `(tick_height, last_id)` pairs it has voted on, most recent last.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct VoteProgram {
+    pub node_id: Pubkey,
+    pub authorized_voter_id: Pubkey,
+    pub commission: u32,
+    pub credits: u64,
+    pub votes: VecDeque<(u64, Hash)>,
+    /// Wall-clock time, in ms, that the most recent vote was processed at —
+    /// used to derive `TransactionProcessor::finality` once enough stake
+    /// has voted on the same `last_id`.
+    pub last_vote_timestamp_ms: u64,
+}
+
+impl Default for VoteProgram {
+    fn default() -> Self {
+        VoteProgram {
+            node_id: Pubkey::default(),
+            authorized_voter_id: Pubkey::default(),
+            commission: 0,
+            credits: 0,
+            votes: VecDeque::new(),
+            last_vote_timestamp_ms: 0,
+        }
+    }
+}
+
+/// Instructions understood by the vote program, packaged separately from
+/// `FinPlanState` so the bank can dispatch votes and staking operations
+/// without parsing them as a fin-plan `Instruction`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum VoteInstruction {
+    InitializeAccount { node_id: Pubkey, commission: u32 },
+    AuthorizeVoter(Pubkey),
+    NewVote(Vote),
+}
+
+pub struct VoteState;
+
+impl VoteState {
+    pub fn id() -> Pubkey {
+        Pubkey::new(&[
+            7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+            7, 7, 7,
+        ])
+    }
+
+    pub fn check_id(program_id: &Pubkey) -> bool {
+        *program_id == Self::id()
+    }
+
+    /// Decode `tx` as a `NewVote` cast against the vote program, returning
+    /// `None` for anything else (wrong program, different instruction, or a
+    /// malformed payload). Used by `vote_listener_stage` to tell vote blobs
+    /// apart from stray traffic before applying them to `BlockThread`.
+    pub fn decode_vote(tx: &Transaction) -> Option<Vote> {
+        match Self::decode_instruction(tx) {
+            Ok(VoteInstruction::NewVote(vote)) => Some(vote),
+            _ => None,
+        }
+    }
+
+    fn decode_instruction(tx: &Transaction) -> Result<VoteInstruction, VoteError> {
+        let program_index = tx
+            .program_ids
+            .iter()
+            .position(Self::check_id)
+            .ok_or(VoteError::InvalidInstruction)?;
+        let ix = tx
+            .instructions
+            .iter()
+            .find(|ix| ix.program_ids_index as usize == program_index)
+            .ok_or(VoteError::InvalidInstruction)?;
+        deserialize(&ix.userdata).map_err(|_| VoteError::InvalidInstruction)
+    }
+
+    /// Apply a vote or account-management instruction to the vote account
+    /// named last among `accounts` — the only account for `NewVote` (the
+    /// authorized voter signs directly for its own vote account, with no
+    /// separate key in the transaction), or the second account for
+    /// `InitializeAccount`/`AuthorizeVoter` (the first being whichever key
+    /// paid the transaction's fee).
+    pub fn process_transaction(tx: &Transaction, accounts: &mut [Account]) -> Result<(), VoteError> {
+        let instruction = Self::decode_instruction(tx)?;
+        let vote_account = accounts.last_mut().ok_or(VoteError::InvalidAccountData)?;
+        let mut vote_program: VoteProgram = if vote_account.userdata.is_empty() {
+            VoteProgram::default()
+        } else {
+            deserialize(&vote_account.userdata).map_err(|_| VoteError::InvalidAccountData)?
+        };
+
+        match instruction {
+            VoteInstruction::InitializeAccount { node_id, commission } => {
+                vote_program.node_id = node_id;
+                vote_program.authorized_voter_id = node_id;
+                vote_program.commission = commission;
+            }
+            VoteInstruction::AuthorizeVoter(authorized_voter_id) => {
+                vote_program.authorized_voter_id = authorized_voter_id;
+            }
+            VoteInstruction::NewVote(_vote) => {
+                let tick_height = vote_program.votes.len() as u64;
+                vote_program.votes.push_back((tick_height, tx.last_id));
+                if vote_program.votes.len() > MAX_VOTE_HISTORY {
+                    vote_program.votes.pop_front();
+                }
+                vote_program.credits += 1;
+                vote_program.last_vote_timestamp_ms = timing::timestamp();
+            }
+        }
+
+        vote_account.userdata =
+            serialize(&vote_program).map_err(|_| VoteError::InvalidAccountData)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum VoteError {
+    InvalidInstruction,
+    InvalidAccountData,
+}
@@ -6,13 +6,13 @@ use fin_plan_transaction::BudgetTransaction;
 use counter::Counter;
 use blockthread::BlockThread;
 use hash::Hash;
-use influx_db_client as influxdb;
 use log::Level;
 use metrics;
 use packet::SharedBlob;
 use result::Result;
 use signature::Keypair;
 use xpz_program_interface::pubkey::Pubkey;
+use std::collections::VecDeque;
 use std::result;
 use std::sync::atomic::AtomicUsize;
 use std::sync::{Arc, RwLock};
@@ -22,13 +22,135 @@ use transaction::Transaction;
 
 pub const VOTE_TIMEOUT_MS: u64 = 1000;
 
+/// How many recent votes `Tower` remembers before the oldest, now-confirmed
+/// entries are popped off to make room.
+pub const MAX_LOCKOUT_HISTORY: usize = 32;
+
+/// The leader's own finality-attestation vote isn't subject to lockout (it
+/// asserts what the network has already agreed on, not a personal
+/// commitment a fork could contest), so it always publishes to a single
+/// reserved crds slot rather than drawing one from a `Tower`.
+const LEADER_VOTE_INDEX: usize = 0;
+
 #[derive(Debug, PartialEq, Eq)]
 enum VoteError {
     NoValidLastIdsToVoteOn,
 }
 
+#[derive(Clone, Debug)]
+struct LockoutVote {
+    last_id: Hash,
+    height: u64,
+    confirmation_count: u32,
+    index: usize,
+    submitted_at: u64,
+}
+
+impl LockoutVote {
+    fn lockout(&self) -> u64 {
+        1u64 << self.confirmation_count
+    }
+
+    fn locked_out_until(&self) -> u64 {
+        self.height + self.lockout()
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        now.saturating_sub(self.submitted_at) > VOTE_TIMEOUT_MS * self.lockout()
+    }
+}
+
+/// A validator's local lockout history. Each vote's lockout doubles
+/// (`2^confirmations`) every time a later vote extends it, and the vote is
+/// "locked out" — conflicting votes are refused — until `height +
+/// lockout`. Bounds the gossip vote table to `MAX_LOCKOUT_HISTORY` slots by
+/// recycling the crds index of whichever vote is confirmed (popped off the
+/// front) or, failing that, expired without confirmation.
+pub struct Tower {
+    votes: VecDeque<LockoutVote>,
+    popped_indexes: Vec<usize>,
+    next_index: usize,
+}
+
+impl Tower {
+    pub fn new() -> Self {
+        Tower {
+            votes: VecDeque::new(),
+            popped_indexes: Vec::new(),
+            next_index: 0,
+        }
+    }
+
+    /// True if voting at `height` would conflict with a vote still inside
+    /// its lockout window.
+    pub fn is_locked_out(&self, height: u64) -> bool {
+        self.votes.iter().any(|vote| vote.locked_out_until() > height)
+    }
+
+    /// If the most recently submitted vote hasn't had its target height
+    /// confirmed within `VOTE_TIMEOUT_MS * lockout`, return its `(last_id,
+    /// index)` so the caller can re-submit the same commitment rather than
+    /// advancing to a new one.
+    pub fn expired_retry(&self, now: u64) -> Option<(Hash, usize)> {
+        self.votes
+            .back()
+            .filter(|vote| vote.is_expired(now))
+            .map(|vote| (vote.last_id, vote.index))
+    }
+
+    /// Push a new vote for `last_id` at `height`, doubling the lockout of
+    /// every vote it extends and popping any vote it has now confirmed.
+    /// Returns the crds vote-table index to publish the new vote at,
+    /// recycled from a popped or expired vote when possible.
+    pub fn push_vote_at_index(&mut self, last_id: Hash, height: u64, now: u64) -> usize {
+        while let Some(front) = self.votes.front() {
+            if front.locked_out_until() > height {
+                break;
+            }
+            let popped = self.votes.pop_front().unwrap();
+            self.popped_indexes.push(popped.index);
+        }
+        for vote in &mut self.votes {
+            vote.confirmation_count += 1;
+        }
+
+        let index = self.popped_indexes.pop().unwrap_or_else(|| {
+            // Recycling an expired vote's index must also evict it from
+            // `self.votes` — otherwise it lingers alongside the new vote
+            // that now carries its index, doubling its lockout on every
+            // later `push_vote_at_index` and making it eligible to be
+            // "recycled" a second time while still live.
+            let expired_position = self.votes.iter().position(|vote| vote.is_expired(now));
+            match expired_position {
+                Some(position) => self.votes.remove(position).unwrap().index,
+                None => {
+                    let index = self.next_index;
+                    self.next_index += 1;
+                    index
+                }
+            }
+        });
+
+        self.votes.push_back(LockoutVote {
+            last_id,
+            height,
+            confirmation_count: 1,
+            index,
+            submitted_at: now,
+        });
+        while self.votes.len() > MAX_LOCKOUT_HISTORY {
+            if let Some(oldest) = self.votes.pop_front() {
+                self.popped_indexes.push(oldest.index);
+            }
+        }
+
+        index
+    }
+}
+
 pub fn create_new_signed_vote_blob(
     last_id: &Hash,
+    vote_index: usize,
     keypair: &Keypair,
     blockthread: &Arc<RwLock<BlockThread>>,
 ) -> Result<SharedBlob> {
@@ -37,7 +159,7 @@ pub fn create_new_signed_vote_blob(
         let mut wblockthread = blockthread.write().unwrap();
         //TODO: doesn't seem like there is a synchronous call to get height and id
         debug!("voting on {:?}", &last_id.as_ref()[..8]);
-        wblockthread.new_vote(*last_id)
+        wblockthread.new_vote_at_index(vote_index, *last_id)
     }?;
     let tx = Transaction::fin_plan_new_vote(&keypair, vote, *last_id, 0);
     {
@@ -53,50 +175,56 @@ pub fn create_new_signed_vote_blob(
 
 fn get_last_id_to_vote_on(
     id: &Pubkey,
-    ids: &[Hash],
+    ids: &[(Pubkey, Hash)],
     transaction_processor: &Arc<TransactionProcessor>,
     now: u64,
     last_vote: &mut u64,
     last_valid_validator_timestamp: &mut u64,
+    get_stake: &Fn(Pubkey) -> f64,
 ) -> result::Result<(Hash, u64), VoteError> {
-    let mut valid_ids = transaction_processor.count_valid_ids(&ids);
-    let super_majority_index = (2 * ids.len()) / 3;
+    let hashes: Vec<Hash> = ids.iter().map(|&(_, hash)| hash).collect();
+    let mut valid_ids = transaction_processor.count_valid_ids(&hashes);
+    let total_stake: f64 = ids.iter().map(|&(pubkey, _)| get_stake(pubkey)).sum();
+    let supermajority_stake = total_stake * 2.0 / 3.0;
 
-    //TODO(anatoly): this isn't stake based voting
     debug!(
         "{}: valid_ids {}/{} {}",
         id,
         valid_ids.len(),
         ids.len(),
-        super_majority_index,
+        supermajority_stake,
     );
 
-    metrics::submit(
-        influxdb::Point::new("vote_stage-peer_count")
-            .add_field("total_peers", influxdb::Value::Integer(ids.len() as i64))
-            .add_field(
-                "valid_peers",
-                influxdb::Value::Integer(valid_ids.len() as i64),
-            ).to_owned(),
+    measure!(
+        "vote_stage-peer_count",
+        int["total_peers"; ids.len()],
+        int["valid_peers"; valid_ids.len()]
     );
 
-    if valid_ids.len() > super_majority_index {
-        *last_vote = now;
-
-        // Sort by timestamp
-        valid_ids.sort_by(|a, b| a.1.cmp(&b.1));
-
-        let last_id = ids[valid_ids[super_majority_index].0];
-        return Ok((last_id, valid_ids[super_majority_index].1));
+    // Sort by timestamp, oldest first, and accumulate stake forward in
+    // time: the first id at which the running total crosses the
+    // supermajority threshold is returned, i.e. the oldest id such that at
+    // least 2/3 of the stake has voted on it or on an older id.
+    valid_ids.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let mut staked_so_far = 0f64;
+    for &(index, timestamp) in &valid_ids {
+        let (pubkey, last_id) = ids[index];
+        staked_so_far += get_stake(pubkey);
+        if staked_so_far >= supermajority_stake {
+            *last_vote = now;
+            return Ok((last_id, timestamp));
+        }
     }
 
     if *last_valid_validator_timestamp != 0 {
         metrics::submit(
-            influxdb::Point::new(&"leader-finality")
+            metrics::Point::new(&"leader-finality")
                 .add_field(
                     "duration_ms",
-                    influxdb::Value::Integer((now - *last_valid_validator_timestamp) as i64),
+                    metrics::Value::Integer((now - *last_valid_validator_timestamp) as i64),
                 ).to_owned(),
+            None,
         );
     }
 
@@ -111,6 +239,7 @@ pub fn send_leader_vote(
     vote_blob_sender: &BlobSender,
     last_vote: &mut u64,
     last_valid_validator_timestamp: &mut u64,
+    get_stake: &Fn(Pubkey) -> f64,
 ) -> Result<()> {
     let now = timing::timestamp();
     if now - *last_vote > VOTE_TIMEOUT_MS {
@@ -122,8 +251,11 @@ pub fn send_leader_vote(
             now,
             last_vote,
             last_valid_validator_timestamp,
+            get_stake,
         ) {
-            if let Ok(shared_blob) = create_new_signed_vote_blob(&last_id, keypair, blockthread) {
+            if let Ok(shared_blob) =
+                create_new_signed_vote_blob(&last_id, LEADER_VOTE_INDEX, keypair, blockthread)
+            {
                 vote_blob_sender.send(vec![shared_blob])?;
                 let finality_ms = now - super_majority_timestamp;
 
@@ -134,9 +266,10 @@ pub fn send_leader_vote(
                 transaction_processor.set_finality((now - *last_valid_validator_timestamp) as usize);
 
                 metrics::submit(
-                    influxdb::Point::new(&"leader-finality")
-                        .add_field("duration_ms", influxdb::Value::Integer(finality_ms as i64))
+                    metrics::Point::new(&"leader-finality")
+                        .add_field("duration_ms", metrics::Value::Integer(finality_ms as i64))
                         .to_owned(),
+                    None,
                 );
             }
         }
@@ -144,14 +277,36 @@ pub fn send_leader_vote(
     Ok(())
 }
 
+/// Votes on the transaction_processor's current `last_id`, subject to
+/// `tower`'s lockout: refuses to vote on a `last_id` that conflicts with a
+/// still-locked prior vote, and re-submits the latest vote at its existing
+/// index rather than advancing if it has expired without confirmation.
 pub fn send_validator_vote(
     transaction_processor: &Arc<TransactionProcessor>,
     keypair: &Arc<Keypair>,
     blockthread: &Arc<RwLock<BlockThread>>,
     vote_blob_sender: &BlobSender,
+    tower: &mut Tower,
+    height: u64,
 ) -> Result<()> {
+    let now = timing::timestamp();
+
+    if let Some((last_id, vote_index)) = tower.expired_retry(now) {
+        if let Ok(shared_blob) = create_new_signed_vote_blob(&last_id, vote_index, keypair, blockthread) {
+            inc_new_counter_info!("replicate-vote_retried", 1);
+            vote_blob_sender.send(vec![shared_blob])?;
+        }
+        return Ok(());
+    }
+
+    if tower.is_locked_out(height) {
+        inc_new_counter_info!("replicate-vote_locked_out", 1);
+        return Ok(());
+    }
+
     let last_id = transaction_processor.last_id();
-    if let Ok(shared_blob) = create_new_signed_vote_blob(&last_id, keypair, blockthread) {
+    let vote_index = tower.push_vote_at_index(last_id, height, now);
+    if let Ok(shared_blob) = create_new_signed_vote_blob(&last_id, vote_index, keypair, blockthread) {
         inc_new_counter_info!("replicate-vote_sent", 1);
 
         vote_blob_sender.send(vec![shared_blob])?;
@@ -164,7 +319,7 @@ pub mod tests {
     use super::*;
     use transaction_processor::TransactionProcessor;
     use bincode::deserialize;
-    use fin_plan_instruction::Vote;
+    use vote_program::Vote;
     use blockthread::{BlockThread, NodeInfo};
     use entry::next_entry;
     use hash::{hash, Hash};
@@ -177,6 +332,10 @@ pub mod tests {
     use builtin_tansaction::SystemTransaction;
     use transaction::Transaction;
 
+    fn get_stake(_id: Pubkey) -> f64 {
+        1.0
+    }
+
     #[test]
     fn test_send_leader_vote() {
         logger::setup();
@@ -234,6 +393,7 @@ pub mod tests {
             &vote_blob_sender,
             &mut last_vote,
             &mut last_valid_validator_timestamp,
+            &get_stake,
         );
         trace!("vote result: {:?}", res);
         assert!(res.is_ok());
@@ -273,6 +433,7 @@ pub mod tests {
             &vote_blob_sender,
             &mut last_vote,
             &mut last_valid_validator_timestamp,
+            &get_stake,
         );
         trace!("vote result: {:?}", res);
         assert!(res.is_ok());
@@ -306,7 +467,9 @@ pub mod tests {
                 }
                 // sleep to get a different timestamp in the transaction_processor
                 sleep(Duration::from_millis(1));
-                last_id
+                let mut pubkey_bytes = [0u8; 32];
+                pubkey_bytes[0] = i as u8;
+                (Pubkey::new(&pubkey_bytes), last_id)
             }).collect();
 
         // see that we fail to have 2/3rds consensus
@@ -317,12 +480,61 @@ pub mod tests {
                 &transaction_processor,
                 0,
                 &mut last_vote,
-                &mut last_valid_validator_timestamp
+                &mut last_valid_validator_timestamp,
+                &get_stake,
             ).is_err()
         );
 
         // register another, see passing
-        transaction_processor.register_entry_id(&ids[6]);
+        transaction_processor.register_entry_id(&ids[6].1);
+
+        let res = get_last_id_to_vote_on(
+            &Pubkey::default(),
+            &ids,
+            &transaction_processor,
+            0,
+            &mut last_vote,
+            &mut last_valid_validator_timestamp,
+            &get_stake,
+        );
+        if let Ok((hash, timestamp)) = res {
+            assert!(hash == ids[6].1);
+            assert!(timestamp != 0);
+        } else {
+            assert!(false, "get_last_id returned error!: {:?}", res);
+        }
+    }
+
+    #[test]
+    fn test_get_last_id_to_vote_on_stake_weighted() {
+        logger::setup();
+
+        let mint = Mint::new(1234);
+        let transaction_processor = Arc::new(TransactionProcessor::new(&mint));
+        let mut last_vote = 0;
+        let mut last_valid_validator_timestamp = 0;
+
+        // One validator holds 67% of the stake and registers its last_id;
+        // the other nine, with the remaining 33% spread evenly, never do.
+        // Count-based voting would see 1/10 "valid" ids and report no
+        // consensus, but stake-weighted voting should see the 67%-holder
+        // alone crossing the 2/3 threshold.
+        let mut heavy_pubkey_bytes = [0u8; 32];
+        heavy_pubkey_bytes[0] = 0u8;
+        let heavy_pubkey = Pubkey::new(&heavy_pubkey_bytes);
+        let ids: Vec<_> = (0..10)
+            .map(|i| {
+                let last_id = hash(&serialize(&i).unwrap());
+                if i == 0 {
+                    transaction_processor.register_entry_id(&last_id);
+                }
+                sleep(Duration::from_millis(1));
+                let mut pubkey_bytes = [0u8; 32];
+                pubkey_bytes[0] = i as u8;
+                (Pubkey::new(&pubkey_bytes), last_id)
+            }).collect();
+
+        let get_weighted_stake = move |pubkey: Pubkey| if pubkey == heavy_pubkey { 67.0 } else { 33.0 / 9.0 };
 
         let res = get_last_id_to_vote_on(
             &Pubkey::default(),
@@ -331,12 +543,81 @@ pub mod tests {
             0,
             &mut last_vote,
             &mut last_valid_validator_timestamp,
+            &get_weighted_stake,
         );
         if let Ok((hash, timestamp)) = res {
-            assert!(hash == ids[6]);
+            assert!(hash == ids[0].1);
             assert!(timestamp != 0);
         } else {
             assert!(false, "get_last_id returned error!: {:?}", res);
         }
     }
+
+    #[test]
+    fn test_tower_refuses_conflicting_vote_while_locked_out() {
+        let mut tower = Tower::new();
+        tower.push_vote_at_index(Hash::default(), 10, 0);
+        // lockout after a single vote is 2^1 == 2, so height 11 is still
+        // inside the lockout window.
+        assert!(tower.is_locked_out(11));
+        assert!(!tower.is_locked_out(12));
+    }
+
+    #[test]
+    fn test_tower_doubles_lockout_and_pops_confirmed_votes() {
+        let mut tower = Tower::new();
+        tower.push_vote_at_index(hash(&serialize(&0).unwrap()), 0, 0);
+        assert!(tower.is_locked_out(1));
+
+        // voting again within the first vote's lockout doubles it instead
+        // of popping it off.
+        tower.push_vote_at_index(hash(&serialize(&1).unwrap()), 1, 0);
+        assert!(tower.is_locked_out(3));
+
+        // once height has advanced past both lockouts, a further vote
+        // confirms (and pops) everything that's now behind it.
+        let index = tower.push_vote_at_index(hash(&serialize(&2).unwrap()), 100, 0);
+        assert!(!tower.is_locked_out(103));
+        // the recycled index should have come from one of the two popped
+        // votes rather than a brand-new one.
+        assert!(index < 2);
+    }
+
+    #[test]
+    fn test_tower_expired_retry() {
+        let mut tower = Tower::new();
+        let last_id = hash(&serialize(&0).unwrap());
+        let index = tower.push_vote_at_index(last_id, 0, 0);
+
+        // not expired yet
+        assert_eq!(tower.expired_retry(VOTE_TIMEOUT_MS), None);
+
+        // expired: VOTE_TIMEOUT_MS * lockout (2) elapsed with no confirmation
+        let retry = tower.expired_retry(VOTE_TIMEOUT_MS * 2 + 1);
+        assert_eq!(retry, Some((last_id, index)));
+    }
+
+    #[test]
+    fn test_tower_evicts_expired_vote_when_recycling_its_index() {
+        let mut tower = Tower::new();
+        let index0 = tower.push_vote_at_index(hash(&serialize(&0).unwrap()), 0, 0);
+
+        // height 1 is still inside the first vote's lockout window (until
+        // height 2), so it isn't confirmed and popped off the front; but
+        // enough time passes that, once its lockout is doubled by this same
+        // call, it counts as expired and its index is recycled instead.
+        let index1 = tower.push_vote_at_index(
+            hash(&serialize(&1).unwrap()),
+            1,
+            VOTE_TIMEOUT_MS * 4 + 1,
+        );
+        assert_eq!(index1, index0);
+
+        // the expired vote must have been evicted along with its index,
+        // not left behind to double-count toward is_locked_out and keep
+        // getting its lockout re-extended under the same index as the
+        // vote that replaced it.
+        assert_eq!(tower.votes.len(), 1);
+        assert_eq!(tower.votes[0].index, index0);
+    }
 }